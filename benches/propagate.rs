@@ -0,0 +1,52 @@
+//! Times the wave solver kernels against each other.
+//!
+//! Run with `cargo bench --features parallel` to include the rayon kernel.
+
+extern crate flipwrecked;
+
+use std::time::Instant;
+
+use flipwrecked::field::{Field, FIELD_WIDTH, FIELD_HEIGHT};
+use flipwrecked::params::WaveParams;
+use flipwrecked::splash::{Splash, SplashShape};
+
+const TICKS: u32 = 500;
+
+fn splashed_field(width: usize, height: usize) -> Field {
+    let mut field = Field::with_size(width, height, 1, WaveParams::default());
+    field.splash((width / 2) as f32,
+                 (height / 2) as f32,
+                 &Splash::new(SplashShape::Circle { radius: 4.0 }, 1.0));
+    field
+}
+
+/// Runs `step` for TICKS ticks and prints how long one tick took on average.
+fn bench<F>(name: &str, width: usize, height: usize, mut step: F)
+    where F: FnMut(&mut Field)
+{
+    let mut field = splashed_field(width, height);
+    // Warm up.
+    for _ in 0..10 {
+        step(&mut field);
+    }
+    let start = Instant::now();
+    for _ in 0..TICKS {
+        step(&mut field);
+    }
+    let elapsed = start.elapsed();
+    let nanos = elapsed.as_secs() * 1_000_000_000 + elapsed.subsec_nanos() as u64;
+    println!("{:>10} {:>4}x{:<4} {:>10} ns/tick",
+             name,
+             width,
+             height,
+             nanos / TICKS as u64);
+}
+
+fn main() {
+    let sizes = [(FIELD_WIDTH, FIELD_HEIGHT), (320, 240), (800, 600)];
+    for &(width, height) in &sizes {
+        bench("serial", width, height, |f| f.step_serial());
+        #[cfg(feature = "parallel")]
+        bench("parallel", width, height, |f| f.step_parallel());
+    }
+}
@@ -0,0 +1,383 @@
+//! Runs lots of matches with nobody watching, to see how the game
+//! balances out.
+//!
+//! ```text
+//! cargo run --release --bin flipwrecked-sim -- --ships hard,easy --matches 1000
+//! ```
+//!
+//! Each ship is steered by a bot (`easy`, `normal` or `hard`) or by a
+//! script: `idle` sits still and `circle` drives round in circles.
+//! Match `n` uses seed `--seed` plus `n`, so any one of them can be
+//! looked at again.  By default every match is printed as a line of CSV,
+//! with the totals on stderr; `--format json` prints the totals and
+//! every match as JSON instead.
+//!
+//! Other options: `--max-ticks <n>` gives up on a match that's gone on
+//! too long, `--params <file>` plays with wave params other than the
+//! game's own `resources/wave_params.toml`, and `--map <file>` plays
+//! on a different arena.
+
+extern crate flipwrecked;
+extern crate serde;
+#[macro_use]
+extern crate serde_derive;
+extern crate serde_json;
+#[cfg(feature = "parallel")]
+extern crate rayon;
+
+#[cfg(feature = "parallel")]
+use rayon::prelude::*;
+
+use std::env;
+use std::io::{self, Write};
+
+use flipwrecked::TICKS_PER_SECOND;
+use flipwrecked::bot::{Bot, Difficulty};
+use flipwrecked::map::Map;
+use flipwrecked::params::{WaveParams, DEFAULT_PARAMS_FILE};
+use flipwrecked::player::{Control, MIN_PLAYERS, MAX_PLAYERS, default_players};
+use flipwrecked::replay::Input;
+use flipwrecked::world::{World, Outcome, FlipCause};
+
+/// What's steering one ship.
+#[derive(Debug, Copy, Clone, PartialEq)]
+enum Driver {
+    Bot(Difficulty),
+    Idle,
+    Circle,
+}
+
+impl Driver {
+    fn from_name(name: &str) -> Option<Driver> {
+        match name {
+            "idle" => Some(Driver::Idle),
+            "circle" => Some(Driver::Circle),
+            _ => Difficulty::from_name(name).map(Driver::Bot),
+        }
+    }
+
+    fn name(&self) -> &'static str {
+        match *self {
+            Driver::Bot(Difficulty::Easy) => "easy",
+            Driver::Bot(Difficulty::Normal) => "normal",
+            Driver::Bot(Difficulty::Hard) => "hard",
+            Driver::Idle => "idle",
+            Driver::Circle => "circle",
+        }
+    }
+}
+
+/// How one ship's match went.
+#[derive(Debug, Clone, Serialize)]
+struct ShipResult {
+    /// The tick it went over on, if it did.
+    flipped: Option<usize>,
+    /// "waves", "rammed" or "stomped".
+    cause: Option<&'static str>,
+    /// The player who rammed or landed on it.
+    by: Option<usize>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+struct MatchResult {
+    seed: u64,
+    ticks: usize,
+    /// The winning player, if anybody won.
+    winner: Option<usize>,
+    draw: bool,
+    /// Still going at `--max-ticks`.
+    unfinished: bool,
+    ships: Vec<ShipResult>,
+}
+
+fn run_match(seed: u64,
+             params: WaveParams,
+             map: &Map,
+             drivers: &[Driver],
+             max_ticks: usize)
+             -> MatchResult {
+    let mut players = default_players(drivers.len());
+    for (player, driver) in players.iter_mut().zip(drivers) {
+        player.control = match *driver {
+            Driver::Bot(difficulty) => Control::Bot(difficulty),
+            _ => Control::Nobody,
+        };
+    }
+    let mut world = World::on_map(seed, params, map, players);
+    let mut bots: Vec<Bot> = drivers.iter()
+        .enumerate()
+        .filter_map(|(i, driver)| match *driver {
+            Driver::Bot(difficulty) => Some(Bot::new(i, difficulty, seed)),
+            _ => None,
+        })
+        .collect();
+    for (i, driver) in drivers.iter().enumerate() {
+        if *driver == Driver::Circle {
+            world.apply_input(Input::Steer(i, 600, 300));
+        }
+    }
+
+    while world.outcome().is_none() && world.frame < max_ticks {
+        let inputs: Vec<Input> = bots.iter_mut().flat_map(|bot| bot.inputs(&world)).collect();
+        for input in inputs {
+            world.apply_input(input);
+        }
+        world.update();
+    }
+
+    let outcome = world.outcome();
+    let ships = world.players
+        .iter()
+        .map(|p| {
+            ShipResult {
+                flipped: p.flipped.map(|(tick, _)| tick),
+                cause: p.flipped.map(|(_, cause)| cause.name()),
+                by: p.flipped.and_then(|(_, cause)| match cause {
+                    FlipCause::Waves => None,
+                    FlipCause::Rammed(by) | FlipCause::Stomped(by) => Some(by),
+                }),
+            }
+        })
+        .collect();
+    MatchResult {
+        seed: seed,
+        ticks: world.frame,
+        winner: match outcome {
+            Some(Outcome::Winner(i)) => Some(i),
+            _ => None,
+        },
+        draw: outcome == Some(Outcome::Draw),
+        unfinished: outcome.is_none(),
+        ships: ships,
+    }
+}
+
+/// Runs `matches` matches, starting from seed `first_seed`.
+#[cfg(not(feature = "parallel"))]
+fn run_matches(first_seed: u64,
+               matches: u64,
+               params: WaveParams,
+               map: &Map,
+               drivers: &[Driver],
+               max_ticks: usize)
+               -> Vec<MatchResult> {
+    (0..matches).map(|n| run_match(first_seed + n, params, map, drivers, max_ticks)).collect()
+}
+
+/// Runs `matches` matches, starting from seed `first_seed`, spread
+/// across threads.  Gives the same results as running them one by one.
+#[cfg(feature = "parallel")]
+fn run_matches(first_seed: u64,
+               matches: u64,
+               params: WaveParams,
+               map: &Map,
+               drivers: &[Driver],
+               max_ticks: usize)
+               -> Vec<MatchResult> {
+    (0..matches)
+        .into_par_iter()
+        .map(|n| run_match(first_seed + n, params, map, drivers, max_ticks))
+        .collect()
+}
+
+#[derive(Debug, Clone, Serialize)]
+struct ShipTotals {
+    driver: &'static str,
+    wins: usize,
+    win_rate: f32,
+    flipped_by_waves: usize,
+    flipped_by_rams: usize,
+    flipped_by_stomps: usize,
+}
+
+#[derive(Debug, Clone, Serialize)]
+struct Totals {
+    matches: usize,
+    draws: usize,
+    draw_rate: f32,
+    unfinished: usize,
+    /// Of the matches that finished, in ticks.
+    mean_length: f32,
+    median_length: usize,
+    ships: Vec<ShipTotals>,
+}
+
+fn totals(drivers: &[Driver], results: &[MatchResult]) -> Totals {
+    let matches = results.len();
+    let rate = |n: usize| if matches == 0 { 0.0 } else { n as f32 / matches as f32 };
+    let mut lengths: Vec<usize> =
+        results.iter().filter(|r| !r.unfinished).map(|r| r.ticks).collect();
+    lengths.sort();
+    let mean_length = if lengths.is_empty() {
+        0.0
+    } else {
+        lengths.iter().sum::<usize>() as f32 / lengths.len() as f32
+    };
+    let draws = results.iter().filter(|r| r.draw).count();
+
+    let ships = drivers.iter()
+        .enumerate()
+        .map(|(i, driver)| {
+            let wins = results.iter().filter(|r| r.winner == Some(i)).count();
+            let flips = |cause: &str| {
+                results.iter().filter(|r| r.ships[i].cause == Some(cause)).count()
+            };
+            ShipTotals {
+                driver: driver.name(),
+                wins: wins,
+                win_rate: rate(wins),
+                flipped_by_waves: flips("waves"),
+                flipped_by_rams: flips("rammed"),
+                flipped_by_stomps: flips("stomped"),
+            }
+        })
+        .collect();
+
+    Totals {
+        matches: matches,
+        draws: draws,
+        draw_rate: rate(draws),
+        unfinished: results.iter().filter(|r| r.unfinished).count(),
+        mean_length: mean_length,
+        median_length: lengths.get(lengths.len() / 2).cloned().unwrap_or(0),
+        ships: ships,
+    }
+}
+
+fn csv_header(ships: usize) -> String {
+    let mut header = String::from("seed,ticks,result");
+    for i in 0..ships {
+        header.push_str(&format!(",p{}_flipped,p{}_cause,p{}_by", i, i, i));
+    }
+    header
+}
+
+fn csv_line(result: &MatchResult) -> String {
+    let opt = |n: Option<usize>| n.map_or(String::new(), |n| n.to_string());
+    let outcome = match result.winner {
+        Some(i) => i.to_string(),
+        None if result.draw => String::from("draw"),
+        None => String::from("unfinished"),
+    };
+    let mut line = format!("{},{},{}", result.seed, result.ticks, outcome);
+    for ship in &result.ships {
+        line.push_str(&format!(",{},{},{}",
+                               opt(ship.flipped),
+                               ship.cause.unwrap_or(""),
+                               opt(ship.by)));
+    }
+    line
+}
+
+fn print_totals<W: Write>(out: &mut W, totals: &Totals) -> io::Result<()> {
+    let percent = |rate: f32| rate * 100.0;
+    writeln!(out,
+             "{} matches, {} draws ({:.1}%), {} unfinished",
+             totals.matches,
+             totals.draws,
+             percent(totals.draw_rate),
+             totals.unfinished)?;
+    writeln!(out,
+             "Match length: mean {:.0} ticks, median {} ticks",
+             totals.mean_length,
+             totals.median_length)?;
+    for (i, ship) in totals.ships.iter().enumerate() {
+        writeln!(out,
+                 "p{} ({}): won {} ({:.1}%), flipped by waves {}, rams {}, stomps {}",
+                 i,
+                 ship.driver,
+                 ship.wins,
+                 percent(ship.win_rate),
+                 ship.flipped_by_waves,
+                 ship.flipped_by_rams,
+                 ship.flipped_by_stomps)?;
+    }
+    Ok(())
+}
+
+fn arg_value(name: &str) -> Option<String> {
+    let args: Vec<String> = env::args().collect();
+    args.windows(2)
+        .find(|pair| pair[0] == name)
+        .map(|pair| pair[1].clone())
+}
+
+fn number_arg<T: std::str::FromStr>(name: &str, default: T) -> T {
+    arg_value(name)
+        .map(|n| n.parse().unwrap_or_else(|_| panic!("{} must be a number", name)))
+        .unwrap_or(default)
+}
+
+fn main() {
+    let drivers: Vec<Driver> = arg_value("--ships")
+        .unwrap_or(String::from("normal,normal"))
+        .split(',')
+        .map(|name| {
+            Driver::from_name(name).unwrap_or_else(|| {
+                panic!("unknown ship {}, use easy, normal, hard, idle or circle", name)
+            })
+        })
+        .collect();
+    if drivers.len() < MIN_PLAYERS || drivers.len() > MAX_PLAYERS {
+        panic!("--ships needs {} to {} ships, not {}",
+               MIN_PLAYERS,
+               MAX_PLAYERS,
+               drivers.len());
+    }
+    let matches: u64 = number_arg("--matches", 100);
+    let first_seed: u64 = number_arg("--seed", 0);
+    let max_ticks: usize = number_arg("--max-ticks", 300 * TICKS_PER_SECOND as usize);
+    // The same params as the game, unless told otherwise.
+    let params = match arg_value("--params") {
+        Some(path) => {
+            WaveParams::load(&path).unwrap_or_else(|e| panic!("Could not load {}: {}", path, e))
+        }
+        None => {
+            WaveParams::load(DEFAULT_PARAMS_FILE).unwrap_or_else(|e| {
+                writeln!(io::stderr(), "Using default wave params, {}: {}", DEFAULT_PARAMS_FILE, e)
+                    .unwrap();
+                WaveParams::default()
+            })
+        }
+    };
+    let map = match arg_value("--map") {
+        Some(path) => {
+            Map::load(&path).unwrap_or_else(|e| panic!("Could not load {}: {}", path, e))
+        }
+        None => Map::open_sea(),
+    };
+    if drivers.len() > map.max_players() {
+        panic!("{} only has room for {} ships", map.name, map.max_players());
+    }
+    let json = match arg_value("--format") {
+        None => false,
+        Some(ref f) if f == "csv" => false,
+        Some(ref f) if f == "json" => true,
+        Some(f) => panic!("--format must be csv or json, not {}", f),
+    };
+
+    let results = run_matches(first_seed, matches, params, &map, &drivers, max_ticks);
+    let totals = totals(&drivers, &results);
+
+    let stdout = io::stdout();
+    let mut out = stdout.lock();
+    if json {
+        #[derive(Serialize)]
+        struct Report<'a> {
+            totals: &'a Totals,
+            matches: &'a [MatchResult],
+        }
+        let report = Report {
+            totals: &totals,
+            matches: &results,
+        };
+        serde_json::to_writer_pretty(&mut out, &report).unwrap();
+        writeln!(out).unwrap();
+    } else {
+        writeln!(out, "{}", csv_header(drivers.len())).unwrap();
+        for result in &results {
+            writeln!(out, "{}", csv_line(result)).unwrap();
+        }
+        print_totals(&mut io::stderr(), &totals).unwrap();
+    }
+}
@@ -0,0 +1,431 @@
+//! Computer-controlled ships.
+//!
+//! A bot picks the nearest ship still afloat, steers so that its
+//! landing splash will come down where that ship is going to be, and
+//! jumps when the two line up.  It also jumps to get out of the water
+//! when it's being rocked too hard, a big wave is about to reach it, or
+//! somebody is about to land on it, and keeps away from the edges of
+//! the arena where ships get pinned, and off the rocks.
+//!
+//! Bots only ever see the world as it was a little while ago, which is
+//! what makes them beatable; how long ago, and how carelessly they aim,
+//! depends on the difficulty.
+
+use std::collections::VecDeque;
+use std::f32::consts::PI;
+
+use na::Vector2;
+use rand::{Rng, XorShiftRng};
+
+use seeded_rng;
+use field::{Field, FIELD_CELL_SIZE, screen_to_field};
+use replay::{Input, steer_amount};
+use ship::{Ship, ShipControls, DRAG, JUMP_COOLDOWN, JUMP_TICKS};
+use world::World;
+
+/// Bots start turning away from an edge this many pixels from it.
+const EDGE_MARGIN: f32 = 160.0;
+/// Bots steer away from rocks closer than this many pixels.
+const ROCK_MARGIN: f32 = 100.0;
+/// How much keeping off the edges matters next to chasing the target.
+const EDGE_WEIGHT: f32 = 2.0;
+/// Jump to attack when the landing would be this close to the target,
+/// in pixels.  About the radius of a landing splash.
+const ATTACK_RANGE: f32 = 50.0;
+/// How many ticks ahead bots look at how their ship is rocking.
+const TIPPING_LOOKAHEAD: f32 = 5.0;
+/// Patient bots give up waiting for an opening after having a shot
+/// lined up for this many ticks, in case the target never jumps.
+const PATIENCE: usize = 60;
+/// Patient bots jump this many ticks before somebody lands on them.
+const DODGE_LEAD: usize = 4;
+/// Closer than this to the target, in pixels, and the bot backs off
+/// rather than risk getting rammed.
+const KEEP_CLEAR: f32 = 140.0;
+/// Somebody coming down from a jump closer than this, in pixels,
+/// is about to land on the bot.
+const STOMP_RANGE: f32 = 110.0;
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Difficulty {
+    Easy,
+    Normal,
+    Hard,
+}
+
+impl Difficulty {
+    pub fn from_name(name: &str) -> Option<Difficulty> {
+        match name {
+            "easy" => Some(Difficulty::Easy),
+            "normal" => Some(Difficulty::Normal),
+            "hard" => Some(Difficulty::Hard),
+            _ => None,
+        }
+    }
+
+    /// How many ticks old the world the bot sees is.
+    fn reaction_ticks(&self) -> usize {
+        match *self {
+            Difficulty::Easy => 30,
+            Difficulty::Normal => 15,
+            Difficulty::Hard => 5,
+        }
+    }
+
+    /// How far off, in pixels, the bot can be about where it's aiming.
+    fn aim_error(&self) -> f32 {
+        match *self {
+            Difficulty::Easy => 120.0,
+            Difficulty::Normal => 60.0,
+            Difficulty::Hard => 20.0,
+        }
+    }
+
+    /// How far over, in radians of roll or pitch, the bot lets itself
+    /// get rocked before jumping out of the water.
+    fn dodge_tipping(&self) -> f32 {
+        match *self {
+            Difficulty::Easy => 0.35,
+            Difficulty::Normal => 0.5,
+            Difficulty::Hard => 0.7,
+        }
+    }
+
+    /// How tall a swell the bot will let reach it before jumping it.
+    fn dodge_swell(&self) -> f32 {
+        match *self {
+            Difficulty::Easy => 0.3,
+            Difficulty::Normal => 0.5,
+            Difficulty::Hard => 0.8,
+        }
+    }
+
+    /// Whether the bot waits until its target can't jump out of the way
+    /// before attacking.  Whoever jumps first usually loses, since the
+    /// other ship gets to sit in the air while the splash goes under it.
+    fn patient(&self) -> bool {
+        *self != Difficulty::Easy
+    }
+}
+
+/// What a bot saw on one tick.
+#[derive(Debug, Copy, Clone)]
+struct Sighting {
+    /// Where the ship it's after is and how fast it's going,
+    /// in pixels and pixels per tick.
+    target: Option<(Vector2<f32>, Vector2<f32>)>,
+    /// How long until the target comes down, if it's in the air.
+    target_landing: Option<usize>,
+    /// How far over its own ship was going to be rocked a few ticks on.
+    tipping: f32,
+    /// The difference between the highest and lowest water
+    /// around its own ship.
+    swell: f32,
+    /// Where the nearest ship in the air is going to come down,
+    /// if it's going to be close, and how long until it does.
+    incoming: Option<(Vector2<f32>, usize)>,
+}
+
+pub struct Bot {
+    player: usize,
+    difficulty: Difficulty,
+    rng: XorShiftRng,
+    // Oldest first; the bot acts on the one at the front.
+    seen: VecDeque<Sighting>,
+    aim_offset: Vector2<f32>,
+    // The last steering sent by `inputs()`.
+    sent: Option<(i16, i16)>,
+    // How many ticks a patient bot has had a shot lined up
+    // without an opening.
+    waited: usize,
+}
+
+/// Where a ship will be after `ticks` ticks of holding `thrust`
+/// and not turning.
+fn predict(ship: &Ship, ticks: usize, thrust: f32) -> Vector2<f32> {
+    let forward = Vector2::new(ship.bearing.sin(), -ship.bearing.cos());
+    let mut location = ship.location;
+    let mut velocity = ship.velocity;
+    for _ in 0..ticks {
+        location += velocity * ship.speed();
+        velocity = (velocity + forward * thrust) * DRAG;
+    }
+    location
+}
+
+// Neighbouring seeds start XorShift off almost the same, so the seed
+// gets stirred up first.  This is the SplitMix64 finaliser.
+fn scramble(seed: u64) -> u64 {
+    let z = (seed ^ (seed >> 30)).wrapping_mul(0xBF58_476D_1CE4_E5B9);
+    let z = (z ^ (z >> 27)).wrapping_mul(0x94D0_49BB_1331_11EB);
+    z ^ (z >> 31)
+}
+
+/// Wraps an angle into -pi to pi.
+fn wrap_angle(a: f32) -> f32 {
+    let a = a % (2.0 * PI);
+    if a > PI {
+        a - 2.0 * PI
+    } else if a < -PI {
+        a + 2.0 * PI
+    } else {
+        a
+    }
+}
+
+/// A push away from whichever edges of the arena `location` is near,
+/// and from the nearest rock, stronger the closer it is.
+fn edge_push(location: Vector2<f32>, field: &Field) -> Vector2<f32> {
+    let push = |from_low: f32, size: f32| {
+        if from_low < EDGE_MARGIN {
+            1.0 - from_low / EDGE_MARGIN
+        } else if size - from_low < EDGE_MARGIN {
+            -(1.0 - (size - from_low) / EDGE_MARGIN)
+        } else {
+            0.0
+        }
+    };
+    let (width, height) = field.screen_size();
+    let mut away = Vector2::new(push(location.x, width), push(location.y, height));
+    if let Some(rock) = nearest_rock(location, field) {
+        let from_rock = location - rock;
+        let distance = from_rock.norm();
+        if distance > 1.0 {
+            away += from_rock / distance * (1.0 - distance / ROCK_MARGIN);
+        }
+    }
+    away
+}
+
+/// The middle of the nearest rock cell within `ROCK_MARGIN`, if any.
+fn nearest_rock(location: Vector2<f32>, field: &Field) -> Option<Vector2<f32>> {
+    let cell = FIELD_CELL_SIZE as f32;
+    let reach = (ROCK_MARGIN / cell) as i32 + 1;
+    let (cx, cy) = screen_to_field(location.x, location.y);
+    let (cx, cy) = (cx as i32, cy as i32);
+    let mut nearest: Option<(f32, Vector2<f32>)> = None;
+    for y in cy - reach..cy + reach + 1 {
+        for x in cx - reach..cx + reach + 1 {
+            if x < 0 || y < 0 || x >= field.width() as i32 || y >= field.height() as i32 ||
+               !field.is_rock(x as usize, y as usize) {
+                continue;
+            }
+            let centre = Vector2::new((x as f32 + 0.5) * cell, (y as f32 + 0.5) * cell);
+            let distance = (centre - location).norm();
+            if distance < ROCK_MARGIN && nearest.map_or(true, |(d, _)| distance < d) {
+                nearest = Some((distance, centre));
+            }
+        }
+    }
+    nearest.map(|(_, centre)| centre)
+}
+
+impl Bot {
+    /// A bot driving player number `player`.  Bots with the same seed
+    /// make the same decisions, given the same world.
+    pub fn new(player: usize, difficulty: Difficulty, seed: u64) -> Self {
+        let mut bot = Bot {
+            player: player,
+            difficulty: difficulty,
+            rng: seeded_rng(scramble(seed ^
+                                     (player as u64 + 1).wrapping_mul(0x9E37_79B9_7F4A_7C15))),
+            seen: VecDeque::new(),
+            aim_offset: Vector2::new(0.0, 0.0),
+            sent: None,
+            waited: 0,
+        };
+        bot.reaim();
+        bot
+    }
+
+    // Picks how far off the next attack will be.
+    fn reaim(&mut self) {
+        let error = self.difficulty.aim_error();
+        self.aim_offset = Vector2::new(self.rng.gen_range(-error, error),
+                                       self.rng.gen_range(-error, error));
+    }
+
+    pub fn player(&self) -> usize {
+        self.player
+    }
+
+    pub fn difficulty(&self) -> Difficulty {
+        self.difficulty
+    }
+
+    /// Forgets everything it's seen, for when the match starts over.
+    pub fn reset(&mut self) {
+        self.seen.clear();
+        self.sent = None;
+        self.waited = 0;
+    }
+
+    fn look(&self, world: &World) -> Sighting {
+        let me = &world.players[self.player].ship;
+        let target = world.players
+            .iter()
+            .enumerate()
+            .filter(|&(i, p)| i != self.player && p.afloat())
+            .map(|(_, p)| &p.ship)
+            .min_by(|a, b| {
+                let da = (a.location - me.location).norm_squared();
+                let db = (b.location - me.location).norm_squared();
+                da.partial_cmp(&db).unwrap_or(::std::cmp::Ordering::Equal)
+            })
+            .map(|ship| (ship.location, ship.velocity * ship.speed(), ship.jump_ticks_left()));
+
+        // Look at the water over twice the length and width of the hull,
+        // to see waves before they arrive.
+        let mut around = me.footprint();
+        around.length *= 2.0;
+        around.width *= 2.0;
+        let water = world.field.sample_hull(&around);
+
+        let incoming = world.players
+            .iter()
+            .enumerate()
+            .filter(|&(i, p)| i != self.player && p.afloat())
+            .filter_map(|(_, p)| {
+                p.ship.jump_ticks_left().map(|ticks| (predict(&p.ship, ticks, 0.0), ticks))
+            })
+            .filter(|&(landing, _)| (landing - me.location).norm() < STOMP_RANGE)
+            .next();
+
+        Sighting {
+            target: target.map(|(location, velocity, _)| (location, velocity)),
+            target_landing: target.and_then(|(_, _, landing)| landing),
+            tipping: f32::max((me.roll() + me.roll_velocity() * TIPPING_LOOKAHEAD).abs(),
+                              (me.pitch() + me.pitch_velocity() * TIPPING_LOOKAHEAD).abs()),
+            swell: water.max - water.min,
+            incoming: incoming,
+        }
+    }
+
+    // Whether the target will still be stuck on the water, waiting
+    // to jump again, when a jump started now comes down.
+    fn opening(&self, seen: &Sighting) -> bool {
+        let age = self.seen.len() - 1;
+        match seen.target_landing {
+            Some(ticks) if ticks > age => {
+                let ticks = ticks - age;
+                ticks + JUMP_COOLDOWN > JUMP_TICKS && ticks <= JUMP_TICKS
+            }
+            _ => false,
+        }
+    }
+
+    // Whether to jump out of the way of somebody landing in `ticks`
+    // ticks, as of when the bot saw them.  Patient bots wait until the
+    // last moment, so they're still in the air when the splash goes by.
+    fn dodge_now(&self, ticks: usize) -> bool {
+        let age = self.seen.len() - 1;
+        !self.difficulty.patient() || ticks <= age + DODGE_LEAD
+    }
+
+    /// Decides what to do this tick.  Call it once per tick,
+    /// before the world is updated.
+    pub fn think(&mut self, world: &World) -> ShipControls {
+        let sighting = self.look(world);
+        self.seen.push_back(sighting);
+        while self.seen.len() > self.difficulty.reaction_ticks() + 1 {
+            self.seen.pop_front();
+        }
+        let seen = self.seen[0];
+
+        let me = &world.players[self.player].ship;
+        if me.flipped {
+            return ShipControls::default();
+        }
+
+        // Head for wherever the target will be after one jump's worth
+        // of ticks, give or take the bot's aim.
+        let mut heading = edge_push(me.location, &world.field) * EDGE_WEIGHT;
+        let mut attack = false;
+        if let Some((location, velocity)) = seen.target {
+            let aim = location + velocity * JUMP_TICKS as f32 + self.aim_offset;
+            let to_aim = aim - me.location;
+            if to_aim.norm() > 1.0 {
+                heading += to_aim.normalize();
+            }
+            let away = me.location - location;
+            if away.norm() < KEEP_CLEAR && away.norm() > 1.0 {
+                heading += away.normalize() * 2.0 * (1.0 - away.norm() / KEEP_CLEAR);
+            }
+            let landing = predict(me, JUMP_TICKS, 1.0);
+            if (landing - aim).norm() < ATTACK_RANGE {
+                attack = !self.difficulty.patient() || self.opening(&seen) ||
+                         self.waited > PATIENCE;
+                self.waited += 1;
+            }
+        }
+
+        // If somebody's about to land on us and we can't jump out of
+        // the way, at least try to get out from under them.
+        if let Some((landing, _)) = seen.incoming {
+            let away = me.location - landing;
+            if !me.can_jump() && away.norm() > 1.0 {
+                heading = away.normalize() * EDGE_WEIGHT +
+                          edge_push(me.location, &world.field) * EDGE_WEIGHT;
+            }
+        }
+
+        // Slide round any rock in the bow's way rather than pushing into it.
+        let forward = Vector2::new(me.bearing.sin(), -me.bearing.cos());
+        let bow = me.location + forward * (me.hull_size().0 / 2.0);
+        if let Some(rock) = nearest_rock(bow, &world.field) {
+            let from_rock = bow - rock;
+            if from_rock.norm() > 1.0 {
+                let out = from_rock.normalize();
+                let into = heading.x * out.x + heading.y * out.y;
+                if into < 0.0 {
+                    heading -= out * into;
+                }
+            }
+        }
+
+        let dodge = seen.tipping > self.difficulty.dodge_tipping() ||
+                    seen.swell > self.difficulty.dodge_swell() ||
+                    seen.incoming.map_or(false, |(_, ticks)| self.dodge_now(ticks));
+        let jump = me.can_jump() && (dodge || attack);
+        if jump {
+            // Aim a bit differently for the next one.
+            self.reaim();
+            self.waited = 0;
+        }
+
+        // Bearings are measured clockwise from straight up the screen.
+        let (turn, thrust) = if heading.norm() > 0.0 {
+            let wanted = heading.x.atan2(-heading.y);
+            let off = wrap_angle(wanted - me.bearing);
+            // Ease off the turn as the ship's own spin will carry it round.
+            let turn = off * 2.0 - me.angular_velocity * 20.0;
+            let thrust = if off.abs() < PI / 2.0 { 1.0 } else { 0.3 };
+            (turn, thrust)
+        } else {
+            (0.0, 0.0)
+        };
+
+        ShipControls {
+            thrust: thrust,
+            turn: f32::max(-1.0, f32::min(turn, 1.0)),
+            jump: jump,
+        }
+    }
+
+    /// Decides what to do this tick, as inputs for the bot's player.
+    /// Steering is only sent when it changes.
+    pub fn inputs(&mut self, world: &World) -> Vec<Input> {
+        let controls = self.think(world);
+        let mut inputs = Vec::new();
+        let steer = (steer_amount(controls.thrust), steer_amount(controls.turn));
+        if self.sent != Some(steer) {
+            self.sent = Some(steer);
+            inputs.push(Input::Steer(self.player, steer.0, steer.1));
+        }
+        if controls.jump {
+            inputs.push(Input::Jump(self.player));
+        }
+        inputs
+    }
+}
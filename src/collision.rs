@@ -0,0 +1,230 @@
+//! Ships bumping into each other, and into rocks.
+//!
+//! Hulls are boxes turned to the ship's bearing, checked against each
+//! other with the separating axis test.  Ships that hit bounce off with
+//! some of their speed soaked up, and get spun around and rocked by it.
+//! Rocks are square boxes that don't move.
+
+use na::Vector2;
+
+use ship::Ship;
+
+/// How much of the closing speed is left after a hit.
+/// 1.0 would be perfectly bouncy, 0.0 would be two lumps of clay.
+const RESTITUTION: f32 = 0.4;
+
+/// How hard a hit rocks a ship, in radians per tick of roll or pitch per
+/// pixel per tick of impulse.  A full speed T-bone should about capsize.
+const KNOCK_COUPLING: f32 = 0.1;
+/// Ships are longer than they're wide, so hits on the bow or stern
+/// pitch them a lot less than hits on the side roll them.
+const PITCH_KNOCK_FACTOR: f32 = 0.4;
+
+/// Where and how hard two ships hit.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct Impact {
+    /// Roughly where the hulls touched, in screen pixels.
+    pub point: Vector2<f32>,
+    /// Unit vector pointing from the first ship towards the second.
+    pub normal: Vector2<f32>,
+    /// How much speed changed hands, in pixels per tick.
+    /// 0 if the ships were already moving apart.
+    pub impulse: f32,
+}
+
+/// A ship's hull as a box in screen pixels.
+#[derive(Debug, Copy, Clone, PartialEq)]
+struct Hull {
+    centre: Vector2<f32>,
+    forward: Vector2<f32>,
+    starboard: Vector2<f32>,
+    half_length: f32,
+    half_width: f32,
+}
+
+impl Hull {
+    fn of(ship: &Ship) -> Self {
+        let (length, width) = ship.hull_size();
+        let b = ship.bearing;
+        Hull {
+            centre: ship.location,
+            forward: Vector2::new(b.sin(), -b.cos()),
+            starboard: Vector2::new(b.cos(), b.sin()),
+            half_length: length / 2.0,
+            half_width: width / 2.0,
+        }
+    }
+
+    /// How far the box reaches from its centre along `axis`.
+    fn reach(&self, axis: Vector2<f32>) -> f32 {
+        self.half_length * dot(self.forward, axis).abs() +
+        self.half_width * dot(self.starboard, axis).abs()
+    }
+
+    fn corners(&self) -> [Vector2<f32>; 4] {
+        let f = self.forward * self.half_length;
+        let s = self.starboard * self.half_width;
+        [self.centre + f + s, self.centre + f - s, self.centre - f + s, self.centre - f - s]
+    }
+
+    /// A square rock `size` pixels across.
+    fn rock(centre: Vector2<f32>, size: f32) -> Self {
+        Hull {
+            centre: centre,
+            forward: Vector2::new(0.0, -1.0),
+            starboard: Vector2::new(1.0, 0.0),
+            half_length: size / 2.0,
+            half_width: size / 2.0,
+        }
+    }
+
+    /// Rotational inertia of the box, for a ship of mass 1.
+    fn inertia(&self) -> f32 {
+        let l = self.half_length * 2.0;
+        let w = self.half_width * 2.0;
+        (l * l + w * w) / 12.0
+    }
+}
+
+fn dot(a: Vector2<f32>, b: Vector2<f32>) -> f32 {
+    a.x * b.x + a.y * b.y
+}
+
+// 2D cross product; positive means `b` is clockwise of `a` on screen,
+// the same way bearings go.
+fn cross(a: Vector2<f32>, b: Vector2<f32>) -> f32 {
+    a.x * b.y - a.y * b.x
+}
+
+/// If the hulls overlap, returns how far they need to move apart along
+/// which axis (pointing from `a` to `b`), and the point they touch at.
+fn overlap(a: &Hull, b: &Hull) -> Option<(f32, Vector2<f32>, Vector2<f32>)> {
+    let d = b.centre - a.centre;
+    let axes = [(a.forward, true), (a.starboard, true), (b.forward, false), (b.starboard, false)];
+    let mut best: Option<(f32, Vector2<f32>, bool)> = None;
+    for &(axis, from_a) in &axes {
+        let depth = a.reach(axis) + b.reach(axis) - dot(d, axis).abs();
+        if depth <= 0.0 {
+            return None;
+        }
+        if best.map_or(true, |(best_depth, _, _)| depth < best_depth) {
+            let normal = if dot(d, axis) < 0.0 { -axis } else { axis };
+            best = Some((depth, normal, from_a));
+        }
+    }
+    let (depth, normal, from_a) = best?;
+
+    // The contact is on whichever box's corners poke deepest into the
+    // face of the other one.  When two corners tie (flat side against
+    // flat side) it's halfway between them.
+    let (corners, push) = if from_a {
+        (b.corners(), -normal)
+    } else {
+        (a.corners(), normal)
+    };
+    let deepest = corners.iter().map(|&c| dot(c, push)).fold(::std::f32::MIN, f32::max);
+    let mut point = Vector2::new(0.0, 0.0);
+    let mut count = 0.0;
+    for &c in &corners {
+        if deepest - dot(c, push) < 1.0 {
+            point += c;
+            count += 1.0;
+        }
+    }
+    Some((depth, normal, point / count))
+}
+
+// How fast a point `r` from the centre of a ship is moving, in pixels
+// per tick.  A ship turning at `w` moves it at `w` times `r` turned a
+// quarter clockwise.
+fn point_velocity(ship: &Ship, r: Vector2<f32>) -> Vector2<f32> {
+    ship.velocity * ship.speed() + Vector2::new(-r.y, r.x) * ship.angular_velocity
+}
+
+// Getting shoved sideways rolls a ship, getting shoved end-on pitches it.
+fn knock(ship: &mut Ship, hull: &Hull, push: Vector2<f32>) {
+    ship.knock(dot(push, hull.starboard) * KNOCK_COUPLING,
+               -dot(push, hull.forward) * KNOCK_COUPLING * PITCH_KNOCK_FACTOR);
+}
+
+/// Whether two ships' hulls overlap, jumping or not.
+pub fn hulls_touch(a: &Ship, b: &Ship) -> bool {
+    overlap(&Hull::of(a), &Hull::of(b)).is_some()
+}
+
+/// Checks two ships for a collision and, if they hit, shoves them apart
+/// and bounces them off each other.  Both ships weigh the same.
+pub fn collide(a: &mut Ship, b: &mut Ship) -> Option<Impact> {
+    let hull_a = Hull::of(a);
+    let hull_b = Hull::of(b);
+    let (depth, normal, point) = overlap(&hull_a, &hull_b)?;
+
+    // Get them out of each other first, half each.
+    a.location -= normal * (depth / 2.0);
+    b.location += normal * (depth / 2.0);
+
+    // How fast the touching points are closing, in pixels per tick.
+    let r_a = point - hull_a.centre;
+    let r_b = point - hull_b.centre;
+    let closing = dot(point_velocity(b, r_b) - point_velocity(a, r_a), normal);
+    if closing >= 0.0 {
+        return Some(Impact {
+            point: point,
+            normal: normal,
+            impulse: 0.0,
+        });
+    }
+
+    let arm_a = cross(r_a, normal);
+    let arm_b = cross(r_b, normal);
+    let impulse = -(1.0 + RESTITUTION) * closing /
+                  (2.0 + arm_a * arm_a / hull_a.inertia() + arm_b * arm_b / hull_b.inertia());
+
+    // Ship velocities get scaled by their speed when they move,
+    // so take that back out.
+    a.velocity -= normal * (impulse / a.speed());
+    a.angular_velocity -= arm_a * impulse / hull_a.inertia();
+    b.velocity += normal * (impulse / b.speed());
+    b.angular_velocity += arm_b * impulse / hull_b.inertia();
+
+    knock(a, &hull_a, -normal * impulse);
+    knock(b, &hull_b, normal * impulse);
+
+    Some(Impact {
+        point: point,
+        normal: normal,
+        impulse: impulse,
+    })
+}
+
+/// Checks a ship against a square rock `size` pixels across centred on
+/// `centre`, and if they hit, shoves the ship clear and bounces it off.
+/// The normal in the `Impact` points from the ship towards the rock.
+pub fn collide_rock(ship: &mut Ship, centre: Vector2<f32>, size: f32) -> Option<Impact> {
+    let hull = Hull::of(ship);
+    let (depth, normal, point) = overlap(&hull, &Hull::rock(centre, size))?;
+    ship.location -= normal * depth;
+
+    let r = point - hull.centre;
+    let closing = -dot(point_velocity(ship, r), normal);
+    if closing >= 0.0 {
+        return Some(Impact {
+            point: point,
+            normal: normal,
+            impulse: 0.0,
+        });
+    }
+
+    // Like two ships, but the rock weighs so much it doesn't budge.
+    let arm = cross(r, normal);
+    let impulse = -(1.0 + RESTITUTION) * closing / (1.0 + arm * arm / hull.inertia());
+    ship.velocity -= normal * (impulse / ship.speed());
+    ship.angular_velocity -= arm * impulse / hull.inertia();
+    knock(ship, &hull, -normal * impulse);
+
+    Some(Impact {
+        point: point,
+        normal: normal,
+        impulse: impulse,
+    })
+}
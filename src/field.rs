@@ -0,0 +1,542 @@
+use rand::{Rng, XorShiftRng};
+#[cfg(feature = "parallel")]
+use rayon::prelude::*;
+
+use std::cmp::{min, max};
+
+use seeded_rng;
+use grid::Grid;
+use splash::{Splash, SplashTarget};
+use params::WaveParams;
+use snapshot::{SnapshotWriter, SnapshotReader, SnapshotError};
+
+pub const FIELD_WIDTH: usize = 80;
+pub const FIELD_HEIGHT: usize = 60;
+pub const FIELD_CELL_SIZE: u32 = 10;
+
+/// Water this deep carries waves at the speed the params say.
+pub const DEFAULT_DEPTH: f32 = 1.0;
+/// The deepest water can be.  Waves are faster in deeper water, so
+/// this is what `WaveParams::is_stable()` has to allow for.
+pub const MAX_DEPTH: f32 = 2.0;
+/// A depth of nothing is solid rock.
+pub const ROCK: f32 = 0.0;
+/// The most of a wave a cell can soak up each tick, like in a thick
+/// kelp bed.  Any more and waves would stop dead instead of fading out.
+pub const MAX_DAMPING: f32 = 0.5;
+
+pub fn screen_to_field_coords(x: u32, y: u32) -> (usize, usize) {
+    let xn = (x / FIELD_CELL_SIZE) as usize;
+    let yn = (y / FIELD_CELL_SIZE) as usize;
+    (xn, yn)
+}
+
+/// Like `screen_to_field_coords()` but keeps the fraction,
+/// for things like splashes that don't have to land on a cell.
+pub fn screen_to_field(x: f32, y: f32) -> (f32, f32) {
+    (x / FIELD_CELL_SIZE as f32, y / FIELD_CELL_SIZE as f32)
+}
+
+pub fn field_to_screen_coords(x: usize, y: usize) -> (i32, i32) {
+    let xn = (x as u32) * FIELD_CELL_SIZE;
+    let yn = (y as u32) * FIELD_CELL_SIZE;
+    (xn as i32, yn as i32)
+}
+
+/// What happens to waves when they hit the edge of the field.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Boundary {
+    /// The edge is a wall and waves bounce off it.
+    Reflecting,
+    /// Waves fade out in a band along the edges instead of bouncing, see
+    /// `WaveParams::sponge_width` and `sponge_damping`.
+    Absorbing,
+    /// Waves going off one side come back on the opposite side.
+    Periodic,
+    /// The water just past the edge is held perfectly flat.
+    FixedZero,
+}
+
+impl Default for Boundary {
+    fn default() -> Self {
+        Boundary::Reflecting
+    }
+}
+
+#[derive(Copy, Clone, Debug)]
+pub struct WaveType {
+    pub velocity: f32,
+    pub position: f32,
+}
+
+impl WaveType {
+    pub fn new(position: f32) -> Self {
+        WaveType {
+            velocity: 0.0,
+            position: position,
+        }
+    }
+
+    #[inline]
+    fn restoring_force(&self, strength: f32) -> f32 {
+        // Position is correct but maybe
+        // velocity plays better?
+        -self.position * strength
+        //-self.velocity * 0.002
+    }
+}
+
+impl Default for WaveType {
+    fn default() -> Self {
+        // If you make these 0 the optimizer/OS won't
+        // actually allocate space for the arrays it needs
+        // until the game is running, I suspect.
+        // So it gets laggy for the first few seconds.
+        // With a slight offset it APPEARS to MOSTLY fix the problem.
+        WaveType {
+            velocity: 0.001,
+            position: 0.001,
+        }
+    }
+}
+
+/// The per-tick numbers `step_row` needs, worked out once per tick
+/// from the `WaveParams`.
+#[derive(Copy, Clone, Debug)]
+struct Coefficients {
+    dt: f32,
+    inv_surface_tension: f32,
+    restoring_force: f32,
+    decay_factor: f32,
+}
+
+/// What's under a cell of water, as far as `step_row` cares.
+#[derive(Copy, Clone, Debug)]
+struct Seabed {
+    /// How strongly the cell is tied to each of its neighbors: above,
+    /// below, left, right, then up-left, up-right, down-left and
+    /// down-right.  Worked out from the depths.
+    ties: [f32; 8],
+    /// The fraction of the wave soaked up each tick, on top of
+    /// `decay_factor`.
+    damping: f32,
+}
+
+// Two cells are tied together by the harmonic mean of their depths, so
+// nothing is tied to a rock and waves bounce off it like off a wall.
+// Two cells of the default depth come out at exactly 1.
+fn couple(a: f32, b: f32) -> f32 {
+    if a + b == 0.0 {
+        0.0
+    } else {
+        2.0 * a * b / (a + b)
+    }
+}
+
+// Steps the wave equation for one row of the field, measuring time in ticks,
+//
+//     d2u/dt2 = dt * (div(depth * grad(u)) / surface_tension - restoring_force * u)
+//
+// with semi-implicit (symplectic) Euler: velocity first, then position
+// from the new velocity.  The divergence is the difference between a cell
+// and its 8 neighbors, each weighted by how strongly they're tied, with
+// the diagonals weighted by 1/sqrt(2) on top.  Waves slow down and bunch
+// up going into shallow water, and bend towards it, like real ones.
+// Then each cell loses `decay_factor` and its own damping.
+//
+// `src` is the whole padded grid for the current tick, `out` is row `y`
+// of the padded grid for the next one, padding cells included.  Since rows
+// only read from `src`, they can be done in any order, or all at once.
+//
+// See `WaveParams::courant_number()` for when this is stable.
+fn step_row(src: &[WaveType],
+            seabed: &[Seabed],
+            stride: usize,
+            y: usize,
+            out: &mut [WaveType],
+            c: &Coefficients) {
+    let row = (y + 1) * stride;
+    let above = &src[row - stride..row];
+    let here = &src[row..row + stride];
+    let below = &src[row + stride..row + 2 * stride];
+    let beds = &seabed[row..row + stride];
+    for x in 1..stride - 1 {
+        let mut val = here[x];
+        let u = val.position;
+        let k = &beds[x].ties;
+
+        // total force = restoring force plus a force based on the
+        // sum of differences in position  between itself and its
+        // neighbors, however strongly it's tied to each.
+        let neighbor_force = (above[x].position - u) * k[0] + (below[x].position - u) * k[1] +
+                             (here[x - 1].position - u) * k[2] +
+                             (here[x + 1].position - u) * k[3] +
+                             (above[x - 1].position - u) * k[4] +
+                             (above[x + 1].position - u) * k[5] +
+                             (below[x - 1].position - u) * k[6] +
+                             (below[x + 1].position - u) * k[7];
+        let forces = val.restoring_force(c.restoring_force) +
+                     neighbor_force * c.inv_surface_tension;
+        val.velocity += forces;
+        val.position += val.velocity * c.dt;
+
+        // Decay intensity.
+        // Decaying position vs. velocity doesn't seem
+        // to have made much difference
+        let decay = c.decay_factor * (1.0 - beds[x].damping);
+        val.velocity *= decay;
+        val.position *= decay;
+
+        out[x] = val;
+    }
+}
+
+#[derive(Clone)]
+pub struct Field {
+    cells: Grid<WaveType>,
+    // Scratch buffer that each tick gets written into,
+    // so every cell is computed from the same old state.
+    next: Grid<WaveType>,
+    // How deep the water is; `ROCK` for rocks.  The padding copies the
+    // nearest real cell, whatever the boundary.
+    depth: Grid<f32>,
+    // The ties in here are worked out from `depth` whenever it changes.
+    seabed: Grid<Seabed>,
+    rng: XorShiftRng,
+    pub params: WaveParams,
+}
+
+impl Field {
+    pub fn new(seed: u64, params: WaveParams) -> Self {
+        Field::with_size(FIELD_WIDTH, FIELD_HEIGHT, seed, params)
+    }
+
+    pub fn with_size(width: usize, height: usize, seed: u64, params: WaveParams) -> Self {
+        let cells = Grid::new(width, height, WaveType::default());
+        let mut field = Field {
+            next: cells.clone(),
+            cells: cells,
+            depth: Grid::new(width, height, DEFAULT_DEPTH),
+            seabed: Grid::new(width,
+                              height,
+                              Seabed {
+                                  ties: [0.0; 8],
+                                  damping: 0.0,
+                              }),
+            rng: seeded_rng(seed),
+            params: params,
+        };
+        field.update_ties(0, 0, width, height);
+        field
+    }
+
+    /// Flattens out all the water, leaving the rocks and depths alone.
+    pub fn calm(&mut self, seed: u64) {
+        let (w, h) = (self.width(), self.height());
+        self.cells = Grid::new(w, h, WaveType::default());
+        self.next = self.cells.clone();
+        self.rng = seeded_rng(seed);
+    }
+
+    /// How deep the water is at a cell; `ROCK` if it's a rock.
+    pub fn depth(&self, x: usize, y: usize) -> f32 {
+        self.depth.get(x, y)
+    }
+
+    pub fn is_rock(&self, x: usize, y: usize) -> bool {
+        self.depth.get(x, y) == ROCK
+    }
+
+    /// Changes how deep the water is at a cell, up to `MAX_DEPTH`.
+    /// Setting it to `ROCK` makes a rock, and any water there is lost.
+    pub fn set_depth(&mut self, x: usize, y: usize, depth: f32) {
+        let depth = f32::max(ROCK, f32::min(depth, MAX_DEPTH));
+        self.depth.set(x, y, depth);
+        if depth == ROCK {
+            self.cells.set(x, y, WaveType::new(0.0));
+        }
+        self.update_ties(x.saturating_sub(1), y.saturating_sub(1), x + 2, y + 2);
+    }
+
+    /// How much of a wave a cell soaks up each tick, on top of the
+    /// params' `decay_factor`.
+    pub fn damping(&self, x: usize, y: usize) -> f32 {
+        self.seabed.get(x, y).damping
+    }
+
+    /// Changes how much of a wave a cell soaks up, up to `MAX_DAMPING`.
+    pub fn set_damping(&mut self, x: usize, y: usize, damping: f32) {
+        self.seabed.get_mut(x, y).damping = f32::max(0.0, f32::min(damping, MAX_DAMPING));
+    }
+
+    // Works out the ties for the cells from (x0, y0) up to but not
+    // including (x1, y1), after the depths there have changed.
+    fn update_ties(&mut self, x0: usize, y0: usize, x1: usize, y1: usize) {
+        let w = self.width() as i32;
+        let h = self.height() as i32;
+        self.depth.fill_padding(|x, y| {
+            (max(0, min(x, w - 1)) as usize, max(0, min(y, h - 1)) as usize)
+        });
+        let inv_sqrt2 = ::std::f32::consts::FRAC_1_SQRT_2;
+        let stride = self.depth.stride() as isize;
+        let neighbors = [-stride, stride, -1, 1, -stride - 1, -stride + 1, stride - 1, stride + 1];
+        for y in y0..min(y1, self.height()) {
+            for x in x0..min(x1, self.width()) {
+                let i = self.depth.index(x, y);
+                let depths = self.depth.data();
+                let mut k = [0.0; 8];
+                for (n, &offset) in neighbors.iter().enumerate() {
+                    let other = depths[(i as isize + offset) as usize];
+                    let diagonal = if n < 4 { 1.0 } else { inv_sqrt2 };
+                    k[n] = couple(depths[i], other) * diagonal;
+                }
+                self.seabed.get_mut(x, y).ties = k;
+            }
+        }
+    }
+
+    pub fn width(&self) -> usize {
+        self.cells.width()
+    }
+
+    pub fn height(&self) -> usize {
+        self.cells.height()
+    }
+
+    /// How big the field is on screen, in pixels.
+    pub fn screen_size(&self) -> (f32, f32) {
+        let cell = FIELD_CELL_SIZE as f32;
+        (self.width() as f32 * cell, self.height() as f32 * cell)
+    }
+
+    /// Saves the params, the water, and the depth and damping.  The scratch
+    /// buffer and the padding get filled in afresh every tick, so they're
+    /// left out.
+    pub fn write_snapshot(&self, w: &mut SnapshotWriter) {
+        self.params.write_snapshot(w);
+        w.usize(self.width());
+        w.usize(self.height());
+        for y in 0..self.height() {
+            for x in 0..self.width() {
+                let cell = self.cells.get(x, y);
+                w.f32(cell.position);
+                w.f32(cell.velocity);
+                w.f32(self.depth.get(x, y));
+                w.f32(self.damping(x, y));
+            }
+        }
+    }
+
+    pub fn read_snapshot(r: &mut SnapshotReader, seed: u64) -> Result<Field, SnapshotError> {
+        let params = WaveParams::read_snapshot(r)?;
+        let width = r.usize()?;
+        let height = r.usize()?;
+        // Anything much bigger is a corrupt snapshot, not a big field.
+        if width == 0 || height == 0 || width > 4096 || height > 4096 {
+            return Err(SnapshotError::Invalid("field size"));
+        }
+        let mut field = Field::with_size(width, height, seed, params);
+        for y in 0..height {
+            for x in 0..width {
+                let position = r.f32()?;
+                let velocity = r.f32()?;
+                let depth = r.f32()?;
+                if !(depth >= ROCK && depth <= MAX_DEPTH) {
+                    return Err(SnapshotError::Invalid("depth"));
+                }
+                let damping = r.f32()?;
+                if !(damping >= 0.0 && damping <= MAX_DAMPING) {
+                    return Err(SnapshotError::Invalid("damping"));
+                }
+                field.cells.set(x,
+                                y,
+                                WaveType {
+                                    velocity: velocity,
+                                    position: position,
+                                });
+                field.depth.set(x, y, depth);
+                field.set_damping(x, y, damping);
+            }
+        }
+        field.update_ties(0, 0, width, height);
+        Ok(field)
+    }
+
+    /// Returns the cell at the given field coordinates.
+    pub fn cell(&self, x: usize, y: usize) -> WaveType {
+        self.cells.get(x, y)
+    }
+
+    pub fn update(&mut self) {
+        // self.sprinkle_random_bits();
+        #[cfg(feature = "parallel")]
+        self.step_parallel();
+        #[cfg(not(feature = "parallel"))]
+        self.step_serial();
+    }
+
+    fn coefficients(&self) -> Coefficients {
+        // Setting decay_factor to 0.98 makes the wave go forever,
+        // setting it to 0.97 makes it just kind of go plonk.
+        // At least with a surface tension of 3.0.
+        Coefficients {
+            dt: self.params.dt,
+            inv_surface_tension: 1.0 / self.params.surface_tension,
+            restoring_force: self.params.restoring_force,
+            decay_factor: self.params.decay_factor,
+        }
+    }
+
+    // Sets up the padding around the grid so that `step_row` sees
+    // whatever the boundary wants to be past the edge.
+    fn fill_padding(&mut self) {
+        let w = self.width() as i32;
+        let h = self.height() as i32;
+        match self.params.boundary {
+            // Walls: the padding copies the nearest real cell, so there's
+            // no difference across the edge to push on anything.
+            Boundary::Reflecting | Boundary::Absorbing => {
+                self.cells.fill_padding(|x, y| {
+                    (max(0, min(x, w - 1)) as usize, max(0, min(y, h - 1)) as usize)
+                })
+            }
+            Boundary::Periodic => {
+                self.cells.fill_padding(|x, y| (((x + w) % w) as usize, ((y + h) % h) as usize))
+            }
+            Boundary::FixedZero => {
+                self.cells.set_padding(WaveType {
+                    velocity: 0.0,
+                    position: 0.0,
+                })
+            }
+        }
+    }
+
+    // Damps cells near the edges, more the closer they are, so waves
+    // running into the edge die out instead of bouncing back.  The
+    // damping ramps up quadratically so there's no sharp change in
+    // the water for waves to reflect off of.
+    fn apply_sponge(&mut self) {
+        let sponge = self.params.sponge_width;
+        if self.params.boundary != Boundary::Absorbing || sponge == 0 {
+            return;
+        }
+        let (w, h) = (self.width(), self.height());
+        let strength = self.params.sponge_damping;
+        for y in 0..h {
+            for x in 0..w {
+                let edge_distance = min(min(x, w - 1 - x), min(y, h - 1 - y));
+                if edge_distance >= sponge {
+                    continue;
+                }
+                let depth = (sponge - edge_distance) as f32 / sponge as f32;
+                let keep = 1.0 - strength * depth * depth;
+                let cell = self.cells.get_mut(x, y);
+                cell.velocity *= keep;
+                cell.position *= keep;
+            }
+        }
+    }
+
+    /// Advances the field one tick, one row at a time.
+    pub fn step_serial(&mut self) {
+        self.fill_padding();
+        let c = self.coefficients();
+        let stride = self.cells.stride();
+        let height = self.height();
+        {
+            let src = self.cells.data();
+            let seabed = self.seabed.data();
+            let rows = self.next.data_mut().chunks_mut(stride).enumerate();
+            for (y, out) in rows.skip(1).take(height) {
+                step_row(src, seabed, stride, y - 1, out, &c);
+            }
+        }
+        ::std::mem::swap(&mut self.cells, &mut self.next);
+        self.apply_sponge();
+    }
+
+    /// Advances the field one tick, with rows spread across threads.
+    /// Gives exactly the same result as `step_serial()`.
+    #[cfg(feature = "parallel")]
+    pub fn step_parallel(&mut self) {
+        self.fill_padding();
+        let c = self.coefficients();
+        let stride = self.cells.stride();
+        let height = self.height();
+        {
+            let src = self.cells.data();
+            let seabed = self.seabed.data();
+            self.next
+                .data_mut()
+                .par_chunks_mut(stride)
+                .enumerate()
+                .filter(|&(y, _)| y >= 1 && y <= height)
+                .for_each(|(y, out)| step_row(src, seabed, stride, y - 1, out, &c));
+        }
+        ::std::mem::swap(&mut self.cells, &mut self.next);
+        self.apply_sponge();
+    }
+
+    /// Adds a splash centred on field coordinates `x`, `y`.  Any of it
+    /// that lands outside the field or on rocks is just dropped.
+    pub fn splash(&mut self, x: f32, y: f32, splash: &Splash) {
+        let extent = splash.extent();
+        let w = self.width() as i32;
+        let h = self.height() as i32;
+        let min_x = max((x - extent).floor() as i32, 0);
+        let max_x = min((x + extent).ceil() as i32, w - 1);
+        let min_y = max((y - extent).floor() as i32, 0);
+        let max_y = min((y + extent).ceil() as i32, h - 1);
+        for yi in min_y..max_y + 1 {
+            for xi in min_x..max_x + 1 {
+                let weight = splash.weight(xi as f32 - x, yi as f32 - y);
+                if weight == 0.0 || self.is_rock(xi as usize, yi as usize) {
+                    continue;
+                }
+                let cell = self.cells.get_mut(xi as usize, yi as usize);
+                // Setting position vs. velocity doesn't appear to make
+                // much difference.
+                // ...okay, the position makes bigger waves.
+                match splash.target {
+                    SplashTarget::Position => cell.position += splash.force * weight,
+                    SplashTarget::Velocity => cell.velocity += splash.force * weight,
+                }
+            }
+        }
+    }
+
+    /// The water height at a point in field coordinates, blended from
+    /// the four nearest cells.  Cell (x, y) covers x..x+1 and y..y+1, so
+    /// whole numbers are on cell corners.  Points past the edge of the
+    /// field read the nearest cell at the edge.
+    pub fn height_at(&self, x: f32, y: f32) -> f32 {
+        let fx = x - 0.5;
+        let fy = y - 0.5;
+        let x0 = fx.floor();
+        let y0 = fy.floor();
+        let tx = fx - x0;
+        let ty = fy - y0;
+
+        let w = self.width() as i32;
+        let h = self.height() as i32;
+        let clamp_x = |x: i32| max(0, min(x, w - 1)) as usize;
+        let clamp_y = |y: i32| max(0, min(y, h - 1)) as usize;
+        let (xa, xb) = (clamp_x(x0 as i32), clamp_x(x0 as i32 + 1));
+        let (ya, yb) = (clamp_y(y0 as i32), clamp_y(y0 as i32 + 1));
+
+        let top = self.cells.get(xa, ya).position * (1.0 - tx) +
+                  self.cells.get(xb, ya).position * tx;
+        let bottom = self.cells.get(xa, yb).position * (1.0 - tx) +
+                     self.cells.get(xb, yb).position * tx;
+        top * (1.0 - ty) + bottom * ty
+    }
+
+    #[allow(dead_code)]
+    fn sprinkle_random_bits(&mut self) {
+        let tx = self.rng.gen_range(0, self.width());
+        let ty = self.rng.gen_range(0, self.height());
+        self.cells.get_mut(tx, ty).position = 1.0;
+    }
+}
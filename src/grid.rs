@@ -0,0 +1,120 @@
+//! A flat 2D grid.
+
+/// A `width` by `height` grid stored row by row in one `Vec`, with one
+/// extra cell of padding all the way around the outside.  Anything
+/// that looks at a cell's neighbors can then read straight through the
+/// padding without bounds checks or special cases for the edges;
+/// whoever owns the grid decides what goes in the padding.
+///
+/// Coordinates passed to `get()` and friends are for the interior,
+/// so (0, 0) is the top-left real cell.
+#[derive(Debug, Clone)]
+pub struct Grid<T> {
+    width: usize,
+    height: usize,
+    data: Vec<T>,
+}
+
+impl<T: Copy> Grid<T> {
+    pub fn new(width: usize, height: usize, fill: T) -> Self {
+        Grid {
+            width: width,
+            height: height,
+            data: vec![fill; (width + 2) * (height + 2)],
+        }
+    }
+
+    pub fn width(&self) -> usize {
+        self.width
+    }
+
+    pub fn height(&self) -> usize {
+        self.height
+    }
+
+    /// Distance between one row and the next in the underlying storage.
+    pub fn stride(&self) -> usize {
+        self.width + 2
+    }
+
+    /// Index into `data()` of the interior cell (x, y).
+    #[inline]
+    pub fn index(&self, x: usize, y: usize) -> usize {
+        (y + 1) * self.stride() + (x + 1)
+    }
+
+    #[inline]
+    pub fn get(&self, x: usize, y: usize) -> T {
+        self.data[self.index(x, y)]
+    }
+
+    #[inline]
+    pub fn get_mut(&mut self, x: usize, y: usize) -> &mut T {
+        let i = self.index(x, y);
+        &mut self.data[i]
+    }
+
+    #[inline]
+    pub fn set(&mut self, x: usize, y: usize, val: T) {
+        *self.get_mut(x, y) = val;
+    }
+
+    /// Whether the signed coordinates are inside the interior.
+    pub fn contains(&self, x: i32, y: i32) -> bool {
+        x >= 0 && y >= 0 && (x as usize) < self.width && (y as usize) < self.height
+    }
+
+    /// The whole backing store, padding included.
+    pub fn data(&self) -> &[T] {
+        &self.data
+    }
+
+    pub fn data_mut(&mut self) -> &mut [T] {
+        &mut self.data
+    }
+
+    /// Fills the padding by copying from the interior: each padding cell
+    /// gets the value of the interior cell `source(x, y)` picks for it,
+    /// where x and y run from -1 to width or height inclusive.
+    pub fn fill_padding<F>(&mut self, source: F)
+        where F: Fn(i32, i32) -> (usize, usize)
+    {
+        let w = self.width as i32;
+        let h = self.height as i32;
+        for x in -1..w + 1 {
+            self.fill_pad_cell(x, -1, &source);
+            self.fill_pad_cell(x, h, &source);
+        }
+        for y in 0..h {
+            self.fill_pad_cell(-1, y, &source);
+            self.fill_pad_cell(w, y, &source);
+        }
+    }
+
+    fn fill_pad_cell<F>(&mut self, x: i32, y: i32, source: &F)
+        where F: Fn(i32, i32) -> (usize, usize)
+    {
+        let (sx, sy) = source(x, y);
+        let val = self.get(sx, sy);
+        let i = ((y + 1) as usize) * self.stride() + ((x + 1) as usize);
+        self.data[i] = val;
+    }
+
+    /// Sets every padding cell to the same value.
+    pub fn set_padding(&mut self, val: T) {
+        let w = self.width as i32;
+        let h = self.height as i32;
+        let stride = self.stride();
+        let mut set = |x: i32, y: i32| {
+            self.data[((y + 1) as usize) * stride + ((x + 1) as usize)] = val;
+        };
+        for x in -1..w + 1 {
+            set(x, -1);
+            set(x, h);
+        }
+        for y in 0..h {
+            set(-1, y);
+            set(w, y);
+        }
+    }
+}
@@ -0,0 +1,118 @@
+//! Reading the water under a ship.
+
+use na::Vector2;
+
+use field::{Field, FIELD_CELL_SIZE, screen_to_field};
+
+/// The patch of water a ship's hull sits on: a rectangle
+/// in field coordinates, turned to the ship's bearing.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct Footprint {
+    /// Middle of the hull, in field cells.
+    pub centre: Vector2<f32>,
+    /// Bow to stern, in field cells.
+    pub length: f32,
+    /// Side to side, in field cells.
+    pub width: f32,
+    /// Same as `Ship::bearing`: 0 is pointing up the screen,
+    /// increasing clockwise.
+    pub bearing: f32,
+}
+
+impl Footprint {
+    /// Takes the centre and size in screen pixels, like a `Ship` has them.
+    pub fn from_screen(location: Vector2<f32>, length: f32, width: f32, bearing: f32) -> Self {
+        let (x, y) = screen_to_field(location.x, location.y);
+        let cell = FIELD_CELL_SIZE as f32;
+        Footprint {
+            centre: Vector2::new(x, y),
+            length: length / cell,
+            width: width / cell,
+            bearing: bearing,
+        }
+    }
+
+    /// Unit vector pointing out the bow.
+    pub fn forward(&self) -> Vector2<f32> {
+        Vector2::new(self.bearing.sin(), -self.bearing.cos())
+    }
+
+    /// Unit vector pointing out the starboard (right) side.
+    pub fn starboard(&self) -> Vector2<f32> {
+        Vector2::new(self.bearing.cos(), self.bearing.sin())
+    }
+}
+
+/// What the water is doing under a hull.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct HullSample {
+    pub max: f32,
+    pub min: f32,
+    pub mean: f32,
+    /// Which way the water surface slopes up, in height per field cell,
+    /// in field coordinates.
+    pub slope: Vector2<f32>,
+    /// How much higher the water is per cell towards the bow.
+    pub pitch_slope: f32,
+    /// How much higher the water is per cell towards starboard.
+    pub roll_slope: f32,
+}
+
+// Samples are taken at most this many cells apart.
+const SAMPLE_SPACING: f32 = 1.0;
+
+/// Evenly spaced offsets from -size/2 to size/2, symmetric around 0.
+fn sample_offsets(size: f32) -> Vec<f32> {
+    let steps = f32::max((size / SAMPLE_SPACING).ceil(), 1.0) as usize;
+    (0..steps + 1)
+        .map(|i| (i as f32 / steps as f32 - 0.5) * size)
+        .collect()
+}
+
+impl Field {
+    /// Samples the water under a footprint.  Any of it hanging off
+    /// the edge of the field reads the nearest cell at the edge.
+    ///
+    /// The slope is a least-squares fit of a plane through the samples;
+    /// since they're laid out symmetrically along the hull, the bow-ward
+    /// and starboard-ward slopes can be fit separately.
+    pub fn sample_hull(&self, footprint: &Footprint) -> HullSample {
+        let forward = footprint.forward();
+        let starboard = footprint.starboard();
+        let along = sample_offsets(footprint.length);
+        let across = sample_offsets(footprint.width);
+
+        let mut max = ::std::f32::MIN;
+        let mut min = ::std::f32::MAX;
+        let mut sum = 0.0;
+        let mut sum_along = 0.0;
+        let mut sum_along2 = 0.0;
+        let mut sum_across = 0.0;
+        let mut sum_across2 = 0.0;
+        for &s in &along {
+            for &t in &across {
+                let p = footprint.centre + forward * s + starboard * t;
+                let h = self.height_at(p.x, p.y);
+                max = f32::max(max, h);
+                min = f32::min(min, h);
+                sum += h;
+                sum_along += h * s;
+                sum_along2 += s * s;
+                sum_across += h * t;
+                sum_across2 += t * t;
+            }
+        }
+
+        let count = (along.len() * across.len()) as f32;
+        let pitch_slope = if sum_along2 > 0.0 { sum_along / sum_along2 } else { 0.0 };
+        let roll_slope = if sum_across2 > 0.0 { sum_across / sum_across2 } else { 0.0 };
+        HullSample {
+            max: max,
+            min: min,
+            mean: sum / count,
+            slope: forward * pitch_slope + starboard * roll_slope,
+            pitch_slope: pitch_slope,
+            roll_slope: roll_slope,
+        }
+    }
+}
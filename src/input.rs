@@ -0,0 +1,432 @@
+//! Which keys and gamepad buttons do what, loaded from a TOML file
+//! so people can set them up however suits their hands and keyboard.
+//!
+//! Keys are named the way SDL names them ("W", "Space", "Left",
+//! "Keypad 8"), gamepad buttons likewise ("a", "start", "dpleft"), and
+//! gamepad sticks by axis and direction ("leftx-", "righty+").
+//!
+//! ```toml
+//! [[keyboard]]
+//! thrust = ["W"]
+//! turn_left = ["A"]
+//! turn_right = ["D"]
+//! jump = ["S"]
+//! reset = ["Space"]
+//! pause = ["Escape"]
+//!
+//! [gamepad]
+//! thrust = ["a", "righttrigger+"]
+//! turn_left = ["dpleft", "leftx-"]
+//! # ...
+//! ```
+//!
+//! There's one `[[keyboard]]` section per keyboard layout, in order,
+//! and one `[gamepad]` section that every gamepad uses.  Sticks and
+//! triggers bound to thrust and turning steer proportionally; how far
+//! they have to move before they count is set at the top of the file:
+//!
+//! ```toml
+//! stick_dead_zone = 0.25
+//! trigger_dead_zone = 0.1
+//! ```
+//!
+//! Files that bind the same key or button to two things, or that have
+//! actions or gamepad buttons nobody's heard of, are refused.
+
+use std::error::Error;
+use std::fmt;
+use std::fs::File;
+use std::io::{self, Read, Write};
+use std::path::Path;
+use std::str::FromStr;
+
+use toml;
+
+/// Where the game looks for key bindings by default.  If it's not
+/// there the defaults are used, and it gets written when the
+/// bindings are changed in game.
+pub const DEFAULT_INPUT_FILE: &str = "input.toml";
+
+/// Something a player can do.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
+pub enum Action {
+    Thrust,
+    TurnLeft,
+    TurnRight,
+    Jump,
+    Reset,
+    Pause,
+}
+
+impl Action {
+    pub fn all() -> [Action; 6] {
+        [Action::Thrust,
+         Action::TurnLeft,
+         Action::TurnRight,
+         Action::Jump,
+         Action::Reset,
+         Action::Pause]
+    }
+
+    /// For showing on screen.
+    pub fn name(&self) -> &'static str {
+        match *self {
+            Action::Thrust => "Thrust",
+            Action::TurnLeft => "Turn left",
+            Action::TurnRight => "Turn right",
+            Action::Jump => "Jump",
+            Action::Reset => "Reset",
+            Action::Pause => "Pause",
+        }
+    }
+}
+
+/// Which keys or buttons do each action, for one keyboard layout
+/// or for gamepads.  Any of the inputs listed for an action does it.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Bindings {
+    pub thrust: Vec<String>,
+    pub turn_left: Vec<String>,
+    pub turn_right: Vec<String>,
+    pub jump: Vec<String>,
+    pub reset: Vec<String>,
+    pub pause: Vec<String>,
+}
+
+fn names(names: &[&str]) -> Vec<String> {
+    names.iter().map(|s| s.to_string()).collect()
+}
+
+impl Bindings {
+    fn new(thrust: &[&str],
+           turn_left: &[&str],
+           turn_right: &[&str],
+           jump: &[&str],
+           reset: &[&str],
+           pause: &[&str])
+           -> Self {
+        Bindings {
+            thrust: names(thrust),
+            turn_left: names(turn_left),
+            turn_right: names(turn_right),
+            jump: names(jump),
+            reset: names(reset),
+            pause: names(pause),
+        }
+    }
+
+    pub fn get(&self, action: Action) -> &[String] {
+        match action {
+            Action::Thrust => &self.thrust,
+            Action::TurnLeft => &self.turn_left,
+            Action::TurnRight => &self.turn_right,
+            Action::Jump => &self.jump,
+            Action::Reset => &self.reset,
+            Action::Pause => &self.pause,
+        }
+    }
+
+    fn get_mut(&mut self, action: Action) -> &mut Vec<String> {
+        match action {
+            Action::Thrust => &mut self.thrust,
+            Action::TurnLeft => &mut self.turn_left,
+            Action::TurnRight => &mut self.turn_right,
+            Action::Jump => &mut self.jump,
+            Action::Reset => &mut self.reset,
+            Action::Pause => &mut self.pause,
+        }
+    }
+
+    /// What the named key or button does here, if anything.
+    pub fn action_for(&self, input: &str) -> Option<Action> {
+        Action::all().iter().cloned().find(|&a| self.get(a).iter().any(|i| i == input))
+    }
+
+    fn unbind(&mut self, input: &str) {
+        for &action in &Action::all() {
+            self.get_mut(action).retain(|i| i != input);
+        }
+    }
+
+    fn inputs(&self) -> Vec<&str> {
+        Action::all().iter().flat_map(|&a| self.get(a)).map(|i| i.as_str()).collect()
+    }
+}
+
+/// One set of bindings in an `InputMap`.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum Device {
+    /// A keyboard layout, numbered from 0.
+    Keyboard(usize),
+    Gamepad,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct InputMap {
+    /// How far a stick has to move off centre before it does anything,
+    /// from 0 to 1.  Worn sticks that drift need more.
+    pub stick_dead_zone: f32,
+    /// The same for triggers, which rest at one end rather than the middle.
+    pub trigger_dead_zone: f32,
+    pub keyboard: Vec<Bindings>,
+    pub gamepad: Bindings,
+}
+
+/// Four people can share a keyboard: WASD, IJKL, the arrow keys,
+/// and the number pad.
+impl Default for InputMap {
+    fn default() -> Self {
+        InputMap {
+            stick_dead_zone: 0.25,
+            trigger_dead_zone: 0.1,
+            keyboard: vec![Bindings::new(&["W"], &["A"], &["D"], &["S"], &["Space"], &["Escape"]),
+                           Bindings::new(&["I"], &["J"], &["L"], &["K"], &[], &[]),
+                           Bindings::new(&["Up"], &["Left"], &["Right"], &["Down"], &[], &[]),
+                           Bindings::new(&["Keypad 8"],
+                                         &["Keypad 4"],
+                                         &["Keypad 6"],
+                                         &["Keypad 5"],
+                                         &[],
+                                         &[])],
+            gamepad: Bindings::new(&["a", "righttrigger+"],
+                                   &["dpleft", "leftx-"],
+                                   &["dpright", "leftx+"],
+                                   &["b"],
+                                   &["back"],
+                                   &["start"]),
+        }
+    }
+}
+
+/// What SDL calls gamepad buttons and axes.
+const PAD_BUTTONS: [&str; 15] = ["a",
+                                 "b",
+                                 "x",
+                                 "y",
+                                 "back",
+                                 "guide",
+                                 "start",
+                                 "leftstick",
+                                 "rightstick",
+                                 "leftshoulder",
+                                 "rightshoulder",
+                                 "dpup",
+                                 "dpdown",
+                                 "dpleft",
+                                 "dpright"];
+const PAD_AXES: [&str; 6] = ["leftx",
+                             "lefty",
+                             "rightx",
+                             "righty",
+                             "lefttrigger",
+                             "righttrigger"];
+
+/// The name a gamepad stick or trigger gets in bindings,
+/// for pushing it in one direction.
+pub fn axis_name(axis: &str, positive: bool) -> String {
+    format!("{}{}", axis, if positive { "+" } else { "-" })
+}
+
+/// Splits a binding like "leftx-" into the axis name and whether it's
+/// the positive direction, or returns `None` for a button.
+pub fn split_axis_name(name: &str) -> Option<(&str, bool)> {
+    if name.ends_with('+') {
+        Some((&name[..name.len() - 1], true))
+    } else if name.ends_with('-') {
+        Some((&name[..name.len() - 1], false))
+    } else {
+        None
+    }
+}
+
+/// How far a stick or trigger is pushed in one direction, from 0 to 1,
+/// with the dead zone cut out and the rest stretched to fill the range.
+pub fn axis_strength(value: i16, positive: bool, dead_zone: f32) -> f32 {
+    let v = value as f32 / 32767.0;
+    let v = if positive { v } else { -v };
+    if v <= dead_zone || dead_zone >= 1.0 {
+        0.0
+    } else {
+        f32::min((v - dead_zone) / (1.0 - dead_zone), 1.0)
+    }
+}
+
+#[derive(Debug)]
+pub enum InputMapError {
+    Io(io::Error),
+    Parse(toml::de::Error),
+    Save(toml::ser::Error),
+    /// Parsed, but doesn't make sense.
+    Invalid(String),
+}
+
+impl fmt::Display for InputMapError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            InputMapError::Io(ref e) => write!(f, "could not read or write bindings: {}", e),
+            InputMapError::Parse(ref e) => write!(f, "could not parse bindings: {}", e),
+            InputMapError::Save(ref e) => write!(f, "could not save bindings: {}", e),
+            InputMapError::Invalid(ref why) => write!(f, "bad bindings: {}", why),
+        }
+    }
+}
+
+impl Error for InputMapError {
+    fn description(&self) -> &str {
+        match *self {
+            InputMapError::Io(_) => "could not read or write bindings",
+            InputMapError::Parse(_) => "could not parse bindings",
+            InputMapError::Save(_) => "could not save bindings",
+            InputMapError::Invalid(_) => "bad bindings",
+        }
+    }
+}
+
+impl From<io::Error> for InputMapError {
+    fn from(e: io::Error) -> Self {
+        InputMapError::Io(e)
+    }
+}
+
+impl From<toml::de::Error> for InputMapError {
+    fn from(e: toml::de::Error) -> Self {
+        InputMapError::Parse(e)
+    }
+}
+
+impl From<toml::ser::Error> for InputMapError {
+    fn from(e: toml::ser::Error) -> Self {
+        InputMapError::Save(e)
+    }
+}
+
+fn first_repeat<'a>(inputs: &[&'a str]) -> Option<&'a str> {
+    inputs.iter()
+        .enumerate()
+        .find(|&(i, input)| inputs[..i].contains(input))
+        .map(|(_, &input)| input)
+}
+
+impl FromStr for InputMap {
+    type Err = InputMapError;
+
+    fn from_str(s: &str) -> Result<InputMap, InputMapError> {
+        let map: InputMap = toml::from_str(s)?;
+        map.check()?;
+        Ok(map)
+    }
+}
+
+impl InputMap {
+    /// Makes sure nothing is bound twice, every gamepad input is one SDL
+    /// knows about, and the dead zones leave some room to move.
+    pub fn check(&self) -> Result<(), InputMapError> {
+        let invalid = |why: String| Err(InputMapError::Invalid(why));
+        for &(name, zone) in &[("stick_dead_zone", self.stick_dead_zone),
+                               ("trigger_dead_zone", self.trigger_dead_zone)] {
+            if !(zone >= 0.0 && zone < 1.0) {
+                return invalid(format!("{} has to be from 0 up to 1, not {}", name, zone));
+            }
+        }
+
+        // The layouts share one keyboard, so a key can only be in one.
+        let keys: Vec<&str> = self.keyboard.iter().flat_map(|b| b.inputs()).collect();
+        if let Some(key) = first_repeat(&keys) {
+            return invalid(format!("key {} is bound more than once", key));
+        }
+
+        let pad = self.gamepad.inputs();
+        if let Some(input) = first_repeat(&pad) {
+            return invalid(format!("gamepad {} is bound more than once", input));
+        }
+        for input in pad {
+            let known = match split_axis_name(input) {
+                Some((axis, _)) => PAD_AXES.contains(&axis),
+                None => PAD_BUTTONS.contains(&input),
+            };
+            if !known {
+                return invalid(format!("there's no gamepad button or axis called {}", input));
+            }
+        }
+        Ok(())
+    }
+
+    pub fn load<P: AsRef<Path>>(path: P) -> Result<InputMap, InputMapError> {
+        let mut s = String::new();
+        File::open(path)?.read_to_string(&mut s)?;
+        InputMap::from_str(&s)
+    }
+
+    pub fn save<P: AsRef<Path>>(&self, path: P) -> Result<(), InputMapError> {
+        let s = toml::to_string(self)?;
+        File::create(path)?.write_all(s.as_bytes())?;
+        Ok(())
+    }
+
+    /// The bindings for a device.  Keyboard layouts past the end
+    /// of the file have nothing bound.
+    pub fn bindings(&self, device: Device) -> Option<&Bindings> {
+        match device {
+            Device::Keyboard(layout) => self.keyboard.get(layout),
+            Device::Gamepad => Some(&self.gamepad),
+        }
+    }
+
+    /// Which keyboard layout the named key belongs to and what it does.
+    pub fn key_action(&self, key: &str) -> Option<(usize, Action)> {
+        self.keyboard
+            .iter()
+            .enumerate()
+            .filter_map(|(layout, b)| b.action_for(key).map(|a| (layout, a)))
+            .next()
+    }
+
+    /// The dead zone for the named axis.
+    pub fn dead_zone(&self, axis: &str) -> f32 {
+        if axis.contains("trigger") {
+            self.trigger_dead_zone
+        } else {
+            self.stick_dead_zone
+        }
+    }
+
+    /// What the named gamepad button or stick direction does.
+    pub fn pad_action(&self, input: &str) -> Option<Action> {
+        self.gamepad.action_for(input)
+    }
+
+    /// Makes `input` the one and only thing doing `action` on `device`.
+    /// Whatever it used to do, it doesn't any more; on the keyboard
+    /// that goes for every layout, since they share the keys.
+    pub fn bind(&mut self, device: Device, action: Action, input: &str) {
+        let bindings = match device {
+            Device::Keyboard(layout) => {
+                for b in &mut self.keyboard {
+                    b.unbind(input);
+                }
+                while self.keyboard.len() <= layout {
+                    self.keyboard.push(Bindings::default());
+                }
+                &mut self.keyboard[layout]
+            }
+            Device::Gamepad => {
+                self.gamepad.unbind(input);
+                &mut self.gamepad
+            }
+        };
+        *bindings.get_mut(action) = vec![input.to_string()];
+    }
+
+    /// Leaves nothing doing `action` on `device`.
+    pub fn clear(&mut self, device: Device, action: Action) {
+        let bindings = match device {
+            Device::Keyboard(layout) => self.keyboard.get_mut(layout),
+            Device::Gamepad => Some(&mut self.gamepad),
+        };
+        if let Some(bindings) = bindings {
+            bindings.get_mut(action).clear();
+        }
+    }
+}
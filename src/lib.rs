@@ -0,0 +1,61 @@
+//! The simulation core of Flipwrecked.
+//!
+//! Everything in here runs without a window or a `ggez::Context`,
+//! so it can be driven by the game in `main.rs` or by anything
+//! else that wants to run matches headless.
+
+extern crate rand;
+extern crate nalgebra as na;
+extern crate serde;
+#[macro_use]
+extern crate serde_derive;
+extern crate toml;
+#[cfg(feature = "parallel")]
+extern crate rayon;
+
+use rand::{SeedableRng, XorShiftRng};
+
+use std::time::Duration;
+
+pub mod bot;
+pub mod collision;
+pub mod field;
+pub mod grid;
+pub mod input;
+pub mod hull;
+pub mod map;
+pub mod net;
+pub mod params;
+pub mod player;
+pub mod replay;
+pub mod series;
+pub mod snapshot;
+pub mod ship;
+pub mod splash;
+pub mod world;
+
+// The arena is the size of the (default) ggez window.
+pub const WINDOW_HEIGHT: usize = 600;
+pub const WINDOW_WIDTH: usize = 800;
+
+/// The simulation always steps at this rate, however fast
+/// or slow the frames are being drawn.
+pub const TICKS_PER_SECOND: u32 = 60;
+
+pub fn tick_duration() -> Duration {
+    Duration::new(0, 1_000_000_000 / TICKS_PER_SECOND)
+}
+
+/// Makes the RNG used by the simulation.  The same seed always
+/// gives the same sequence, so matches can be reproduced.
+pub fn seeded_rng(seed: u64) -> XorShiftRng {
+    // XorShift panics on an all-zero seed, so the last two words
+    // are fixed nonzero values.
+    let lo = seed as u32;
+    let hi = (seed >> 32) as u32;
+    XorShiftRng::from_seed([lo, hi, 0x9E37_79B9, 0x7F4A_7C15])
+}
+
+pub fn clamp(val: f32, lower: f32, upper: f32) -> f32 {
+    f32::min(f32::max(val, lower), upper)
+}
@@ -1,6 +1,7 @@
 extern crate ggez;
-extern crate rand;
-extern crate nalgebra as na;
+extern crate sdl2;
+extern crate sdl2_mixer;
+extern crate flipwrecked;
 
 // extern crate ggez_goodies;
 
@@ -13,44 +14,54 @@ use ggez::graphics;
 use ggez::graphics::Color;
 use ggez::graphics::Drawable;
 
-use std::vec;
-use std::time::Duration;
-use std::boxed::Box;
-use std::cmp::{min, max};
+use sdl2::GameControllerSubsystem;
+use sdl2::controller::GameController;
 
-mod ship;
-use ship::Ship;
-use ship::Buttons;
+use std::env;
+use std::fs::File;
+use std::io::Write;
+use std::net::UdpSocket;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
 
+use flipwrecked::field::{Boundary, Field, FIELD_CELL_SIZE, DEFAULT_DEPTH, MAX_DEPTH, MAX_DAMPING,
+                         ROCK, field_to_screen_coords, screen_to_field, screen_to_field_coords};
+use flipwrecked::ship::{Ship, SHIP_SIZE};
+use flipwrecked::bot::{Bot, Difficulty};
+use flipwrecked::player::{Control, Player, Spawn, assign_controls, default_players,
+                          MIN_PLAYERS, MAX_PLAYERS};
+use flipwrecked::map::{Map, DAMPING_STEP, level_depth};
+use flipwrecked::input::{InputMap, Action, Device, axis_name, split_axis_name, axis_strength,
+                         DEFAULT_INPUT_FILE};
+use flipwrecked::net::{NetSession, NET_PLAYERS};
+use flipwrecked::series::{Series, SeriesFormat};
+use flipwrecked::replay::{Input, Replay, ReplayWriter, STEER_SCALE, steer_amount};
+use flipwrecked::world::{World, Outcome, landing_splash};
+use flipwrecked::params::{WaveParams, ParamsWatcher, Preset, DEFAULT_PARAMS_FILE};
+use flipwrecked::{tick_duration, TICKS_PER_SECOND};
 
-// SDL2 drawing on Windows appears to be *way*
-// slower than on Linux or Mac.  Ick.
-#[cfg(target_os = "windows")]
-const FIELD_WIDTH: usize = 40;
-#[cfg(target_os = "windows")]
-const FIELD_HEIGHT: usize = 30;
-#[cfg(target_os = "windows")]
-const FIELD_CELL_SIZE: u32 = 20;
+mod scene;
+use scene::{Scene, SceneStack, Transition};
 
+const RAD_TO_DEGREES: f32 = 180.0 / std::f32::consts::PI;
 
-#[cfg(not(target_os = "windows"))]
-const FIELD_WIDTH: usize = 80;
-#[cfg(not(target_os = "windows"))]
-const FIELD_HEIGHT: usize = 60;
-#[cfg(not(target_os = "windows"))]
-const FIELD_CELL_SIZE: u32 = 10;
+// Every match gets recorded here unless `--record` says otherwise.
+const DEFAULT_REPLAY_FILE: &str = "last_match.replay";
 
-fn screen_to_field_coords(x: u32, y: u32) -> (usize, usize) {
-    let xn = (x / FIELD_CELL_SIZE) as usize;
-    let yn = (y / FIELD_CELL_SIZE) as usize;
-    (xn, yn)
-}
+// F5 saves the state of the match here, as well as keeping it to go
+// back to with F9, so it can be attached to a bug report.
+const SNAPSHOT_FILE: &str = "snapshot.bin";
 
-fn field_to_screen_coords(x: usize, y: usize) -> (i32, i32) {
-    let xn = (x as u32) * FIELD_CELL_SIZE;
-    let yn = (y as u32) * FIELD_CELL_SIZE;
-    (xn as i32, yn as i32)
-}
+// Where the map editor saves a map that didn't come from a file.
+const DEFAULT_MAP_FILE: &str = "resources/maps/custom.toml";
+
+// The loudest SDL_mixer plays music, and how many steps the volume
+// setting goes up in to get there.
+const MAX_MUSIC_VOLUME: i32 = 128;
+const VOLUME_STEPS: i32 = 10;
+
+// If drawing falls way behind, don't try to catch up all at once;
+// the game just runs slow instead of locking up.
+const MAX_TICKS_PER_FRAME: u32 = 5;
 
 
 fn interp_between_square(t: f64, v1: Color, v2: Color) -> Color {
@@ -71,10 +82,6 @@ fn interp_between_square(t: f64, v1: Color, v2: Color) -> Color {
     Color::RGBA(rr as u8, rg as u8, rb as u8, ra as u8)
 }
 
-fn clamp(val: f32, lower: f32, upper: f32) -> f32 {
-    f32::min(f32::max(val, lower), upper)
-}
-
 // Fields values are 0 to +1
 // Color values are 0-255
 // We'll do negative = red and positive = blue
@@ -89,13 +96,38 @@ fn field_to_color(val: f32) -> Color {
     }
 }
 
+// Rocks, and the bit of the window past the edge of a small arena.
+const ROCK_COLOUR: (u8, u8, u8) = (110, 100, 80);
+
+// Kelp beds, at their thickest.
+const KELP_COLOUR: (u8, u8, u8) = (90, 150, 70);
+
+// What the wave tiles get tinted by, for water of a given depth and
+// damping: sandy in the shallows, darker out in deep water, greener the
+// thicker the kelp, and as they are otherwise.
+fn seabed_tint(depth: f32, damping: f32) -> Color {
+    let (towards, t) = if depth < DEFAULT_DEPTH {
+        ((210, 230, 160), 1.0 - depth / DEFAULT_DEPTH)
+    } else {
+        ((110, 130, 200), (depth - DEFAULT_DEPTH) / (MAX_DEPTH - DEFAULT_DEPTH))
+    };
+    let kelp = damping / MAX_DAMPING;
+    let blend = |c: u8, k: u8| {
+        let c = 255.0 + (c as f32 - 255.0) * t;
+        (c + (k as f32 - c) * kelp) as u8
+    };
+    let (r, g, b) = towards;
+    let (kr, kg, kb) = KELP_COLOUR;
+    Color::RGBA(blend(r, kr), blend(g, kg), blend(b, kb), 255)
+}
+
 struct WaveImages {
     image: graphics::Image,
     layers: Vec<graphics::Rect>,
+    // The depth and damping the image is tinted for right now.
+    tinted: (f32, f32),
 }
 
-const FLIP_THRESHOLD: f32 = 0.1;
-
 impl WaveImages {
     fn new(ctx: &mut ggez::Context) -> Self {
         let img = graphics::Image::new(ctx, "ocean_tiles.png").unwrap();
@@ -106,17 +138,25 @@ impl WaveImages {
         WaveImages {
             image: img,
             layers: layers,
+            tinted: (DEFAULT_DEPTH, 0.0),
         }
     }
 
-    fn draw_images(&mut self, ctx: &mut ggez::Context, rect: graphics::Rect, height: f32) {
-        // let c = field_to_color(height);
-        // self.image.set_color_mod(c);
-        let img = if height < -FLIP_THRESHOLD {
+    fn draw_images(&mut self,
+                   ctx: &mut ggez::Context,
+                   rect: graphics::Rect,
+                   height: f32,
+                   flip_threshold: f32,
+                   seabed: (f32, f32)) {
+        if seabed != self.tinted {
+            self.image.set_color_mod(seabed_tint(seabed.0, seabed.1));
+            self.tinted = seabed;
+        }
+        let img = if height < -flip_threshold {
             self.layers[0]
         } else if height <= 0.0 {
             self.layers[1]
-        } else if height <= FLIP_THRESHOLD {
+        } else if height <= flip_threshold {
             self.layers[2]
         } else {
             self.layers[3]
@@ -126,476 +166,1922 @@ impl WaveImages {
     }
 }
 
-#[derive(Copy, Clone, Debug)]
-struct WaveType {
-    velocity: f32,
-    position: f32,
-}
+fn draw_field(ctx: &mut ggez::Context,
+              field: &Field,
+              waves: &mut WaveImages)
+              -> GameResult<()> {
+    let (rr, rg, rb) = ROCK_COLOUR;
+    let rock = Color::RGBA(rr, rg, rb, 255);
+    for x in 0..field.width() {
+        for y in 0..field.height() {
+            let (xi, yi) = field_to_screen_coords(x, y);
+            let r = graphics::Rect::new(xi, yi, FIELD_CELL_SIZE, FIELD_CELL_SIZE);
+            let color = if field.is_rock(x, y) {
+                rock
+            } else {
+                field_to_color(field.cell(x, y).position)
+            };
+            graphics::set_color(ctx, color);
+            // Wow actually putting a ? at the end of this takes us
+            // from 325 to 275 fps.  Wacky.
+            let _ = graphics::rectangle(ctx, graphics::DrawMode::Fill, r);
+
+            // let color = waves.draw_images(ctx, r, self.0[x][y].position);
+        }
+    }
 
-impl WaveType {
-    fn new(position: f32) -> Self {
-        WaveType {
-            velocity: 0.0,
-            position: position,
+    for x in 0..field.width() {
+        for y in 0..field.height() {
+            if field.is_rock(x, y) {
+                continue;
+            }
+            let (xi, yi) = field_to_screen_coords(x, y);
+            let r = graphics::Rect::new(xi, yi, FIELD_CELL_SIZE, FIELD_CELL_SIZE);
+            // let color = field_to_color(self.0[x][y].position);
+            // graphics::set_color(ctx, color);
+            // graphics::rectangle(ctx, graphics::DrawMode::Fill, r);
+
+            waves.draw_images(ctx,
+                              r,
+                              field.cell(x, y).position,
+                              field.params.flip_threshold,
+                              (field.depth(x, y), field.damping(x, y)));
         }
     }
 
-    fn restoring_force(&self) -> f32 {
-        // Position is correct but maybe
-        // velocity plays better?
-        -self.position * 0.05
-        //-self.velocity * 0.002
+    // Land all around a small arena.
+    let (w, h) = field_to_screen_coords(field.width(), field.height());
+    let window_w = flipwrecked::WINDOW_WIDTH as u32;
+    let window_h = flipwrecked::WINDOW_HEIGHT as u32;
+    graphics::set_color(ctx, rock);
+    if (w as u32) < window_w {
+        let r = graphics::Rect::new(w, 0, window_w - w as u32, window_h);
+        graphics::rectangle(ctx, graphics::DrawMode::Fill, r)?;
     }
+    if (h as u32) < window_h {
+        let r = graphics::Rect::new(0, h, w as u32, window_h - h as u32);
+        graphics::rectangle(ctx, graphics::DrawMode::Fill, r)?;
+    }
+    Ok(())
+}
+
+/// The images for drawing one ship.
+struct ShipSprite {
+    image: graphics::Image,
+    angle_image: graphics::Image,
+    sideways_image: graphics::Image,
 }
 
-impl Default for WaveType {
-    fn default() -> Self {
-        // If you make these 0 the optimizer/OS won't
-        // actually allocate space for the arrays it needs
-        // until the game is running, I suspect.
-        // So it gets laggy for the first few seconds.
-        // With a slight offset it APPEARS to MOSTLY fix the problem.
-        WaveType {
-            velocity: 0.001,
-            position: 0.001,
+impl ShipSprite {
+    fn new(ctx: &mut ggez::Context, filename: &str) -> Self {
+        ShipSprite {
+            image: graphics::Image::new(ctx, format!("{}.png", filename)).unwrap(),
+            angle_image: graphics::Image::new(ctx, format!("{}_tipping.png", filename)).unwrap(),
+            sideways_image: graphics::Image::new(ctx, format!("{}_sideways.png", filename))
+                .unwrap(),
         }
     }
+
+    /// Loads the sprite for player number `player`.  There's only art
+    /// for two ships, so everybody after that gets one of those tinted
+    /// to their own colour.
+    fn for_player(ctx: &mut ggez::Context, player: usize, colour: (u8, u8, u8)) -> Self {
+        let mut sprite = ShipSprite::new(ctx, &format!("ship{}", player % 2 + 1));
+        if player >= 2 {
+            let (r, g, b) = colour;
+            let tint = Color::RGB(r, g, b);
+            sprite.image.set_color_mod(tint);
+            sprite.angle_image.set_color_mod(tint);
+            sprite.sideways_image.set_color_mod(tint);
+        }
+        sprite
+    }
+
+    fn draw(&mut self, ctx: &mut ggez::Context, ship: &Ship) -> GameResult<()> {
+        let x = ship.location.x;
+        let y = ship.location.y;
+        self.draw_at(ctx, ship, x, y, SHIP_SIZE * ship.scale())
+    }
+
+    fn draw_at(&mut self,
+               ctx: &mut ggez::Context,
+               ship: &Ship,
+               x: f32,
+               y: f32,
+               size: f32)
+               -> GameResult<()> {
+        let half_size = size / 2.0;
+        let r = graphics::Rect::new((x - half_size) as i32,
+                                    (y - half_size) as i32,
+                                    size as u32,
+                                    size as u32);
+        let angle = (ship.bearing * RAD_TO_DEGREES) as f64;
+
+        if ship.flipped {
+            self.sideways_image.draw_ex(ctx, None, Some(r), angle, None, false, false)?;
+        } else if ship.is_tipping() {
+            self.angle_image.draw_ex(ctx, None, Some(r), angle, None, false, false)?;
+        } else {
+            self.image.draw_ex(ctx, None, Some(r), angle, None, false, false)?;
+        }
+
+        Ok(())
+    }
 }
 
-struct Field(Vec<Vec<WaveType>>);
+// On the bindings screen, sticks and triggers count as pushed
+// once they're this far over.
+const AXIS_THRESHOLD: i16 = 16384;
+
+// Buttons or sticks bound to jump and the like go off past this.
+const PAD_PRESS_THRESHOLD: f32 = 0.5;
+
+/// How hard a gamepad is doing an action, from 0 to 1: the most of
+/// any of the buttons and stick directions bound to it.
+fn pad_strength(pad: &GameController, map: &InputMap, action: Action) -> f32 {
+    map.gamepad
+        .get(action)
+        .iter()
+        .map(|name| match split_axis_name(name) {
+            Some((axis, positive)) => {
+                match Axis::from_string(axis) {
+                    Some(a) => axis_strength(pad.axis(a), positive, map.dead_zone(axis)),
+                    None => 0.0,
+                }
+            }
+            None => {
+                match Button::from_string(name) {
+                    Some(b) if pad.button(b) => 1.0,
+                    _ => 0.0,
+                }
+            }
+        })
+        .fold(0.0, f32::max)
+}
 
-impl Field {
-    fn new() -> Self {
-        let mut field = Vec::with_capacity(FIELD_WIDTH);
-        for _i in 0..FIELD_WIDTH {
-            let mut bit = Vec::with_capacity(FIELD_HEIGHT);
-            bit.resize(FIELD_HEIGHT, WaveType::default());
-            field.push(bit);
+/// What one gamepad was doing last time it was read.
+#[derive(Debug, Clone, Default)]
+struct PadState {
+    thrust: i16,
+    turn: i16,
+    held: Vec<Action>,
+}
+
+#[derive(Debug, Copy, Clone, PartialEq)]
+enum PadEvent {
+    Steer(i16, i16),
+    Pressed(Action),
+}
+
+/// Every gamepad plugged in, read directly so each one can steer its
+/// own ship; the ggez controller events don't say which pad they're from.
+struct Gamepads {
+    subsystem: GameControllerSubsystem,
+    pads: Vec<GameController>,
+    states: Vec<PadState>,
+}
+
+impl Gamepads {
+    fn new(ctx: &mut ggez::Context) -> Option<Self> {
+        match ctx.sdl_context.game_controller() {
+            Ok(subsystem) => {
+                let mut pads = Gamepads {
+                    subsystem: subsystem,
+                    pads: Vec::new(),
+                    states: Vec::new(),
+                };
+                pads.refresh();
+                Some(pads)
+            }
+            Err(e) => {
+                println!("No gamepad support: {}", e);
+                None
+            }
         }
-        Field(field)
     }
 
-    fn draw(&mut self, ctx: &mut ggez::Context, waves: &mut WaveImages) -> GameResult<()> {
-        for x in 0..FIELD_WIDTH {
-            for y in 0..FIELD_HEIGHT {
-                let (xi, yi) = field_to_screen_coords(x, y);
-                let r = graphics::Rect::new(xi, yi, FIELD_CELL_SIZE, FIELD_CELL_SIZE);
-                let color = field_to_color(self.0[x][y].position);
-                graphics::set_color(ctx, color);
-                // Wow actually putting a ? at the end of this takes us
-                // from 325 to 275 fps.  Wacky.
-                let _ = graphics::rectangle(ctx, graphics::DrawMode::Fill, r);
+    fn len(&self) -> usize {
+        self.pads.len()
+    }
 
-                // let color = waves.draw_images(ctx, r, self.0[x][y].position);
+    /// Opens any gamepads that were plugged in and forgets any that
+    /// were unplugged.  Returns true if anything changed, in which case
+    /// the pads may have been renumbered.
+    fn refresh(&mut self) -> bool {
+        let count = self.subsystem.num_joysticks().unwrap_or(0);
+        let available = (0..count).filter(|&i| self.subsystem.is_game_controller(i)).count();
+        if available == self.pads.len() && self.pads.iter().all(|p| p.attached()) {
+            return false;
+        }
+        self.pads.clear();
+        for i in 0..count {
+            if !self.subsystem.is_game_controller(i) {
+                continue;
+            }
+            match self.subsystem.open(i) {
+                Ok(pad) => {
+                    println!("Gamepad {}: {}", self.pads.len() + 1, pad.name());
+                    self.pads.push(pad);
+                }
+                Err(e) => println!("Could not open gamepad: {}", e),
             }
         }
+        self.states = vec![PadState::default(); self.pads.len()];
+        true
+    }
 
-        for x in 0..FIELD_WIDTH {
-            for y in 0..FIELD_HEIGHT {
-                let (xi, yi) = field_to_screen_coords(x, y);
-                let r = graphics::Rect::new(xi, yi, FIELD_CELL_SIZE, FIELD_CELL_SIZE);
-                // let color = field_to_color(self.0[x][y].position);
-                // graphics::set_color(ctx, color);
-                // graphics::rectangle(ctx, graphics::DrawMode::Fill, r);
+    /// Forgets what every pad was doing, so whatever they're doing
+    /// next time they're read counts as new.
+    fn forget(&mut self) {
+        self.states = vec![PadState::default(); self.pads.len()];
+    }
 
-                waves.draw_images(ctx, r, self.0[x][y].position);
+    /// Reads every pad, and returns the steering of any whose sticks
+    /// moved and any actions that were just pressed, by pad number.
+    fn poll(&mut self, map: &InputMap) -> Vec<(usize, PadEvent)> {
+        let mut events = Vec::new();
+        for (i, (pad, state)) in self.pads.iter().zip(&mut self.states).enumerate() {
+            let strength = |action| pad_strength(pad, map, action);
+            let thrust = (strength(Action::Thrust) * STEER_SCALE).round() as i16;
+            let turn = ((strength(Action::TurnRight) - strength(Action::TurnLeft)) * STEER_SCALE)
+                .round() as i16;
+            if thrust != state.thrust || turn != state.turn {
+                state.thrust = thrust;
+                state.turn = turn;
+                events.push((i, PadEvent::Steer(thrust, turn)));
             }
+            for &action in &[Action::Jump, Action::Reset, Action::Pause] {
+                let down = strength(action) > PAD_PRESS_THRESHOLD;
+                let was_down = state.held.contains(&action);
+                if down && !was_down {
+                    state.held.push(action);
+                    events.push((i, PadEvent::Pressed(action)));
+                } else if !down && was_down {
+                    state.held.retain(|&a| a != action);
+                }
+            }
+        }
+        events
+    }
+}
+
+fn draw_text(ctx: &mut ggez::Context,
+             font: &graphics::Font,
+             text: &str,
+             x: i32,
+             y: i32)
+             -> GameResult<()> {
+    let mut t = graphics::Text::new(ctx, text, font)?;
+    let r = graphics::Rect::new(x, y, t.width(), t.height());
+    t.draw(ctx, None, Some(r))
+}
+
+
+/// The first key bound to `action` on the first keyboard layout,
+/// for telling people what to press.
+fn key_hint(map: &InputMap, action: Action) -> String {
+    map.bindings(Device::Keyboard(0))
+        .and_then(|b| b.get(action).first().cloned())
+        .unwrap_or(String::from(action.name()))
+}
+
+/// Draws the dark box menus and overlays sit in.
+fn draw_panel(ctx: &mut ggez::Context) -> GameResult<()> {
+    graphics::set_color(ctx, Color::RGBA(0, 0, 0, 200));
+    let r = graphics::Rect::new(100, 100, 600, 400);
+    graphics::rectangle(ctx, graphics::DrawMode::Fill, r)?;
+    graphics::set_color(ctx, Color::RGBA(255, 255, 255, 255));
+    Ok(())
+}
+
+/// What a key or button press means on a menu.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+enum MenuInput {
+    Up,
+    Down,
+    Left,
+    Right,
+    Select,
+    Back,
+}
+
+impl MenuInput {
+    fn from_key(keycode: Keycode) -> Option<MenuInput> {
+        match keycode {
+            Keycode::Up => Some(MenuInput::Up),
+            Keycode::Down => Some(MenuInput::Down),
+            Keycode::Left => Some(MenuInput::Left),
+            Keycode::Right => Some(MenuInput::Right),
+            Keycode::Return | Keycode::Space => Some(MenuInput::Select),
+            Keycode::Escape | Keycode::Backspace => Some(MenuInput::Back),
+            _ => None,
+        }
+    }
+
+    fn from_button(btn: Button) -> Option<MenuInput> {
+        match btn {
+            Button::DPadUp => Some(MenuInput::Up),
+            Button::DPadDown => Some(MenuInput::Down),
+            Button::DPadLeft => Some(MenuInput::Left),
+            Button::DPadRight => Some(MenuInput::Right),
+            Button::A | Button::Start => Some(MenuInput::Select),
+            Button::B | Button::Back => Some(MenuInput::Back),
+            _ => None,
+        }
+    }
+}
+
+/// A list of choices with one picked out, moved through with up and down.
+struct Menu {
+    selected: usize,
+}
+
+impl Menu {
+    fn new() -> Self {
+        Menu { selected: 0 }
+    }
+
+    /// Moves the selection for up and down; anything else is handed back.
+    fn input(&mut self, input: MenuInput, items: usize) -> Option<MenuInput> {
+        match input {
+            MenuInput::Up => self.selected = (self.selected + items - 1) % items,
+            MenuInput::Down => self.selected = (self.selected + 1) % items,
+            _ => return Some(input),
+        }
+        None
+    }
+
+    fn draw(&self,
+            ctx: &mut ggez::Context,
+            font: &graphics::Font,
+            title: &str,
+            items: &[String])
+            -> GameResult<()> {
+        draw_panel(ctx)?;
+        draw_text(ctx, font, title, 130, 120)?;
+        for (i, item) in items.iter().enumerate() {
+            let marker = if i == self.selected { ">" } else { " " };
+            draw_text(ctx, font, &format!("{} {}", marker, item), 130, 180 + 40 * i as i32)?;
         }
         Ok(())
     }
+}
 
-    fn update(&mut self) {
-        // self.sprinkle_random_bits();
-        self.propegate();
-        self.decay();
-    }
-
-    fn decay(&mut self) {
-        // Decay intensity.
-        // Setting this to 0.98 makes the wave go forever,
-        // setting it to 0.97 makes it just kind of go plonk.
-        // At least with a surface tension of 3.0.
-        let decay_factor = 0.99;
-        for x in 0..FIELD_WIDTH {
-            for y in 0..FIELD_HEIGHT {
-                // let val = self.0[x][y].position * decay_factor;
-                // self.0[x][y].position = val;
-                // Decaying position vs. velocity doesn't seem
-                // to have made much difference
-                self.0[x][y].velocity *= decay_factor;
-                self.0[x][y].position *= decay_factor;
-
-                // We might just want to zero this out if it goes below a certain point.
-                // if f32::abs(self.0[x][y].velocity) < 0.001 {
-                //     self.0[x][y].velocity = 0.0;
-                // }
-                // if f32::abs(self.0[x][y].position) < 0.001 {
-                //     self.0[x][y].position = 0.0;
-                // }
-            }
-        }
-    }
-
-    // This gets the difference between a poitn and one of its neighbors.
-    //
-    fn relative_position(&self, x: i32, y: i32, dx: i32, dy: i32) -> f32 {
-        let pos = self.0[x as usize][y as usize].position;
-        if x == 0 && dx < 0 {
-            0.0
-        } else if x == (FIELD_WIDTH as i32) - 1 && dx > 0 {
-            0.0
-        } else if y == 0 && dy < 0 {
-            0.0
-        } else if y == (FIELD_HEIGHT as i32) - 1 && dy > 0 {
-            0.0
-        } else {
-            self.0[(x + dx) as usize][(y + dy) as usize].position - pos
-
-        }
-    }
-
-    fn propegate(&mut self) {
-        let dt = 0.01;
-        let sqrt2 = std::f32::consts::SQRT_2;
-        // How strongly each cell is affected by its neighbors.
-        // Higher numbers mean weaker.
-        let surface_tension = 4.0;
-        for x in 0..FIELD_WIDTH {
-            for y in 0..FIELD_HEIGHT {
-                let mut val = self.0[x][y];
-                let ix = x as i32;
-                let iy = y as i32;
-
-                val.position += val.velocity * dt;
-                // val.position = clamp(val.position, -1.0, 1.0);
-                // total force = restoring force plus a force based on the
-                // sum of differences in position  between itself and its
-                // neighbors
-                // We can add divisors or multipliers based on the position
-                // to mess with the "speed of sound", kinda, or at least make
-                // anisotropic substances.  Sweet!
-                let neighbor_force = self.relative_position(ix, iy, 0, -1) +
-                                     self.relative_position(ix, iy, 0, 1) +
-                                     self.relative_position(ix, iy, -1, 0) +
-                                     self.relative_position(ix, iy, 1, 0) +
-                                     self.relative_position(ix, iy, -1, -1) / sqrt2 +
-                                     self.relative_position(ix, iy, 1, -1) / sqrt2 +
-                                     self.relative_position(ix, iy, -1, 1) / sqrt2 +
-                                     self.relative_position(ix, iy, 1, 1) / sqrt2;
-                let forces = val.restoring_force() + neighbor_force / surface_tension;
-                val.velocity += forces;
-                val.velocity = clamp(val.velocity, -1.0, 1.0);
-
-                // println!("{:?}", val);
-                self.0[x][y] = val;
-            }
-        }
-    }
-
-    // Creates a square disturbance in the field, setting all positions
-    // inside it to the given force.
-    // Eventually should add the values, not set them.
-    // Maybe should set velocity rather than position?
-    fn create_splash(&mut self, x: usize, y: usize, radius: usize, force: f32) {
-        let max_x = min(x + radius, FIELD_WIDTH);
-        let min_x = max(x - radius, 0);
-        let max_y = min(y + radius, FIELD_HEIGHT);
-        let min_y = max(y - radius, 0);
-        // println!("{}:{}, {}:{}", min_x, max_x, min_y, max_y);
-        for x in min_x..max_x {
-            for y in min_y..max_y {
-                // println!("Setting cell {},{} to force {}", x, y, force);
-                // Setting position vs. velocity doesn't appear to make
-                // much difference.
-                // ...okay, the position makes bigger waves.
-                // self.0[x][y].position = force;
-                self.0[x][y].position += force;
-            }
-        }
-    }
-
-    pub fn read_strength(&self, x: i32, y: i32) -> f32 {
-        let x = x as u32;
-        let y = y as u32;
-        self.0[x as usize][y as usize].position
-        // f32::abs(self.0[x as usize][y as usize].position)
-    }
-
-    pub fn read_strength_area(&self, x: i32, y: i32) -> (f32, f32) {
-        let radius = 2;
-        let x = x as u32;
-        let y = y as u32;
-        let mut max = 0.0;
-        let mut min = 0.0;
-        for xi in (x - radius)..(x + radius) {
-            for yi in (y - radius)..(y + radius) {
-                let value = self.0[x as usize][y as usize].position;
-                max = f32::max(value, max);
-                min = f32::min(value, min);
-            }
-        }
-        (max, min)
-        // f32::abs(self.0[x as usize][y as usize].position)
-    }
-
-    #[allow(dead_code)]
-    fn sprinkle_random_bits(&mut self) {
-        let tx = rand::random::<usize>() % FIELD_WIDTH;
-        let ty = rand::random::<usize>() % FIELD_HEIGHT;
-        self.0[tx][ty].position = 1.0;
-    }
-}
-
-
-// The ndarray crate would be nice here.
-struct MainState {
-    field: Field,
-    player1: Ship,
-    player2: Ship,
-    frame: usize,
-    wave_images: WaveImages,
-    player1_wins_image: graphics::Image,
-    player2_wins_image: graphics::Image,
-    nobody_wins_image: graphics::Image,
-    reset: bool,
+/// How the next match will be set up.
+#[derive(Debug, Copy, Clone)]
+struct MatchSettings {
+    /// Every match uses this seed if it's set, otherwise the clock.
+    seed: Option<u64>,
+    players: usize,
+    /// How many of the players are bots; they get the last ships.
+    bots: usize,
+    difficulty: Difficulty,
+    series: SeriesFormat,
 }
 
-impl MainState {
+impl MatchSettings {
+    /// The players for a match, with the bots and people given their
+    /// ships, and people given keyboard layouts or `pads` gamepads.
+    fn players(&self, pads: usize) -> Vec<Player> {
+        let mut players = default_players(self.players);
+        for player in &mut players[self.players - self.bots..] {
+            player.control = Control::Bot(self.difficulty);
+        }
+        assign_controls(&mut players, pads);
+        players
+    }
+
+    fn seed(&self) -> u64 {
+        self.seed.unwrap_or_else(|| {
+            SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .map(|d| d.as_secs())
+                .unwrap_or(0)
+        })
+    }
+}
+
+/// What a match should do when whatever was laid over it goes away.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+enum MatchCommand {
+    /// Put everybody back at the start for the next round.
+    NextRound,
+    /// Start the series over from nothing each.
+    Restart,
+    /// Go back to whatever the match was started from.
+    Leave,
+}
+
+/// Everything that lasts longer than one scene.
+struct Shared {
+    font: graphics::Font,
+    win_images: WinImages,
+    input_map: InputMap,
+    input_file: String,
+    gamepads: Option<Gamepads>,
+    // What the params file says; `preset` is played on top of it.
+    file_params: WaveParams,
+    params_watcher: ParamsWatcher,
+    preset: Preset,
+    // Music volume, from 0 to VOLUME_STEPS.
+    volume: i32,
+    settings: MatchSettings,
+    // The arena matches are played on, and the file it came from.
+    map: Map,
+    map_file: Option<String>,
+    // Where matches get recorded, if anywhere.
+    record_file: Option<String>,
+    // Left by a scene over a match for the match to pick up.
+    match_command: Option<MatchCommand>,
+}
+
+impl Shared {
+    fn pad_count(&self) -> usize {
+        self.gamepads.as_ref().map_or(0, |p| p.len())
+    }
+
+    fn save_bindings(&self) {
+        match self.input_map.save(&self.input_file) {
+            Ok(()) => println!("Saved bindings to {}", self.input_file),
+            Err(e) => println!("{}: {}", self.input_file, e),
+        }
+    }
+
+    /// The wave params matches should be using right now, on this map.
+    fn params(&self) -> WaveParams {
+        self.map.params(self.preset.apply(self.file_params))
+    }
+
+    /// Picks up any edits to the wave params file.
+    fn reload_params(&mut self) {
+        match self.params_watcher.poll() {
+            Some(Ok(params)) => {
+                println!("Reloaded {}", self.params_watcher.path().display());
+                self.file_params = params;
+            }
+            Some(Err(e)) => {
+                println!("Keeping old params, {}: {}",
+                         self.params_watcher.path().display(),
+                         e)
+            }
+            None => (),
+        }
+    }
+
+    /// Makes sure the next match fits on the map.
+    fn fit_settings_to_map(&mut self) {
+        let settings = &mut self.settings;
+        settings.players = usize::min(settings.players, self.map.max_players());
+        settings.bots = usize::min(settings.bots, settings.players);
+    }
+
+    fn set_volume(&mut self, volume: i32) {
+        self.volume = volume.max(0).min(VOLUME_STEPS);
+        sdl2_mixer::Music::set_volume((MAX_MUSIC_VOLUME * self.volume / VOLUME_STEPS) as _);
+    }
+}
+
+/// The pictures for who won.  There are only proper victory screens
+/// for the first two players.
+struct WinImages {
+    player1: graphics::Image,
+    player2: graphics::Image,
+    nobody: graphics::Image,
+}
+
+impl WinImages {
     fn new(ctx: &mut ggez::Context) -> Self {
-        let f = Field::new();
-        let wi = WaveImages::new(ctx);
-        let player1_wins_image = graphics::Image::new(ctx, "ship1_wins.png").unwrap();
-        let player2_wins_image = graphics::Image::new(ctx, "ship2_wins.png").unwrap();
-        let nobody_wins_image = graphics::Image::new(ctx, "nobody_wins.png").unwrap();
-        let mut player1 = Ship::new(100 as i32, 100 as i32, ctx, "ship1");
-        player1.bearing = 3.14159;
-        let player2 = Ship::new(600 as i32, 400 as i32, ctx, "ship2");
-        MainState {
-            field: f,
-            frame: 0,
-            wave_images: wi,
-            player1: player1,
-            player2: player2,
-            player1_wins_image: player1_wins_image,
-            player2_wins_image: player2_wins_image,
-            nobody_wins_image: nobody_wins_image,
-            reset: false,
+        WinImages {
+            player1: graphics::Image::new(ctx, "ship1_wins.png").unwrap(),
+            player2: graphics::Image::new(ctx, "ship2_wins.png").unwrap(),
+            nobody: graphics::Image::new(ctx, "nobody_wins.png").unwrap(),
         }
     }
+}
 
-    fn reset(&mut self, ctx: &mut ggez::Context) {
-        self.field = Field::new();
-        self.player1 = Ship::new(100 as i32, 100 as i32, ctx, "ship1");
-        self.player1.bearing = 3.14159;
-        self.player2 = Ship::new(600 as i32, 400 as i32, ctx, "ship2");
-        self.reset = false;
+/// Draws the round and everybody's score along a line at `y`.
+fn draw_scoreboard(ctx: &mut ggez::Context,
+                   font: &graphics::Font,
+                   series: &Series,
+                   colours: &[(u8, u8, u8)],
+                   y: i32)
+                   -> GameResult<()> {
+    graphics::set_color(ctx, Color::RGBA(0, 0, 0, 160));
+    let r = graphics::Rect::new(0, y - 5, flipwrecked::WINDOW_WIDTH as u32, 35);
+    graphics::rectangle(ctx, graphics::DrawMode::Fill, r)?;
+    graphics::set_color(ctx, Color::RGBA(255, 255, 255, 255));
+    let round = format!("Round {}, {}", series.round(), series.format.name());
+    draw_text(ctx, font, &round, 10, y)?;
+    for (i, (&score, &(r, g, b))) in series.scores.iter().zip(colours).enumerate() {
+        let x = 300 + 62 * i as i32;
+        graphics::set_color(ctx, Color::RGBA(r, g, b, 255));
+        graphics::rectangle(ctx,
+                            graphics::DrawMode::Fill,
+                            graphics::Rect::new(x, y + 4, 16, 16))?;
+        graphics::set_color(ctx, Color::RGBA(255, 255, 255, 255));
+        draw_text(ctx, font, &format!("{}", score), x + 22, y)?;
     }
+    Ok(())
+}
 
-    fn calculate_flips(&mut self) {
-        let ship_location1 = self.player1.location;
-        let wave_location1 = screen_to_field_coords(ship_location1.x as u32,
-                                                    ship_location1.y as u32);
-        let (wave_strength1, _) = self.field
-            .read_strength_area(wave_location1.0 as i32, wave_location1.1 as i32);
-        if wave_strength1 > FLIP_THRESHOLD && !self.player1.jumping {
-            self.player1.flip();
+/// Who won, laid over the end of a round.  At the end of a series,
+/// it's who won the series instead.
+struct ResultsScene {
+    // What the picture is of.
+    outcome: Outcome,
+    // Anybody without a victory screen gets their ship drawn big
+    // on a band of their colour.
+    winner: Option<(ShipSprite, (u8, u8, u8))>,
+    // The score so far, if there's more than one round.
+    series: Option<Series>,
+    colours: Vec<(u8, u8, u8)>,
+    command: Option<MatchCommand>,
+}
+
+impl ResultsScene {
+    fn new(ctx: &mut ggez::Context,
+           world: &World,
+           outcome: Outcome,
+           series: Option<Series>)
+           -> Self {
+        let outcome = match series.as_ref().and_then(|s| s.winner()) {
+            Some(player) => Outcome::Winner(player),
+            None => outcome,
+        };
+        let winner = match outcome {
+            Outcome::Winner(n) if n >= 2 => {
+                let colour = world.players[n].colour;
+                Some((ShipSprite::for_player(ctx, n, colour), colour))
+            }
+            _ => None,
+        };
+        ResultsScene {
+            outcome: outcome,
+            winner: winner,
+            series: series,
+            colours: world.players.iter().map(|p| p.colour).collect(),
+            command: None,
+        }
+    }
+
+    fn series_over(&self) -> bool {
+        self.series.as_ref().map_or(true, |s| s.is_over())
+    }
+
+    fn action(&mut self, action: Option<Action>) {
+        match action {
+            Some(Action::Reset) if self.series_over() => {
+                self.command = Some(MatchCommand::Restart)
+            }
+            Some(Action::Reset) => self.command = Some(MatchCommand::NextRound),
+            Some(Action::Pause) => self.command = Some(MatchCommand::Leave),
+            _ => (),
+        }
+    }
+}
+
+impl Scene<Shared> for ResultsScene {
+    fn update(&mut self,
+              _ctx: &mut ggez::Context,
+              shared: &mut Shared,
+              _dt: Duration)
+              -> GameResult<Transition<Shared>> {
+        if let Some(command) = self.command.take() {
+            shared.match_command = Some(command);
+            return Ok(Transition::Pop);
         }
+        Ok(Transition::None)
+    }
 
-        let ship_location2 = self.player2.location;
-        let wave_location2 = screen_to_field_coords(ship_location2.x as u32,
-                                                    ship_location2.y as u32);
-        // println!("Location 1: {:?}, location 2: {:?}",
-        //          wave_location1,
-        //          wave_location2);
-        let (_, wave_strength2) = self.field
-            .read_strength_area(wave_location2.0 as i32, wave_location2.1 as i32);
-        if wave_strength2 < -FLIP_THRESHOLD && !self.player2.jumping {
-            self.player2.flip();
+    fn draw(&mut self, ctx: &mut ggez::Context, shared: &mut Shared) -> GameResult<()> {
+        let images = &mut shared.win_images;
+        match self.outcome {
+            Outcome::Draw => images.nobody.draw(ctx, None, None)?,
+            Outcome::Winner(0) => images.player1.draw(ctx, None, None)?,
+            Outcome::Winner(1) => images.player2.draw(ctx, None, None)?,
+            Outcome::Winner(_) => {
+                if let Some((ref mut sprite, (r, g, b))) = self.winner {
+                    graphics::set_color(ctx, Color::RGBA(r, g, b, 200));
+                    let band = graphics::Rect::new(0, 200, flipwrecked::WINDOW_WIDTH as u32, 200);
+                    graphics::rectangle(ctx, graphics::DrawMode::Fill, band)?;
+                    let x = flipwrecked::WINDOW_WIDTH as f32 / 2.0;
+                    let y = flipwrecked::WINDOW_HEIGHT as f32 / 2.0;
+                    let ship = Ship::new(0, 0);
+                    sprite.draw_at(ctx, &ship, x, y, SHIP_SIZE * 1.5)?;
+                }
+            }
+        }
+        if let Some(ref series) = self.series {
+            draw_scoreboard(ctx, &shared.font, series, &self.colours, 10)?;
+            if let Some(player) = series.winner() {
+                let line = format!("Player {} wins the series!", player + 1);
+                draw_text(ctx, &shared.font, &line, 10, 50)?;
+            }
         }
+        let again = if self.series_over() { "again" } else { "next round" };
+        let hint = format!("{}: {}   {}: menu",
+                           key_hint(&shared.input_map, Action::Reset),
+                           again,
+                           key_hint(&shared.input_map, Action::Pause));
+        graphics::set_color(ctx, Color::RGBA(255, 255, 255, 255));
+        draw_text(ctx, &shared.font, &hint, 20, 560)
+    }
 
-        println!("Flipped? {} {}", self.player1.flipped, self.player2.flipped);
+    fn draws_below(&self) -> bool {
+        true
+    }
 
-        println!("wave_strength1 = {:+} wave_strength2 = {:+}",
-                 wave_strength1,
-                 wave_strength2);
+    fn key_down(&mut self, shared: &mut Shared, keycode: Keycode, repeat: bool) {
+        if !repeat {
+            let action = shared.input_map.key_action(&keycode.name()).map(|(_, a)| a);
+            self.action(action);
+        }
+    }
+
+    fn pad_button(&mut self, shared: &mut Shared, btn: Button) {
+        let action = shared.input_map.pad_action(&btn.string());
+        self.action(action);
     }
 }
 
+/// Laid over a match to stop the clock, with a few things that can be
+/// changed without leaving it.
+struct PauseScene {
+    menu: Menu,
+    chosen: Option<MenuInput>,
+}
 
-impl game::EventHandler for MainState {
-    fn update(&mut self, ctx: &mut ggez::Context, dt: Duration) -> GameResult<()> {
-        if self.reset {
-            self.reset(ctx);
+impl PauseScene {
+    fn new() -> Self {
+        PauseScene {
+            menu: Menu::new(),
+            chosen: None,
         }
+    }
 
-        // Add a wake as the ship moves
-        let p1_field_location = screen_to_field_coords(self.player1.location.x as u32,
-                                                       self.player1.location.y as u32);
+    fn items(shared: &Shared) -> Vec<String> {
+        vec![String::from("Resume"),
+             String::from("Restart"),
+             format!("Music volume: < {} >", shared.volume),
+             format!("Waves: < {} >", shared.preset.name()),
+             String::from("Leave match"),
+             String::from("Quit to title")]
+    }
 
+    fn input(&mut self, shared: &mut Shared, input: MenuInput) {
+        let input = match self.menu.input(input, PauseScene::items(shared).len()) {
+            Some(input) => input,
+            None => return,
+        };
+        let step = match input {
+            MenuInput::Left => -1,
+            MenuInput::Right => 1,
+            _ => {
+                self.chosen = Some(input);
+                return;
+            }
+        };
+        match self.menu.selected {
+            2 => {
+                let volume = shared.volume + step;
+                shared.set_volume(volume);
+            }
+            3 => {
+                let presets = Preset::all();
+                let current = presets.iter().position(|&p| p == shared.preset).unwrap_or(0) as i32;
+                let next = (current + step).max(0).min(presets.len() as i32 - 1);
+                shared.preset = presets[next as usize];
+            }
+            _ => (),
+        }
+    }
+}
 
-        let p2_field_location = screen_to_field_coords(self.player2.location.x as u32,
-                                                       self.player2.location.y as u32);
-        let (sx1, sy1) = p1_field_location;
-        let (sx2, sy2) = p2_field_location;
+impl Scene<Shared> for PauseScene {
+    fn update(&mut self,
+              ctx: &mut ggez::Context,
+              shared: &mut Shared,
+              _dt: Duration)
+              -> GameResult<Transition<Shared>> {
+        Ok(match self.chosen.take() {
+            Some(MenuInput::Select) => {
+                match self.menu.selected {
+                    0 => Transition::Pop,
+                    1 => {
+                        shared.match_command = Some(MatchCommand::Restart);
+                        Transition::Pop
+                    }
+                    4 => {
+                        shared.match_command = Some(MatchCommand::Leave);
+                        Transition::Pop
+                    }
+                    5 => Transition::ReplaceAll(Box::new(TitleScreen::new(ctx))),
+                    _ => Transition::None,
+                }
+            }
+            Some(MenuInput::Back) => Transition::Pop,
+            _ => Transition::None,
+        })
+    }
 
-        self.field.update();
-        self.player1.update();
-        self.player2.update();
-        self.calculate_flips();
+    fn draw(&mut self, ctx: &mut ggez::Context, shared: &mut Shared) -> GameResult<()> {
+        let items = PauseScene::items(shared);
+        self.menu.draw(ctx, &shared.font, "Paused", &items)
+    }
 
-        if self.player1.post_jump == 30 {
-            // create splash from landing
-            // println!("Splashing down");
-            self.field.create_splash(sx1, sy1, 6, -1.0);
-        } else if !self.player1.jumping {
-            // create wake
-            self.field.create_splash(sx1, sy1, 1, -0.01);
+    fn draws_below(&self) -> bool {
+        true
+    }
+
+    fn key_down(&mut self, shared: &mut Shared, keycode: Keycode, repeat: bool) {
+        if repeat {
+            return;
+        }
+        // Whatever paused the game unpauses it too.
+        if shared.input_map.key_action(&keycode.name()).map(|(_, a)| a) == Some(Action::Pause) {
+            self.chosen = Some(MenuInput::Back);
+        } else if let Some(input) = MenuInput::from_key(keycode) {
+            self.input(shared, input);
         }
+    }
 
-        if self.player2.post_jump == 30 {
-            self.field.create_splash(sx2, sy2, 6, 1.0);
-        } else if !self.player2.jumping {
-            self.field.create_splash(sx2, sy2, 1, 0.01);
+    fn pad_button(&mut self, shared: &mut Shared, btn: Button) {
+        if shared.input_map.pad_action(&btn.string()) == Some(Action::Pause) {
+            self.chosen = Some(MenuInput::Back);
+        } else if let Some(input) = MenuInput::from_button(btn) {
+            self.input(shared, input);
         }
+    }
+}
 
-        if self.frame % 100 == 0 {
-            let time = ggez::timer::get_time_since_start(ctx).as_secs();
-            println!("Time {}s Frame {}, FPS: {}",
-                     time,
-                     self.frame,
-                     ggez::timer::get_fps(ctx));
+/// The screen for changing key and gamepad bindings.  Left and right
+/// pick a keyboard layout or the gamepad, up and down pick an action,
+/// Return waits for the new key or button, and Backspace unbinds it.
+struct RebindScene {
+    device: usize,
+    row: usize,
+    waiting: bool,
+    done: bool,
+    // Who's using what, to show next to each device.
+    controls: Vec<Control>,
+}
+
+impl RebindScene {
+    fn new(controls: Vec<Control>) -> Self {
+        RebindScene {
+            device: 0,
+            row: 0,
+            waiting: false,
+            done: false,
+            controls: controls,
         }
+    }
 
-        // Shipwave
-        // println!("Wave at ship {}", self.field.read_strength(self.ship.location.x as i32,
-        //    self.ship.location.y as i32));
+    // Every keyboard layout there could be a player on, then the gamepad.
+    fn devices(map: &InputMap) -> Vec<Device> {
+        let layouts = usize::max(map.keyboard.len(), 4);
+        let mut devices: Vec<Device> = (0..layouts).map(Device::Keyboard).collect();
+        devices.push(Device::Gamepad);
+        devices
+    }
 
+    fn bind(&mut self, map: &mut InputMap, device: Device, input: &str) {
+        let devices = RebindScene::devices(map);
+        if self.waiting && devices[self.device] == device {
+            map.bind(device, Action::all()[self.row], input);
+            self.waiting = false;
+        }
+    }
+}
 
-        self.frame += 1;
-        // println!("Frame {}, FPS: {}", self.frame, ggez::timer::get_fps(ctx));
+impl Scene<Shared> for RebindScene {
+    fn update(&mut self,
+              _ctx: &mut ggez::Context,
+              shared: &mut Shared,
+              _dt: Duration)
+              -> GameResult<Transition<Shared>> {
+        if self.done {
+            shared.save_bindings();
+            return Ok(Transition::Pop);
+        }
+        Ok(Transition::None)
+    }
 
-        Ok(())
+    fn draw(&mut self, ctx: &mut ggez::Context, shared: &mut Shared) -> GameResult<()> {
+        let font = &shared.font;
+        let map = &shared.input_map;
+        draw_panel(ctx)?;
+
+        let device = RebindScene::devices(map)[self.device];
+        let (title, control) = match device {
+            Device::Keyboard(n) => (format!("Keyboard layout {}", n + 1), Control::Keyboard(n)),
+            Device::Gamepad => (String::from("Gamepads"), Control::Gamepad(0)),
+        };
+        let users: Vec<String> = self.controls
+            .iter()
+            .enumerate()
+            .filter(|&(_, &c)| match (c, control) {
+                (Control::Gamepad(_), Control::Gamepad(_)) => true,
+                (a, b) => a == b,
+            })
+            .map(|(i, _)| format!("{}", i + 1))
+            .collect();
+        let title = if users.is_empty() {
+            format!("< {} >", title)
+        } else {
+            format!("< {} (player {}) >", title, users.join(", "))
+        };
+        draw_text(ctx, font, &title, 130, 120)?;
+
+        for (i, &action) in Action::all().iter().enumerate() {
+            let bound = match map.bindings(device) {
+                Some(b) if !b.get(action).is_empty() => b.get(action).join(", "),
+                _ => String::from("-"),
+            };
+            let bound = if i == self.row && self.waiting {
+                String::from("press something...")
+            } else {
+                bound
+            };
+            let marker = if i == self.row { ">" } else { " " };
+            let line = format!("{} {}: {}", marker, action.name(), bound);
+            draw_text(ctx, font, &line, 130, 180 + 40 * i as i32)?;
+        }
+        draw_text(ctx,
+                  font,
+                  "Return: rebind   Backspace: unbind   Esc: done",
+                  130,
+                  450)
+    }
+
+    fn draws_below(&self) -> bool {
+        true
+    }
+
+    fn key_down(&mut self, shared: &mut Shared, keycode: Keycode, repeat: bool) {
+        if repeat {
+            return;
+        }
+        let map = &mut shared.input_map;
+        if self.waiting {
+            match keycode {
+                Keycode::Escape => self.waiting = false,
+                _ => {
+                    let device = RebindScene::devices(map)[self.device];
+                    // Gamepad bindings are waiting for a button, not a key.
+                    if let Device::Keyboard(_) = device {
+                        self.bind(map, device, &keycode.name());
+                    }
+                }
+            }
+            return;
+        }
+        let devices = RebindScene::devices(map).len();
+        let rows = Action::all().len();
+        match keycode {
+            Keycode::Escape | Keycode::F1 => self.done = true,
+            Keycode::Left => self.device = (self.device + devices - 1) % devices,
+            Keycode::Right => self.device = (self.device + 1) % devices,
+            Keycode::Up => self.row = (self.row + rows - 1) % rows,
+            Keycode::Down => self.row = (self.row + 1) % rows,
+            Keycode::Return => self.waiting = true,
+            Keycode::Backspace => {
+                map.clear(RebindScene::devices(map)[self.device], Action::all()[self.row])
+            }
+            _ => (),
+        }
+    }
+
+    fn pad_button(&mut self, shared: &mut Shared, btn: Button) {
+        self.bind(&mut shared.input_map, Device::Gamepad, &btn.string());
+    }
+
+    fn pad_axis(&mut self, shared: &mut Shared, axis: Axis, value: i16) {
+        if value > AXIS_THRESHOLD || value < -AXIS_THRESHOLD {
+            self.bind(&mut shared.input_map,
+                      Device::Gamepad,
+                      &axis_name(&axis.string(), value > 0));
+        }
+    }
+}
+
+
+/// Which steering keys one player is holding down.
+#[derive(Debug, Copy, Clone, Default)]
+struct HeldKeys {
+    thrust: bool,
+    left: bool,
+    right: bool,
+}
+
+impl HeldKeys {
+    /// Keys are just a stick that's always all the way over or not at all.
+    fn steer(&self, player: usize) -> Input {
+        let amount = |held: bool| if held { 1.0 } else { 0.0 };
+        Input::Steer(player,
+                     steer_amount(amount(self.thrust)),
+                     steer_amount(amount(self.right) - amount(self.left)))
+    }
+}
+
+
+struct MatchScene {
+    world: World,
+    wave_images: WaveImages,
+    sprites: Vec<ShipSprite>,
+    // Real time that has passed but not been simulated yet.
+    accumulator: Duration,
+    // How long the match has been running, not counting pauses.
+    played: Duration,
+    // Inputs waiting to be applied at the start of the next tick.
+    pending: Vec<Input>,
+    recorder: Option<ReplayWriter>,
+    // When watching a replay, the recorded inputs and how far
+    // through them we are.  Live input is ignored.
+    playback: Option<(Replay, usize)>,
+    // Keeps a network match in step with the other end, which has
+    // the other ship.  Inputs go through it instead of `pending`.
+    net: Option<NetSession>,
+    // The last save state, from F5.
+    saved_state: Option<Vec<u8>>,
+    // Steering keys held down, by player.
+    held_keys: Vec<HeldKeys>,
+    bots: Vec<Bot>,
+    series: Series,
+    // Set by key presses, for the next update to act on.
+    pause: bool,
+    rebind: bool,
+    // Whether the results are up for the round that just ended.
+    results_shown: bool,
+    // A replay keeps running after a round ends, so it shows
+    // who won itself instead of stopping for the results scene.
+    replay_results: Option<ResultsScene>,
+}
+
+impl MatchScene {
+    /// Starts a match with the current settings, plays back a replay,
+    /// or plays somebody over the network.
+    fn new(ctx: &mut ggez::Context,
+           shared: &mut Shared,
+           playback: Option<Replay>,
+           net: Option<NetSession>)
+           -> Self {
+        let (seed, settings) = match (&playback, &net) {
+            // A replay already has the bots' inputs in it.
+            (&Some(ref replay), _) => {
+                (replay.seed,
+                 MatchSettings {
+                     seed: Some(replay.seed),
+                     players: replay.players,
+                     bots: 0,
+                     difficulty: Difficulty::Normal,
+                     series: SeriesFormat::Single,
+                 })
+            }
+            (_, &Some(ref net)) => {
+                (net.seed(),
+                 MatchSettings {
+                     seed: Some(net.seed()),
+                     players: NET_PLAYERS,
+                     bots: 0,
+                     ..shared.settings
+                 })
+            }
+            _ => (shared.settings.seed(), shared.settings),
+        };
+        println!("Match seed: {}, {} players on {}",
+                 seed,
+                 settings.players,
+                 shared.map.name);
+
+        let mut players = settings.players(shared.pad_count());
+        if let Some(ref net) = net {
+            players[net.remote_player()].control = Control::Remote;
+            assign_controls(&mut players, shared.pad_count());
+        }
+        let bots = players.iter()
+            .enumerate()
+            .filter_map(|(i, p)| match p.control {
+                Control::Bot(difficulty) => Some(Bot::new(i, difficulty, seed)),
+                _ => None,
+            })
+            .collect();
+        // A replay brings the params it was recorded with, and both ends
+        // of a network match play on the ones they agreed on.
+        let params = match (&playback, &net) {
+            (&Some(Replay { params: Some(params), .. }), _) => params,
+            (_, &Some(ref net)) => net.params(),
+            _ => shared.params(),
+        };
+        let world = World::on_map(seed, params, &shared.map, players);
+        let sprites = world.players
+            .iter()
+            .enumerate()
+            .map(|(i, p)| ShipSprite::for_player(ctx, i, p.colour))
+            .collect();
+
+        let recorder = match (&playback, &net, &shared.record_file) {
+            (&None, &None, &Some(ref path)) => {
+                match ReplayWriter::create(path,
+                                           seed,
+                                           settings.players,
+                                           &shared.map,
+                                           &world.field.params) {
+                    Ok(recorder) => Some(recorder),
+                    Err(e) => {
+                        println!("Could not create replay file {}, not recording: {}", path, e);
+                        None
+                    }
+                }
+            }
+            _ => None,
+        };
+
+        MatchScene {
+            world: world,
+            wave_images: WaveImages::new(ctx),
+            sprites: sprites,
+            accumulator: Duration::new(0, 0),
+            played: Duration::new(0, 0),
+            pending: Vec::new(),
+            recorder: recorder,
+            playback: playback.map(|r| (r, 0)),
+            net: net,
+            saved_state: None,
+            held_keys: vec![HeldKeys::default(); settings.players],
+            bots: bots,
+            series: Series::new(settings.series, settings.players),
+            pause: false,
+            rebind: false,
+            results_shown: false,
+            replay_results: None,
+        }
+    }
+
+    /// Which player, if any, is steering with the given keyboard layout.
+    fn keyboard_player(&self, layout: usize) -> Option<usize> {
+        self.world.players.iter().position(|p| p.control == Control::Keyboard(layout))
+    }
+
+    fn gamepad_player(&self, pad: usize) -> Option<usize> {
+        self.world.players.iter().position(|p| p.control == Control::Gamepad(pad))
+    }
+
+    /// Does whatever `action` means for `player`, pressed or released.
+    fn do_action(&mut self, player: Option<usize>, action: Action, pressed: bool) {
+        match action {
+            Action::Thrust | Action::TurnLeft | Action::TurnRight => (),
+            Action::Jump => {
+                if let (Some(player), true) = (player, pressed) {
+                    self.push_input(Input::Jump(player));
+                }
+                return;
+            }
+            // Starting over is up to the results scene.
+            Action::Reset => return,
+            Action::Pause => {
+                if pressed {
+                    self.pause = true;
+                }
+                return;
+            }
+        }
+        if let Some(player) = player {
+            let steer = {
+                let keys = &mut self.held_keys[player];
+                match action {
+                    Action::Thrust => keys.thrust = pressed,
+                    Action::TurnLeft => keys.left = pressed,
+                    _ => keys.right = pressed,
+                }
+                keys.steer(player)
+            };
+            self.push_input(steer);
+        }
+    }
+
+    /// Reads the gamepads and turns whatever they're doing into inputs.
+    fn poll_gamepads(&mut self, shared: &mut Shared) {
+        let (events, changed) = match shared.gamepads {
+            Some(ref mut pads) => {
+                let changed = pads.refresh();
+                (pads.poll(&shared.input_map), changed)
+            }
+            None => return,
+        };
+        if changed {
+            self.release_all();
+            assign_controls(&mut self.world.players, shared.pad_count());
+        }
+        for (pad, event) in events {
+            let player = self.gamepad_player(pad);
+            match event {
+                PadEvent::Steer(thrust, turn) => {
+                    if let Some(player) = player {
+                        self.push_input(Input::Steer(player, thrust, turn));
+                    }
+                }
+                PadEvent::Pressed(action) => self.do_action(player, action, true),
+            }
+        }
+    }
+
+    /// Lets go of every key and stick, so nothing is stuck held down
+    /// after something else has had the input for a while.  Bots and
+    /// the other end of a network match keep doing whatever they were.
+    fn release_all(&mut self) {
+        for player in 0..self.world.players.len() {
+            match self.world.players[player].control {
+                Control::Bot(_) | Control::Remote => continue,
+                _ => (),
+            }
+            self.held_keys[player] = HeldKeys::default();
+            self.push_input(Input::Steer(player, 0, 0));
+        }
+    }
+
+    /// Puts everybody back at the start for another round.
+    fn next_round(&mut self) {
+        self.push_input(Input::Reset);
+        for bot in &mut self.bots {
+            bot.reset();
+        }
+        self.played = Duration::new(0, 0);
+    }
+
+    /// Starts the whole series over.
+    fn restart(&mut self) {
+        self.series.reset();
+        self.next_round();
+    }
+
+    /// Keeps the state of the match to go back to with `load_state()`,
+    /// and writes it out too.
+    fn save_state(&mut self) {
+        let snapshot = self.world.snapshot();
+        match File::create(SNAPSHOT_FILE).and_then(|mut f| f.write_all(&snapshot)) {
+            Ok(()) => println!("Saved state to {}", SNAPSHOT_FILE),
+            Err(e) => println!("Could not write {}: {}", SNAPSHOT_FILE, e),
+        }
+        self.saved_state = Some(snapshot);
     }
 
-    fn draw(&mut self, ctx: &mut ggez::Context) -> GameResult<()> {
-        graphics::clear(ctx);
+    /// Puts the match back to the last `save_state()`.  Not for replays
+    /// or network matches, which can't go back in time.
+    fn load_state(&mut self) {
+        if self.playback.is_some() || self.net.is_some() {
+            return;
+        }
+        if let Some(ref snapshot) = self.saved_state {
+            if let Err(e) = self.world.restore(snapshot) {
+                println!("Could not load state: {}", e);
+                return;
+            }
+            if self.recorder.take().is_some() {
+                println!("Went back to a saved state, recording stopped");
+            }
+        }
+    }
+
+    /// Runs one tick, or returns false if a network match has to wait
+    /// for the other end to catch up first.
+    fn step(&mut self) -> bool {
+        if let Some(ref mut net) = self.net {
+            for input in self.pending.drain(..) {
+                net.push_input(input);
+            }
+            return match net.advance(&mut self.world) {
+                Ok(ran) => ran,
+                Err(e) => {
+                    println!("Network trouble: {}", e);
+                    false
+                }
+            };
+        }
+        self.run_bots();
+        self.apply_inputs();
+        self.world.update();
+        true
+    }
+
+    /// Lets the bots decide what to do on the next tick.
+    fn run_bots(&mut self) {
+        if self.playback.is_some() {
+            return;
+        }
+        for bot in &mut self.bots {
+            self.pending.extend(bot.inputs(&self.world));
+        }
+    }
+
+    /// Queues up an input from the keyboard to happen on the next tick.
+    fn push_input(&mut self, input: Input) {
+        if self.playback.is_none() {
+            self.pending.push(input);
+        }
+    }
+
+    /// Applies whatever inputs belong to the tick that's about to run,
+    /// either from the replay being watched or from the keyboard.
+    fn apply_inputs(&mut self) {
+        let tick = self.world.frame;
+        if let Some((ref replay, ref mut pos)) = self.playback {
+            while *pos < replay.events.len() && replay.events[*pos].0 <= tick {
+                self.world.apply_input(replay.events[*pos].1);
+                *pos += 1;
+            }
+            let changed = replay.param_changes.iter().rev().find(|&&(at, _)| at <= tick);
+            if let Some(&(_, params)) = changed {
+                self.world.field.params = params;
+            }
+            return;
+        }
+
+        for input in self.pending.drain(..) {
+            let failed = match self.recorder {
+                Some(ref mut recorder) => recorder.record(tick, input).err(),
+                None => None,
+            };
+            if let Some(e) = failed {
+                println!("Could not write replay, recording stopped: {}", e);
+                self.recorder = None;
+            }
+            self.world.apply_input(input);
+        }
+    }
+}
+
+impl Scene<Shared> for MatchScene {
+    fn update(&mut self,
+              ctx: &mut ggez::Context,
+              shared: &mut Shared,
+              dt: Duration)
+              -> GameResult<Transition<Shared>> {
+        match shared.match_command.take() {
+            Some(MatchCommand::NextRound) => self.next_round(),
+            Some(MatchCommand::Restart) => self.restart(),
+            Some(MatchCommand::Leave) => return Ok(Transition::Pop),
+            None => (),
+        }
+        self.poll_gamepads(shared);
+        if self.rebind {
+            self.rebind = false;
+            let controls = self.world.players.iter().map(|p| p.control).collect();
+            return Ok(Transition::Push(Box::new(RebindScene::new(controls))));
+        }
+        if self.pause {
+            self.pause = false;
+            return Ok(Transition::Push(Box::new(PauseScene::new())));
+        }
+
+        // Over the network, only once both ends agree on it.
+        let outcome = match self.net {
+            Some(ref net) => net.confirmed_world(&self.world).outcome(),
+            None => self.world.outcome(),
+        };
+        if let Some(outcome) = outcome {
+            if self.playback.is_some() {
+                if self.replay_results.is_none() {
+                    let results = ResultsScene::new(ctx, &self.world, outcome, None);
+                    self.replay_results = Some(results);
+                }
+            } else if !self.results_shown {
+                self.results_shown = true;
+                self.series.record(outcome);
+                let series = match self.series.format {
+                    SeriesFormat::Single => None,
+                    _ => Some(self.series.clone()),
+                };
+                let results = ResultsScene::new(ctx, &self.world, outcome, series);
+                return Ok(Transition::Push(Box::new(results)));
+            }
+        } else {
+            self.results_shown = false;
+            self.replay_results = None;
+        }
+
+        let tick = tick_duration();
+        self.accumulator += dt;
+        if self.accumulator > tick * MAX_TICKS_PER_FRAME {
+            self.accumulator = tick * MAX_TICKS_PER_FRAME;
+        }
+
+        while self.accumulator >= tick {
+            // Picks up a new preset from the pause menu, or file edits.
+            // Both ends of a network match have to stick with the same,
+            // and a replay has its own.
+            let params = shared.params();
+            if self.net.is_none() && self.playback.is_none() &&
+               self.world.field.params != params {
+                self.world.field.params = params;
+                let frame = self.world.frame;
+                let failed = match self.recorder {
+                    Some(ref mut recorder) => recorder.record_params(frame, &params).err(),
+                    None => None,
+                };
+                if let Some(e) = failed {
+                    println!("Could not write replay, recording stopped: {}", e);
+                    self.recorder = None;
+                }
+            }
+            if !self.step() {
+                break;
+            }
+            self.accumulator -= tick;
+            self.played += tick;
+
+            let frame = self.world.frame;
+            if frame % (TICKS_PER_SECOND as usize) == 0 {
+                shared.reload_params();
+            }
+            if frame % 100 == 0 {
+                println!("Time {}s Frame {}, FPS: {}",
+                         self.played.as_secs(),
+                         frame,
+                         ggez::timer::get_fps(ctx));
+            }
+        }
 
+        Ok(Transition::None)
+    }
+
+    fn draw(&mut self, ctx: &mut ggez::Context, shared: &mut Shared) -> GameResult<()> {
         // Background
-        self.field.draw(ctx, &mut self.wave_images)?;
+        draw_field(ctx, &self.world.field, &mut self.wave_images)?;
 
         // Foreground
-        self.player1.draw(ctx)?;
-        self.player2.draw(ctx)?;
+        for (sprite, player) in self.sprites.iter_mut().zip(&self.world.players) {
+            sprite.draw(ctx, &player.ship)?;
+        }
 
-        if self.player1.flipped && self.player2.flipped {
-            self.nobody_wins_image.draw(ctx, None, None)?;
-        } else if self.player1.flipped {
-            self.player2_wins_image.draw(ctx, None, None)?;
-        } else if self.player2.flipped {
-            self.player1_wins_image.draw(ctx, None, None)?;
+        if self.series.format != SeriesFormat::Single && !self.results_shown {
+            let colours: Vec<(u8, u8, u8)> = self.world.players.iter().map(|p| p.colour).collect();
+            draw_scoreboard(ctx, &shared.font, &self.series, &colours, 10)?;
         }
 
-        ctx.renderer.present();
+        if let Some(ref mut results) = self.replay_results {
+            results.draw(ctx, shared)?;
+        }
         Ok(())
     }
 
-    fn key_down_event(&mut self, _keycode: Keycode, _keymod: Mod, _repeat: bool) {
-        match _keycode {
-            Keycode::W => self.player1.key_down_event(Buttons::Up),
-            Keycode::A => self.player1.key_down_event(Buttons::Left),
-            Keycode::D => self.player1.key_down_event(Buttons::Right),
-            Keycode::S => self.player1.jump(),
-
-            Keycode::I => self.player2.key_down_event(Buttons::Up),
-            Keycode::J => self.player2.key_down_event(Buttons::Left),
-            Keycode::L => self.player2.key_down_event(Buttons::Right),
-            Keycode::K => self.player2.jump(),
-            Keycode::Space => {
-                if self.player1.flipped || self.player2.flipped {
-                    self.reset = true;
+    fn covered(&mut self, shared: &mut Shared) {
+        // Whatever gets let go of while something else is on top,
+        // this won't hear about.
+        self.release_all();
+        if let Some(ref mut pads) = shared.gamepads {
+            pads.forget();
+        }
+    }
+
+    fn key_down(&mut self, shared: &mut Shared, keycode: Keycode, repeat: bool) {
+        if repeat {
+            return;
+        }
+        match keycode {
+            Keycode::F1 => self.rebind = true,
+            Keycode::F5 => self.save_state(),
+            Keycode::F9 => self.load_state(),
+            _ => {
+                if let Some((layout, action)) = shared.input_map.key_action(&keycode.name()) {
+                    let player = self.keyboard_player(layout);
+                    self.do_action(player, action, true);
                 }
             }
+        }
+    }
+
+    fn key_up(&mut self, shared: &mut Shared, keycode: Keycode) {
+        if let Some((layout, action)) = shared.input_map.key_action(&keycode.name()) {
+            let player = self.keyboard_player(layout);
+            self.do_action(player, action, false);
+        }
+    }
+}
+
+/// Play, options, or quit, over the title screen.
+struct MainMenu {
+    menu: Menu,
+    chosen: Option<MenuInput>,
+}
+
+impl MainMenu {
+    fn new() -> Self {
+        MainMenu {
+            menu: Menu::new(),
+            chosen: None,
+        }
+    }
+
+    fn items() -> Vec<String> {
+        vec![String::from("Play"),
+             String::from("Options"),
+             String::from("Edit map"),
+             String::from("Quit")]
+    }
+
+    fn input(&mut self, input: MenuInput) {
+        self.chosen = self.menu.input(input, MainMenu::items().len());
+    }
+}
+
+impl Scene<Shared> for MainMenu {
+    fn update(&mut self,
+              ctx: &mut ggez::Context,
+              shared: &mut Shared,
+              _dt: Duration)
+              -> GameResult<Transition<Shared>> {
+        Ok(match self.chosen.take() {
+            Some(MenuInput::Select) => {
+                match self.menu.selected {
+                    0 => Transition::Push(Box::new(MatchScene::new(ctx, shared, None, None))),
+                    1 => Transition::Push(Box::new(OptionsScene::new())),
+                    2 => Transition::Push(Box::new(EditorScene::new(ctx, shared))),
+                    _ => Transition::Quit,
+                }
+            }
+            Some(MenuInput::Back) => Transition::Pop,
+            _ => Transition::None,
+        })
+    }
+
+    fn draw(&mut self, ctx: &mut ggez::Context, shared: &mut Shared) -> GameResult<()> {
+        self.menu.draw(ctx, &shared.font, "Flipwrecked", &MainMenu::items())
+    }
+
+    fn draws_below(&self) -> bool {
+        true
+    }
+
+    fn key_down(&mut self, _shared: &mut Shared, keycode: Keycode, _repeat: bool) {
+        if let Some(input) = MenuInput::from_key(keycode) {
+            self.input(input);
+        }
+    }
+
+    fn pad_button(&mut self, _shared: &mut Shared, btn: Button) {
+        if let Some(input) = MenuInput::from_button(btn) {
+            self.input(input);
+        }
+    }
+}
+
+/// Sets up the next match: how many players, how many of them are
+/// bots and how good, and the controls.
+struct OptionsScene {
+    menu: Menu,
+    chosen: Option<MenuInput>,
+}
+
+const DIFFICULTIES: [Difficulty; 3] = [Difficulty::Easy, Difficulty::Normal, Difficulty::Hard];
+
+impl OptionsScene {
+    fn new() -> Self {
+        OptionsScene {
+            menu: Menu::new(),
+            chosen: None,
+        }
+    }
+
+    fn items(settings: &MatchSettings) -> Vec<String> {
+        vec![format!("Players: < {} >", settings.players),
+             format!("Computer players: < {} >", settings.bots),
+             format!("Computer difficulty: < {:?} >", settings.difficulty),
+             format!("Series: < {} >", settings.series.name()),
+             String::from("Controls"),
+             String::from("Back")]
+    }
+
+    /// Changes the settings; the map decides how many players there can be.
+    fn input(&mut self, settings: &mut MatchSettings, max_players: usize, input: MenuInput) {
+        let input = match self.menu.input(input, OptionsScene::items(settings).len()) {
+            Some(input) => input,
+            None => return,
+        };
+        let step = match input {
+            MenuInput::Left => -1,
+            MenuInput::Right => 1,
+            _ => {
+                self.chosen = Some(input);
+                return;
+            }
+        };
+        let change = |value: usize, min: usize, max: usize| {
+            (value as i32 + step).max(min as i32).min(max as i32) as usize
+        };
+        match self.menu.selected {
+            0 => {
+                settings.players = change(settings.players, MIN_PLAYERS, max_players);
+                settings.bots = settings.bots.min(settings.players);
+            }
+            1 => settings.bots = change(settings.bots, 0, settings.players),
+            2 => {
+                let current = DIFFICULTIES.iter()
+                    .position(|&d| d == settings.difficulty)
+                    .unwrap_or(1);
+                settings.difficulty = DIFFICULTIES[change(current, 0, DIFFICULTIES.len() - 1)];
+            }
+            3 => {
+                let formats = SeriesFormat::all();
+                // Anything from the command line that isn't on the list
+                // goes back to a single round.
+                let current = formats.iter().position(|&f| f == settings.series).unwrap_or(0);
+                settings.series = formats[change(current, 0, formats.len() - 1)];
+            }
             _ => (),
         }
+    }
+}
+
+impl Scene<Shared> for OptionsScene {
+    fn update(&mut self,
+              _ctx: &mut ggez::Context,
+              shared: &mut Shared,
+              _dt: Duration)
+              -> GameResult<Transition<Shared>> {
+        Ok(match self.chosen.take() {
+            Some(MenuInput::Select) if self.menu.selected == 4 => {
+                let controls = shared.settings
+                    .players(shared.pad_count())
+                    .iter()
+                    .map(|p| p.control)
+                    .collect();
+                Transition::Push(Box::new(RebindScene::new(controls)))
+            }
+            Some(MenuInput::Select) if self.menu.selected == 5 => Transition::Pop,
+            Some(MenuInput::Back) => Transition::Pop,
+            _ => Transition::None,
+        })
+    }
+
+    fn draw(&mut self, ctx: &mut ggez::Context, shared: &mut Shared) -> GameResult<()> {
+        let items = OptionsScene::items(&shared.settings);
+        self.menu.draw(ctx, &shared.font, "Options", &items)
+    }
+
+    fn draws_below(&self) -> bool {
+        true
+    }
+
+    fn key_down(&mut self, shared: &mut Shared, keycode: Keycode, _repeat: bool) {
+        if let Some(input) = MenuInput::from_key(keycode) {
+            self.input(&mut shared.settings, shared.map.max_players(), input);
+        }
+    }
 
+    fn pad_button(&mut self, shared: &mut Shared, btn: Button) {
+        if let Some(input) = MenuInput::from_button(btn) {
+            self.input(&mut shared.settings, shared.map.max_players(), input);
+        }
     }
+}
+
+/// What clicking on the water does in the map editor.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+enum EditTool {
+    Rock,
+    Depth,
+    Kelp,
+    Spawn,
+    /// Makes waves, to see what the map does with them.
+    Splash,
+}
 
+impl EditTool {
+    fn name(&self) -> &'static str {
+        match *self {
+            EditTool::Rock => "Rock",
+            EditTool::Depth => "Depth",
+            EditTool::Kelp => "Kelp",
+            EditTool::Spawn => "Spawns",
+            EditTool::Splash => "Splash",
+        }
+    }
+}
+
+// The brush paints a square this many cells out from the one clicked.
+const MAX_BRUSH: usize = 4;
+
+/// Paints rocks, depths and kelp onto the current map with the mouse,
+/// puts down spawns, and splashes the water to try it out.  Edits go
+/// straight into the map matches are played on, so playtesting is just
+/// starting a match.
+struct EditorScene {
+    // Live water in the shape of the map, for seeing the waves.
+    field: Field,
+    wave_images: WaveImages,
+    // A ship sitting on each spawn.
+    players: Vec<Player>,
+    sprites: Vec<ShipSprite>,
+    tool: EditTool,
+    // In the map's steps, the way the digits in a map file are.
+    depth_level: u32,
+    kelp_level: u32,
+    brush: usize,
+    accumulator: Duration,
+    // The last thing that happened, like the map being saved.
+    status: String,
+    // The map as it was when the editor was opened, to put back if
+    // it gets left in a state nobody can play on.
+    original: Map,
+    // Set by key presses, for the next update to act on.
+    playtest: bool,
+    leave: bool,
+}
 
-    fn key_up_event(&mut self, _keycode: Keycode, _keymod: Mod, _repeat: bool) {
-        match _keycode {
-            Keycode::W => self.player1.key_up_event(Buttons::Up),
-            Keycode::A => self.player1.key_up_event(Buttons::Left),
-            Keycode::D => self.player1.key_up_event(Buttons::Right),
+impl EditorScene {
+    fn new(ctx: &mut ggez::Context, shared: &mut Shared) -> Self {
+        let mut editor = EditorScene {
+            field: shared.map.field(shared.settings.seed(), shared.params()),
+            wave_images: WaveImages::new(ctx),
+            players: Vec::new(),
+            sprites: Vec::new(),
+            tool: EditTool::Rock,
+            depth_level: 3,
+            kelp_level: 3,
+            brush: 1,
+            accumulator: Duration::new(0, 0),
+            status: format!("Editing {}", shared.map.name),
+            original: shared.map.clone(),
+            playtest: false,
+            leave: false,
+        };
+        editor.place_ships(&shared.map);
+        editor
+    }
+
+    /// Puts a ship on each spawn.  The sprites catch up in `update()`.
+    fn place_ships(&mut self, map: &Map) {
+        self.players = default_players(map.max_players());
+        map.place(&mut self.players);
+    }
+
+    fn map_file(shared: &Shared) -> String {
+        shared.map_file.clone().unwrap_or(String::from(DEFAULT_MAP_FILE))
+    }
+
+    fn save(&mut self, shared: &mut Shared) {
+        let path = EditorScene::map_file(shared);
+        self.status = match shared.map.save(&path) {
+            Ok(()) => {
+                shared.map_file = Some(path.clone());
+                format!("Saved to {}", path)
+            }
+            Err(e) => format!("Not saved, {}", e),
+        };
+        println!("{}", self.status);
+    }
+
+    fn load(&mut self, shared: &mut Shared) {
+        let path = EditorScene::map_file(shared);
+        self.status = match Map::load(&path) {
+            Ok(map) => {
+                self.field = map.field(shared.settings.seed(), shared.params());
+                self.place_ships(&map);
+                shared.map = map;
+                format!("Loaded {}", path)
+            }
+            Err(e) => format!("{}: {}", path, e),
+        };
+        println!("{}", self.status);
+    }
+
+    /// Sets the depth and/or kelp under the brush, on the map and the
+    /// water both.
+    fn paint(&mut self,
+             map: &mut Map,
+             x: usize,
+             y: usize,
+             depth: Option<f32>,
+             damping: Option<f32>) {
+        let (x0, y0) = (x.saturating_sub(self.brush), y.saturating_sub(self.brush));
+        let x1 = usize::min(x + self.brush + 1, map.width());
+        let y1 = usize::min(y + self.brush + 1, map.height());
+        for y in y0..y1 {
+            for x in x0..x1 {
+                if let Some(depth) = depth {
+                    map.set_depth(x, y, depth);
+                    self.field.set_depth(x, y, depth);
+                }
+                if let Some(damping) = damping {
+                    map.set_damping(x, y, damping);
+                    self.field.set_damping(x, y, damping);
+                }
+            }
+        }
+    }
+
+    /// Adds a spawn facing the middle of the arena, or with `add` false,
+    /// takes away the one nearest.  There's always room for at least
+    /// `MIN_PLAYERS`, so the editor can always put ships on them.
+    fn edit_spawns(&mut self, map: &mut Map, x: f32, y: f32, add: bool) {
+        if add {
+            let cell = FIELD_CELL_SIZE as f32;
+            let (mid_x, mid_y) = (map.width() as f32 * cell / 2.0,
+                                  map.height() as f32 * cell / 2.0);
+            // Bearings go clockwise from up.
+            let bearing = f32::atan2(mid_x - x, y - mid_y);
+            if map.spawns.len() < MAX_PLAYERS {
+                map.spawns.push(Spawn::new(x, y, bearing));
+            } else {
+                self.status = format!("There can't be more than {} spawns", MAX_PLAYERS);
+            }
+        } else if map.spawns.len() <= MIN_PLAYERS {
+            self.status = format!("There have to be at least {} spawns", MIN_PLAYERS);
+            return;
+        } else {
+            let distance = |s: &Spawn| (s.x - x).powi(2) + (s.y - y).powi(2);
+            let nearest = (0..map.spawns.len()).min_by(|&a, &b| {
+                distance(&map.spawns[a])
+                    .partial_cmp(&distance(&map.spawns[b]))
+                    .unwrap()
+            });
+            if let Some(i) = nearest {
+                map.spawns.remove(i);
+            }
+        }
+        self.place_ships(map);
+    }
+
+    fn key_hints() -> [&'static str; 2] {
+        ["R rock, D depth, K kelp, P spawns, W splash, up/down level, [ ] brush",
+         "B edges, C calm, S save, L load, Enter playtest, Esc done"]
+    }
+}
 
-            Keycode::I => self.player2.key_up_event(Buttons::Up),
-            Keycode::J => self.player2.key_up_event(Buttons::Left),
-            Keycode::L => self.player2.key_up_event(Buttons::Right),
+impl Scene<Shared> for EditorScene {
+    fn update(&mut self,
+              ctx: &mut ggez::Context,
+              shared: &mut Shared,
+              dt: Duration)
+              -> GameResult<Transition<Shared>> {
+        if self.playtest || self.leave {
+            let playtest = self.playtest;
+            self.playtest = false;
+            self.leave = false;
+            if let Err(e) = shared.map.check() {
+                if playtest {
+                    self.status = format!("Can't playtest, {}", e);
+                    return Ok(Transition::None);
+                }
+                println!("Putting the map back the way it was, {}", e);
+                shared.map = self.original.clone();
+            }
+            shared.fit_settings_to_map();
+            if playtest {
+                self.status = String::from("Back from playtesting");
+                return Ok(Transition::Push(Box::new(MatchScene::new(ctx, shared, None, None))));
+            }
+            return Ok(Transition::Pop);
+        }
+
+        while self.sprites.len() < self.players.len() {
+            let i = self.sprites.len();
+            self.sprites.push(ShipSprite::for_player(ctx, i, self.players[i].colour));
+        }
+
+        let tick = tick_duration();
+        self.accumulator += dt;
+        if self.accumulator > tick * MAX_TICKS_PER_FRAME {
+            self.accumulator = tick * MAX_TICKS_PER_FRAME;
+        }
+        while self.accumulator >= tick {
+            self.field.params = shared.params();
+            self.field.update();
+            self.accumulator -= tick;
+        }
+        Ok(Transition::None)
+    }
+
+    fn draw(&mut self, ctx: &mut ggez::Context, shared: &mut Shared) -> GameResult<()> {
+        draw_field(ctx, &self.field, &mut self.wave_images)?;
+        for (sprite, player) in self.sprites.iter_mut().zip(&self.players) {
+            sprite.draw(ctx, &player.ship)?;
+        }
+
+        let level = match self.tool {
+            EditTool::Depth => {
+                let depth = level_depth(self.depth_level);
+                if depth < DEFAULT_DEPTH {
+                    format!(" {} (shallow)", self.depth_level)
+                } else if depth > DEFAULT_DEPTH {
+                    format!(" {} (deep)", self.depth_level)
+                } else {
+                    format!(" {}", self.depth_level)
+                }
+            }
+            EditTool::Kelp => format!(" {}", self.kelp_level),
+            _ => String::new(),
+        };
+        let size = self.brush * 2 + 1;
+        let edges = match shared.map.boundary {
+            Some(boundary) => format!("{:?}", boundary),
+            None => String::from("params"),
+        };
+        let line = format!("{}{}, brush {}x{}, {} spawns, edges {}.  {}",
+                           self.tool.name(),
+                           level,
+                           size,
+                           size,
+                           shared.map.max_players(),
+                           edges,
+                           self.status);
+        graphics::set_color(ctx, Color::RGBA(255, 255, 255, 255));
+        draw_text(ctx, &shared.font, &line, 10, 10)?;
+        for (i, hint) in EditorScene::key_hints().iter().enumerate() {
+            draw_text(ctx, &shared.font, hint, 10, 36 + 26 * i as i32)?;
+        }
+        Ok(())
+    }
+
+    fn key_down(&mut self, shared: &mut Shared, keycode: Keycode, _repeat: bool) {
+        match keycode {
+            Keycode::R => self.tool = EditTool::Rock,
+            Keycode::D => self.tool = EditTool::Depth,
+            Keycode::K => self.tool = EditTool::Kelp,
+            Keycode::P => self.tool = EditTool::Spawn,
+            Keycode::W => self.tool = EditTool::Splash,
+            Keycode::Up | Keycode::Down => {
+                let step = if keycode == Keycode::Up { 1 } else { -1 };
+                let change = |level: u32| (level as i32 + step).max(1).min(9) as u32;
+                match self.tool {
+                    EditTool::Depth => self.depth_level = change(self.depth_level),
+                    EditTool::Kelp => self.kelp_level = change(self.kelp_level),
+                    _ => (),
+                }
+            }
+            Keycode::LeftBracket => self.brush = self.brush.saturating_sub(1),
+            Keycode::RightBracket => self.brush = usize::min(self.brush + 1, MAX_BRUSH),
+            Keycode::B => {
+                let next = match shared.map.boundary {
+                    None => Some(Boundary::Reflecting),
+                    Some(Boundary::Reflecting) => Some(Boundary::Absorbing),
+                    Some(Boundary::Absorbing) => Some(Boundary::Periodic),
+                    Some(Boundary::Periodic) => Some(Boundary::FixedZero),
+                    Some(Boundary::FixedZero) => None,
+                };
+                shared.map.boundary = next;
+            }
+            Keycode::C => self.field.calm(shared.settings.seed()),
+            Keycode::S => self.save(shared),
+            Keycode::L => self.load(shared),
+            Keycode::Return => self.playtest = true,
+            Keycode::Escape => self.leave = true,
             _ => (),
         }
     }
 
-    fn controller_button_down_event(&mut self, _btn: Button) {
-        println!("Button {:?} released", _btn);
-        //     let x = x as u32 / FIELD_CELL_SIZE;
-        //     let y = y as u32 / FIELD_CELL_SIZE;
-        //     println!("Creating splash at {}, {}", x, y);
-        // match button {
-        //     MouseButton::Left => {
-        //         self.player2.key_down_event(Buttons::Up);
-        //     }
-        //     // MouseButton::Left => {
-        //     //     self.field.create_splash(x as usize, y as usize, 3, 1.0);
-        //     // }
-        //     //
-        //     // MouseButton::Right => {
-        //     //     self.field.create_splash(x as usize, y as usize, 3, -1.0);
-        //     // }
-        //     _ => (),
-        // }
-    }
-    fn mouse_button_down_event(&mut self, _button: MouseButton, _x: i32, _y: i32) {
-        // println!("Mouse clicking at {}, {}", x, y);
-        // let x = x as u32 / FIELD_CELL_SIZE;
-        // let y = y as u32 / FIELD_CELL_SIZE;
-        // println!("Creating splash at {}, {}", x, y);
-        // match button {
-        //     MouseButton::Left => {
-        //         self.field.create_splash(x as usize, y as usize, 3, 1.0);
-        //     }
-        //     _ => (),
-        // }
-    }
-
-    fn controller_button_up_event(&mut self, _btn: Button) {
-        println!("Button {:?} pressed", _btn);
-        //     let x = x as u32 / FIELD_CELL_SIZE;
-        //     let y = y as u32 / FIELD_CELL_SIZE;
-        //     println!("Creating splash at {}, {}", x, y);
-        // match button {
-        //     MouseButton::Left => {
-        //         self.player2.key_up_event(Buttons::Up);
-        //     }
-        //     // MouseButton::Left => {
-        //     //     self.field.create_splash(x as usize, y as usize, 3, 1.0);
-        //     // }
-        //     //
-        //     // MouseButton::Right => {
-        //     //     self.field.create_splash(x as usize, y as usize, 3, -1.0);
-        //     // }
-        //     _ => (),
-        // }
-    }
-
-    fn controller_axis_event(&mut self, axis: Axis, value: i16) {
-        println!("Axis {:?}, value {}", axis, value);
-        // if xrel < 0 {
-        //     self.player2.key_up_event(Buttons::Right);
-        //     self.player2.key_down_event(Buttons::Left);
-        // } else {
-        //     self.player2.key_up_event(Buttons::Left);
-        //     self.player2.key_down_event(Buttons::Right);
-
-        // }
+    /// The left button paints or adds, the right one clears or takes
+    /// away.  Splashes are crests on the left and troughs on the right.
+    fn mouse_down(&mut self, shared: &mut Shared, button: MouseButton, x: i32, y: i32) {
+        let add = match button {
+            MouseButton::Left => true,
+            MouseButton::Right => false,
+            _ => return,
+        };
+        if x < 0 || y < 0 {
+            return;
+        }
+        let (cx, cy) = screen_to_field_coords(x as u32, y as u32);
+        let map = &mut shared.map;
+        if cx >= map.width() || cy >= map.height() {
+            return;
+        }
+        match self.tool {
+            EditTool::Rock => {
+                let depth = if add { ROCK } else { DEFAULT_DEPTH };
+                self.paint(map, cx, cy, Some(depth), None);
+            }
+            EditTool::Depth => {
+                let depth = if add {
+                    level_depth(self.depth_level)
+                } else {
+                    DEFAULT_DEPTH
+                };
+                self.paint(map, cx, cy, Some(depth), None);
+            }
+            EditTool::Kelp => {
+                let damping = if add {
+                    self.kelp_level as f32 * DAMPING_STEP
+                } else {
+                    0.0
+                };
+                self.paint(map, cx, cy, None, Some(damping));
+            }
+            EditTool::Spawn => {
+                if add && map.depth(cx, cy) == ROCK {
+                    self.status = String::from("Spawns can't go on rocks");
+                } else {
+                    self.edit_spawns(map, x as f32, y as f32, add);
+                }
+            }
+            EditTool::Splash => {
+                let polarity = if add { 1.0 } else { -1.0 };
+                // Right under the pointer, not the corner of its cell.
+                let (fx, fy) = screen_to_field(x as f32, y as f32);
+                self.field.splash(fx, fy, &landing_splash(polarity));
+            }
+        }
     }
 }
 
@@ -620,42 +2106,177 @@ impl TitleScreen {
     }
 }
 
-impl game::EventHandler for TitleScreen {
-    fn update(&mut self, ctx: &mut ggez::Context, dt: Duration) -> GameResult<()> {
+impl Scene<Shared> for TitleScreen {
+    fn update(&mut self,
+              _ctx: &mut ggez::Context,
+              _shared: &mut Shared,
+              _dt: Duration)
+              -> GameResult<Transition<Shared>> {
         if self.done {
-            ctx.quit()?;
+            self.done = false;
+            return Ok(Transition::Push(Box::new(MainMenu::new())));
         }
-        Ok(())
+        Ok(Transition::None)
     }
 
-    fn draw(&mut self, ctx: &mut ggez::Context) -> GameResult<()> {
-        graphics::clear(ctx);
+    fn draw(&mut self, ctx: &mut ggez::Context, _shared: &mut Shared) -> GameResult<()> {
+        self.image.draw(ctx, None, None)
+    }
 
-        self.image.draw(ctx, None, None)?;
+    fn key_down(&mut self, _shared: &mut Shared, _keycode: Keycode, _repeat: bool) {
+        // Any key brings up the menu.
+        self.done = true;
+    }
 
-        ctx.renderer.present();
-        Ok(())
+    fn pad_button(&mut self, _shared: &mut Shared, _btn: Button) {
+        self.done = true;
     }
+}
 
+/// Returns the value following `--<name>` on the command line, if any.
+fn arg_value(name: &str) -> Option<String> {
+    let args: Vec<String> = env::args().collect();
+    args.windows(2)
+        .find(|pair| pair[0] == name)
+        .map(|pair| pair[1].clone())
+}
 
-    fn key_down_event(&mut self, _keycode: Keycode, _keymod: Mod, _repeat: bool) {
-        // End this gameloop and move on to the next.
-        self.done = true;
+/// The match settings from the command line: `--seed <n>` to play the
+/// same match every time, `--players <n>` ships (2 by default), and
+/// `--bots <n>` of them steered by the computer (none by default) at
+/// `--difficulty easy|normal|hard`, for `--series single|bo<n>|ft<n>`
+/// rounds (best of n or first to n).
+fn settings_from_args() -> MatchSettings {
+    let seed = arg_value("--seed").map(|seed| seed.parse().expect("--seed must be a number"));
+    let players = arg_value("--players")
+        .map(|n| n.parse().expect("--players must be a number"))
+        .unwrap_or(MIN_PLAYERS)
+        .max(MIN_PLAYERS)
+        .min(MAX_PLAYERS);
+    let bots = arg_value("--bots")
+        .map(|n| n.parse().expect("--bots must be a number"))
+        .unwrap_or(0);
+    let difficulty = arg_value("--difficulty")
+        .map(|name| {
+            Difficulty::from_name(&name).expect("--difficulty must be easy, normal or hard")
+        })
+        .unwrap_or(Difficulty::Normal);
+    let series = arg_value("--series")
+        .map(|name| {
+            SeriesFormat::from_name(&name).expect("--series must be single, bo<n> or ft<n>")
+        })
+        .unwrap_or(SeriesFormat::Single);
+    MatchSettings {
+        seed: seed,
+        players: players,
+        bots: usize::min(bots, players),
+        difficulty: difficulty,
+        series: series,
     }
 }
 
+/// `--host <port>` waits for somebody to `--join <address>:<port>`,
+/// and then they play each other over the network.  Hosting picks the
+/// seed; both need the same map and params.
+fn net_from_args(seed: u64, map: &Map, params: WaveParams) -> Option<NetSession> {
+    if let Some(port) = arg_value("--host") {
+        let port: u16 = port.parse().expect("--host must be a port number");
+        let socket = UdpSocket::bind(("0.0.0.0", port))
+            .unwrap_or_else(|e| panic!("Could not listen on port {}: {}", port, e));
+        println!("Waiting for somebody to join on port {}...", port);
+        let net = NetSession::host(socket, seed, map, params)
+            .unwrap_or_else(|e| panic!("Network error: {}", e));
+        return Some(net);
+    }
+    arg_value("--join").map(|addr| {
+        let socket = UdpSocket::bind("0.0.0.0:0").unwrap();
+        println!("Joining {}...", addr);
+        NetSession::join(socket, addr.as_str(), map, params)
+            .unwrap_or_else(|e| panic!("Could not join {}: {}", addr, e))
+    })
+}
+
 fn main() {
+    // `--replay <file>` watches a recorded match instead of playing one.
+    let playback = arg_value("--replay").map(|path| {
+        Replay::load(&path).unwrap_or_else(|e| panic!("Could not load replay {}: {}", path, e))
+    });
+
+    let params_file = arg_value("--params").unwrap_or(String::from(DEFAULT_PARAMS_FILE));
+    let params = WaveParams::load(&params_file).unwrap_or_else(|e| {
+        println!("Using default wave params, {}: {}", params_file, e);
+        WaveParams::default()
+    });
+    let params_watcher = ParamsWatcher::new(&params_file);
+
+    let input_file = arg_value("--input").unwrap_or(String::from(DEFAULT_INPUT_FILE));
+    let input_map = InputMap::load(&input_file).unwrap_or_else(|e| {
+        println!("Using default bindings, {}: {}", input_file, e);
+        InputMap::default()
+    });
+
+    // Every match gets recorded, except when watching one.
+    let record_file = if playback.is_none() {
+        Some(arg_value("--record").unwrap_or(String::from(DEFAULT_REPLAY_FILE)))
+    } else {
+        None
+    };
+
+    // `--map <file>` plays on an arena from a map file.
+    let map = match arg_value("--map") {
+        Some(path) => {
+            Map::load(&path).unwrap_or_else(|e| panic!("Could not load map {}: {}", path, e))
+        }
+        None => Map::open_sea(),
+    };
+    if let Some(ref replay) = playback {
+        if let Err(e) = replay.check_map(&map) {
+            panic!("Can't watch the replay: {}.  Pass the map it was played on with --map.", e);
+        }
+        if replay.map.is_none() {
+            println!("The replay is too old to say what map or params it was played with; \
+                      it may not play back the way it went.");
+        }
+    }
+
+    let settings = settings_from_args();
+    let net = if playback.is_none() {
+        net_from_args(settings.seed(), &map, map.params(params))
+    } else {
+        None
+    };
+
     let c = default_conf();
     let mut ctx = ggez::Context::load_from_conf("Flipwrecked", c).unwrap();
 
-    let titlescreen = TitleScreen::new(&mut ctx);
-    let g = game::Game::from_state(ctx, titlescreen);
-    let mut ctx = g.run().unwrap();
-
     let m = audio::Music::new(&mut ctx, "Trance.ogg").unwrap();
     audio::play_music(&mut ctx, &m).unwrap();
-    let state = MainState::new(&mut ctx);
-    let g = game::Game::from_state(ctx, state);
+
+    let mut shared = Shared {
+        font: graphics::Font::new(&mut ctx, "DejaVuSans.ttf", 20).unwrap(),
+        win_images: WinImages::new(&mut ctx),
+        input_map: input_map,
+        input_file: input_file,
+        gamepads: Gamepads::new(&mut ctx),
+        file_params: params,
+        params_watcher: params_watcher,
+        preset: Preset::File,
+        volume: VOLUME_STEPS,
+        settings: settings,
+        map: map,
+        map_file: arg_value("--map"),
+        record_file: record_file,
+        match_command: None,
+    };
+    shared.fit_settings_to_map();
+
+    let mut scenes: Vec<Box<Scene<Shared>>> = vec![Box::new(TitleScreen::new(&mut ctx))];
+    if playback.is_some() || net.is_some() {
+        // Straight into the match, with the menu to come back to.
+        scenes.push(Box::new(MainMenu::new()));
+        scenes.push(Box::new(MatchScene::new(&mut ctx, &mut shared, playback, net)));
+    }
+    let g = game::Game::from_state(ctx, SceneStack::new(shared, scenes));
 
     g.run().unwrap();
 }
@@ -0,0 +1,500 @@
+//! Arenas: how big the water is, where the rocks are, how deep it is
+//! and where the ships start, loaded from TOML files.
+//!
+//! ```toml
+//! name = "Sound"
+//! width = 40
+//! height = 30
+//! # Optional; otherwise whatever the wave params say.
+//! boundary = "absorbing"
+//! # One line per row of cells, one character per cell.
+//! tiles = """
+//! ##########...
+//! #.....3333...
+//! ...
+//! """
+//! # Optional, the same size as the tiles.
+//! kelp = """
+//! ..........22...
+//! ...
+//! """
+//!
+//! # In cells from the top left, facing in degrees clockwise from up.
+//! [[spawns]]
+//! x = 5.0
+//! y = 5.0
+//! facing = 180.0
+//! ```
+//!
+//! The boundary is what the edges of the arena do to waves, as in the
+//! wave params file: `"reflecting"`, `"absorbing"`, `"periodic"` or
+//! `"fixed_zero"`.
+//!
+//! In the tiles, `#` is rock, `.` is water of `DEFAULT_DEPTH`, and the
+//! digits `1` to `9` are water of other depths, see `level_depth()`:
+//! `1` to `4` are shallows a fifth of that deep for each step, and `6`
+//! to `9` are deep water, with `9` as deep as it gets.  Without any
+//! tiles the whole arena is open water.  Arenas can be smaller than the
+//! window, but not bigger; they sit in its top left corner.
+//!
+//! In the kelp, `.` is clear water and the digits `1` to `9` are ever
+//! thicker kelp beds, which soak up the waves going through them.
+//!
+//! Waves are slower in shallow water and bend towards it, so the depths
+//! can focus them: see `resources/maps/lens.toml`.
+//!
+//! Maps can also be made in game, with the map editor on the main menu.
+
+use std::error::Error;
+use std::f32::consts::PI;
+use std::fmt;
+use std::fs::File;
+use std::io::{self, Read, Write};
+use std::path::Path;
+use std::str::FromStr;
+
+use toml;
+
+use field::{Boundary, Field, FIELD_CELL_SIZE, FIELD_WIDTH, FIELD_HEIGHT, DEFAULT_DEPTH, MAX_DEPTH,
+            ROCK, MAX_DAMPING};
+use grid::Grid;
+use params::WaveParams;
+use player::{Player, Spawn, default_players, MIN_PLAYERS, MAX_PLAYERS};
+
+/// The smallest arena, in cells, that ships still have room to move in.
+pub const MIN_MAP_SIZE: usize = 30;
+
+/// How much more each digit in the kelp soaks up than the one before.
+pub const DAMPING_STEP: f32 = MAX_DAMPING / 10.0;
+
+#[derive(Debug)]
+pub enum MapError {
+    Io(io::Error),
+    Parse(toml::de::Error),
+    Save(toml::ser::Error),
+    /// It parsed, but doesn't make a usable arena.
+    Invalid(String),
+}
+
+impl fmt::Display for MapError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            MapError::Io(ref e) => write!(f, "could not read map: {}", e),
+            MapError::Parse(ref e) => write!(f, "could not parse map: {}", e),
+            MapError::Save(ref e) => write!(f, "could not save map: {}", e),
+            MapError::Invalid(ref why) => write!(f, "bad map: {}", why),
+        }
+    }
+}
+
+impl Error for MapError {
+    fn description(&self) -> &str {
+        match *self {
+            MapError::Io(_) => "could not read map",
+            MapError::Parse(_) => "could not parse map",
+            MapError::Save(_) => "could not save map",
+            MapError::Invalid(_) => "bad map",
+        }
+    }
+}
+
+impl From<io::Error> for MapError {
+    fn from(e: io::Error) -> Self {
+        MapError::Io(e)
+    }
+}
+
+impl From<toml::de::Error> for MapError {
+    fn from(e: toml::de::Error) -> Self {
+        MapError::Parse(e)
+    }
+}
+
+impl From<toml::ser::Error> for MapError {
+    fn from(e: toml::ser::Error) -> Self {
+        MapError::Save(e)
+    }
+}
+
+fn invalid<T>(why: String) -> Result<T, MapError> {
+    Err(MapError::Invalid(why))
+}
+
+/// A spawn point the way it's written in a map file.
+#[derive(Debug, Copy, Clone, PartialEq, Serialize, Deserialize)]
+struct MapSpawn {
+    x: f32,
+    y: f32,
+    #[serde(default)]
+    facing: f32,
+}
+
+/// A map file as it's written.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct MapFile {
+    #[serde(default)]
+    name: String,
+    width: usize,
+    height: usize,
+    #[serde(default)]
+    boundary: Option<Boundary>,
+    #[serde(default)]
+    tiles: Option<String>,
+    #[serde(default)]
+    kelp: Option<String>,
+    #[serde(default)]
+    spawns: Vec<MapSpawn>,
+}
+
+/// What one character in the kelp means, or `None` if it's nonsense.
+fn kelp_damping(tile: char) -> Option<f32> {
+    match tile {
+        '.' => Some(0.0),
+        _ => {
+            match tile.to_digit(10) {
+                Some(0) | None => None,
+                Some(n) => Some(DAMPING_STEP * n as f32),
+            }
+        }
+    }
+}
+
+/// The kelp character for a damping, to the nearest step.
+fn damping_tile(damping: f32) -> char {
+    let n = (damping / DAMPING_STEP).round() as u32;
+    if n == 0 {
+        '.'
+    } else {
+        ::std::char::from_digit(n.min(9), 10).unwrap()
+    }
+}
+
+// Writes one layer of a map out, one character per cell, or `None` if
+// every cell is `blank`.
+fn write_layer<F>(grid: &Grid<f32>, blank: char, tile: F) -> Option<String>
+    where F: Fn(f32) -> char
+{
+    let mut layer = String::new();
+    for y in 0..grid.height() {
+        for x in 0..grid.width() {
+            layer.push(tile(grid.get(x, y)));
+        }
+        layer.push('\n');
+    }
+    if layer.chars().all(|c| c == blank || c == '\n') {
+        None
+    } else {
+        Some(layer)
+    }
+}
+
+// Reads one layer of a map into `grid`, one character per cell.
+fn read_layer<F>(layer: &str,
+                 what: &str,
+                 grid: &mut Grid<f32>,
+                 tile_value: F)
+                 -> Result<(), MapError>
+    where F: Fn(char) -> Option<f32>
+{
+    let (width, height) = (grid.width(), grid.height());
+    let rows: Vec<&str> = layer.lines().collect();
+    if rows.len() != height {
+        return invalid(format!("{} rows of {} for a height of {}", rows.len(), what, height));
+    }
+    for (y, row) in rows.iter().enumerate() {
+        if row.chars().count() != width {
+            return invalid(format!("row {} of the {} isn't {} wide", y + 1, what, width));
+        }
+        for (x, tile) in row.chars().enumerate() {
+            match tile_value(tile) {
+                Some(v) => grid.set(x, y, v),
+                None => return invalid(format!("no such {} as '{}'", what, tile)),
+            }
+        }
+    }
+    Ok(())
+}
+
+/// How deep the water is for a digit in the tiles.  Up to 5, which is
+/// `DEFAULT_DEPTH`, each one is a fifth of that deeper; from there each
+/// one is a quarter of the way on to `MAX_DEPTH`, so 9 is exactly that.
+pub fn level_depth(level: u32) -> f32 {
+    if level <= 5 {
+        DEFAULT_DEPTH * level as f32 / 5.0
+    } else {
+        DEFAULT_DEPTH + (MAX_DEPTH - DEFAULT_DEPTH) * (level - 5) as f32 / 4.0
+    }
+}
+
+/// The tile for a depth, to the nearest level.
+fn depth_tile(depth: f32) -> char {
+    if depth == ROCK {
+        return '#';
+    }
+    let level = if depth <= DEFAULT_DEPTH {
+        (depth / DEFAULT_DEPTH * 5.0).round() as u32
+    } else {
+        5 + ((depth - DEFAULT_DEPTH) / (MAX_DEPTH - DEFAULT_DEPTH) * 4.0).round() as u32
+    };
+    match level {
+        5 => '.',
+        n => ::std::char::from_digit(n.max(1).min(9), 10).unwrap(),
+    }
+}
+
+/// What one character in the tiles means, or `None` if it's nonsense.
+fn tile_depth(tile: char) -> Option<f32> {
+    match tile {
+        '#' => Some(ROCK),
+        '.' => Some(DEFAULT_DEPTH),
+        _ => {
+            match tile.to_digit(10) {
+                Some(0) | None => None,
+                Some(n) => Some(level_depth(n)),
+            }
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct Map {
+    pub name: String,
+    /// What the edges do to waves, if the map says.
+    pub boundary: Option<Boundary>,
+    depth: Grid<f32>,
+    damping: Grid<f32>,
+    /// Where each player starts, in order.  Players past the end
+    /// can't play on this map.
+    pub spawns: Vec<Spawn>,
+}
+
+impl FromStr for Map {
+    type Err = MapError;
+
+    /// Parses a map from TOML, and checks it's one ships can play on.
+    fn from_str(s: &str) -> Result<Map, MapError> {
+        let file: MapFile = toml::from_str(s)?;
+        let (width, height) = (file.width, file.height);
+        if width < MIN_MAP_SIZE || height < MIN_MAP_SIZE || width > FIELD_WIDTH ||
+           height > FIELD_HEIGHT {
+            return invalid(format!("it's {}x{}, but has to be from {}x{} to {}x{}",
+                                   width,
+                                   height,
+                                   MIN_MAP_SIZE,
+                                   MIN_MAP_SIZE,
+                                   FIELD_WIDTH,
+                                   FIELD_HEIGHT));
+        }
+
+        let mut depth = Grid::new(width, height, DEFAULT_DEPTH);
+        if let Some(ref tiles) = file.tiles {
+            read_layer(tiles, "tiles", &mut depth, tile_depth)?;
+        }
+        let mut damping = Grid::new(width, height, 0.0);
+        if let Some(ref kelp) = file.kelp {
+            read_layer(kelp, "kelp", &mut damping, kelp_damping)?;
+        }
+
+        let cell = FIELD_CELL_SIZE as f32;
+        let mut spawns = Vec::new();
+        for s in &file.spawns {
+            if !depth.contains(s.x as i32, s.y as i32) || s.x < 0.0 || s.y < 0.0 {
+                return invalid(format!("spawn at {}, {} is off the map", s.x, s.y));
+            }
+            if depth.get(s.x as usize, s.y as usize) == ROCK {
+                return invalid(format!("spawn at {}, {} is on a rock", s.x, s.y));
+            }
+            spawns.push(Spawn::new(s.x * cell, s.y * cell, s.facing * PI / 180.0));
+        }
+        if spawns.len() < MIN_PLAYERS || spawns.len() > MAX_PLAYERS {
+            return invalid(format!("it has {} spawns, but needs {} to {}",
+                                   spawns.len(),
+                                   MIN_PLAYERS,
+                                   MAX_PLAYERS));
+        }
+
+        Ok(Map {
+            name: file.name,
+            boundary: file.boundary,
+            depth: depth,
+            damping: damping,
+            spawns: spawns,
+        })
+    }
+}
+
+impl Map {
+    /// The arena before there were maps: the whole window, open water,
+    /// with the standard spawns.
+    pub fn open_sea() -> Self {
+        Map {
+            name: String::from("Open sea"),
+            boundary: None,
+            depth: Grid::new(FIELD_WIDTH, FIELD_HEIGHT, DEFAULT_DEPTH),
+            damping: Grid::new(FIELD_WIDTH, FIELD_HEIGHT, 0.0),
+            spawns: default_players(MAX_PLAYERS).iter().map(|p| p.spawn).collect(),
+        }
+    }
+
+    pub fn load<P: AsRef<Path>>(path: P) -> Result<Map, MapError> {
+        let mut s = String::new();
+        File::open(path)?.read_to_string(&mut s)?;
+        Map::from_str(&s)
+    }
+
+    /// Writes the map out as TOML, the way `from_str` reads it.  Depths
+    /// and damping in between the steps the tiles can show get rounded.
+    pub fn to_toml(&self) -> Result<String, MapError> {
+        let cell = FIELD_CELL_SIZE as f32;
+        let file = MapFile {
+            name: self.name.clone(),
+            width: self.width(),
+            height: self.height(),
+            boundary: self.boundary,
+            tiles: write_layer(&self.depth, '.', depth_tile),
+            kelp: write_layer(&self.damping, '.', damping_tile),
+            spawns: self.spawns
+                .iter()
+                .map(|s| {
+                    MapSpawn {
+                        x: s.x / cell,
+                        y: s.y / cell,
+                        // Hundredths of a degree, so a spawn loaded at
+                        // 225 doesn't get saved at 224.99998.
+                        facing: (s.bearing * 180.0 / PI * 100.0).round() / 100.0,
+                    }
+                })
+                .collect(),
+        };
+        Ok(toml::to_string_pretty(&file)?)
+    }
+
+    /// A number that changes if anything that matters to a match does:
+    /// the size, the terrain, the spawns or the boundary, but not the
+    /// name.  Replays keep it so they're only watched on the map they
+    /// were played on.
+    pub fn checksum(&self) -> u64 {
+        // FNV-1a, which comes out the same on every build.
+        let mut hash: u64 = 0xcbf2_9ce4_8422_2325;
+        {
+            let mut add = |n: u32| {
+                for byte in 0..4 {
+                    hash ^= ((n >> (byte * 8)) & 0xff) as u64;
+                    hash = hash.wrapping_mul(0x0100_0000_01b3);
+                }
+            };
+            add(self.width() as u32);
+            add(self.height() as u32);
+            for y in 0..self.height() {
+                for x in 0..self.width() {
+                    add(self.depth(x, y).to_bits());
+                    add(self.damping(x, y).to_bits());
+                }
+            }
+            for spawn in &self.spawns {
+                add(spawn.x.to_bits());
+                add(spawn.y.to_bits());
+                add(spawn.bearing.to_bits());
+            }
+            add(match self.boundary {
+                None => 0,
+                Some(Boundary::Reflecting) => 1,
+                Some(Boundary::Absorbing) => 2,
+                Some(Boundary::Periodic) => 3,
+                Some(Boundary::FixedZero) => 4,
+            });
+        }
+        hash
+    }
+
+    /// Whether ships could play on the map as it is, after an edit.
+    pub fn check(&self) -> Result<(), MapError> {
+        Map::from_str(&self.to_toml()?).map(|_| ())
+    }
+
+    /// Saves the map, as long as it passes `check()`.
+    pub fn save<P: AsRef<Path>>(&self, path: P) -> Result<(), MapError> {
+        let s = self.to_toml()?;
+        Map::from_str(&s)?;
+        File::create(path)?.write_all(s.as_bytes())?;
+        Ok(())
+    }
+
+    /// Width in cells.
+    pub fn width(&self) -> usize {
+        self.depth.width()
+    }
+
+    /// Height in cells.
+    pub fn height(&self) -> usize {
+        self.depth.height()
+    }
+
+    /// How deep the water is at a cell; `ROCK` for rocks.
+    pub fn depth(&self, x: usize, y: usize) -> f32 {
+        self.depth.get(x, y)
+    }
+
+    /// Changes how deep the water is at a cell, up to `MAX_DEPTH`.
+    pub fn set_depth(&mut self, x: usize, y: usize, depth: f32) {
+        self.depth.set(x, y, f32::max(ROCK, f32::min(depth, MAX_DEPTH)));
+    }
+
+    /// How much of a wave the kelp at a cell soaks up each tick.
+    pub fn damping(&self, x: usize, y: usize) -> f32 {
+        self.damping.get(x, y)
+    }
+
+    /// Changes how thick the kelp is at a cell, up to `MAX_DAMPING`.
+    pub fn set_damping(&mut self, x: usize, y: usize, damping: f32) {
+        self.damping.set(x, y, f32::max(0.0, f32::min(damping, MAX_DAMPING)));
+    }
+
+    /// The most players this map has room for.
+    pub fn max_players(&self) -> usize {
+        self.spawns.len()
+    }
+
+    /// `params` with the map's own boundary, if it has one.
+    pub fn params(&self, params: WaveParams) -> WaveParams {
+        WaveParams {
+            boundary: self.boundary.unwrap_or(params.boundary),
+            ..params
+        }
+    }
+
+    /// Flat water in the shape of this map.
+    pub fn field(&self, seed: u64, params: WaveParams) -> Field {
+        let mut field = Field::with_size(self.width(), self.height(), seed, self.params(params));
+        for y in 0..self.height() {
+            for x in 0..self.width() {
+                let depth = self.depth(x, y);
+                if depth != DEFAULT_DEPTH {
+                    field.set_depth(x, y, depth);
+                }
+                field.set_damping(x, y, self.damping(x, y));
+            }
+        }
+        field
+    }
+
+    /// Moves each player to their spawn on this map, good as new.
+    pub fn place(&self, players: &mut [Player]) {
+        assert!(players.len() <= self.max_players(),
+                "{} only has room for {} players, not {}",
+                self.name,
+                self.max_players(),
+                players.len());
+        for (player, &spawn) in players.iter_mut().zip(&self.spawns) {
+            player.spawn = spawn;
+            player.respawn();
+        }
+    }
+}
+
+impl Default for Map {
+    fn default() -> Self {
+        Map::open_sea()
+    }
+}
+
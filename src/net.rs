@@ -0,0 +1,520 @@
+//! Two players on two machines, over UDP, with rollback.
+//!
+//! Both ends run the whole simulation.  Every tick each end sends the
+//! other what its own player is doing, and carries straight on guessing
+//! that the other player is still steering the way they last were.  When
+//! their real input turns up and the guess was wrong, the world is put
+//! back the way it was on that tick and played forward again with the
+//! right input.  The simulation is deterministic, so both ends always
+//! end up in the same place.
+//!
+//! One end hosts, which picks the seed, and the other joins it.  Both
+//! ends need the same map and wave params; the host says which it has
+//! when it welcomes the other end, which won't join if they differ.
+
+use std::collections::VecDeque;
+use std::io;
+use std::net::{SocketAddr, ToSocketAddrs, UdpSocket};
+use std::time::{Duration, Instant};
+
+use map::Map;
+use params::WaveParams;
+use replay::Input;
+use world::World;
+
+/// Network matches are always one against one.
+pub const NET_PLAYERS: usize = 2;
+
+/// How many ticks one end will run on guesses before it stops and
+/// waits for the other to catch up.
+pub const MAX_ROLLBACK: usize = 8;
+
+// Every packet carries all the inputs the other end hasn't said it has
+// yet, so lost packets don't matter, up to this many.
+const MAX_INPUTS_PER_PACKET: usize = 64;
+
+// How long joining waits for the host to answer before giving up.
+const JOIN_TIMEOUT: u64 = 10;
+
+const MAGIC: &[u8] = b"FW";
+const HELLO: u8 = 0;
+const WELCOME: u8 = 1;
+const INPUTS: u8 = 2;
+
+// Bytes in one FrameInput on the wire.
+const INPUT_SIZE: usize = 5;
+
+/// Everything one player did on one tick.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Default)]
+pub struct FrameInput {
+    /// As in `Input::Steer`.
+    pub thrust: i16,
+    pub turn: i16,
+    pub jump: bool,
+    /// Wants the next round to start.
+    pub reset: bool,
+}
+
+impl FrameInput {
+    /// What the other player is guessed to be doing on the tick after
+    /// this: still steering the same way, but not pressing anything.
+    fn predict(&self) -> FrameInput {
+        FrameInput {
+            thrust: self.thrust,
+            turn: self.turn,
+            jump: false,
+            reset: false,
+        }
+    }
+
+    fn apply(&self, world: &mut World, player: usize) {
+        world.apply_input(Input::Steer(player, self.thrust, self.turn));
+        if self.jump {
+            world.apply_input(Input::Jump(player));
+        }
+        // Both players ask for the next round once they've seen who won,
+        // so only the first one to get there counts.
+        if self.reset && world.outcome().is_some() {
+            world.apply_input(Input::Reset);
+        }
+    }
+
+    fn write(&self, out: &mut Vec<u8>) {
+        write_u16(out, self.thrust as u16);
+        write_u16(out, self.turn as u16);
+        out.push(self.jump as u8 | (self.reset as u8) << 1);
+    }
+
+    fn read(bytes: &[u8]) -> FrameInput {
+        FrameInput {
+            thrust: read_u16(&bytes[0..2]) as i16,
+            turn: read_u16(&bytes[2..4]) as i16,
+            jump: bytes[4] & 1 != 0,
+            reset: bytes[4] & 2 != 0,
+        }
+    }
+}
+
+fn write_u16(out: &mut Vec<u8>, n: u16) {
+    out.push(n as u8);
+    out.push((n >> 8) as u8);
+}
+
+fn write_u32(out: &mut Vec<u8>, n: u32) {
+    write_u16(out, n as u16);
+    write_u16(out, (n >> 16) as u16);
+}
+
+fn write_u64(out: &mut Vec<u8>, n: u64) {
+    write_u32(out, n as u32);
+    write_u32(out, (n >> 32) as u32);
+}
+
+fn read_u16(bytes: &[u8]) -> u16 {
+    bytes[0] as u16 | (bytes[1] as u16) << 8
+}
+
+fn read_u32(bytes: &[u8]) -> u32 {
+    read_u16(&bytes[0..2]) as u32 | (read_u16(&bytes[2..4]) as u32) << 16
+}
+
+fn read_u64(bytes: &[u8]) -> u64 {
+    read_u32(&bytes[0..4]) as u64 | (read_u32(&bytes[4..8]) as u64) << 32
+}
+
+fn packet(kind: u8) -> Vec<u8> {
+    let mut p = MAGIC.to_vec();
+    p.push(kind);
+    p
+}
+
+/// Splits a packet into its kind and the rest, if it's one of ours.
+fn open_packet(bytes: &[u8]) -> Option<(u8, &[u8])> {
+    if bytes.len() < 3 || &bytes[0..2] != MAGIC {
+        return None;
+    }
+    Some((bytes[2], &bytes[3..]))
+}
+
+// Says what an end is playing on: a HELLO with just the map and params
+// checksums, or a WELCOME with the seed in front of them.
+fn setup_packet(kind: u8, seed: Option<u64>, map: u64, params: u64) -> Vec<u8> {
+    let mut p = packet(kind);
+    if let Some(seed) = seed {
+        write_u64(&mut p, seed);
+    }
+    write_u64(&mut p, map);
+    write_u64(&mut p, params);
+    p
+}
+
+// Whether a setup body's checksums, after any seed, match ours.
+fn same_setup(body: &[u8], map: u64, params: u64) -> Result<(), &'static str> {
+    if read_u64(&body[..8]) != map {
+        Err("the host is playing on a different map")
+    } else if read_u64(&body[8..16]) != params {
+        Err("the host has different wave params")
+    } else {
+        Ok(())
+    }
+}
+
+// Errors from a UDP socket that just mean nothing's there to read, or
+// that the other end isn't listening yet.
+fn harmless(e: &io::Error) -> bool {
+    match e.kind() {
+        io::ErrorKind::WouldBlock |
+        io::ErrorKind::TimedOut |
+        io::ErrorKind::ConnectionRefused |
+        io::ErrorKind::ConnectionReset => true,
+        _ => false,
+    }
+}
+
+/// One end of a network match.  The world itself belongs to whoever's
+/// running the match; this keeps the inputs in step and the saved
+/// states to roll back to.
+pub struct NetSession {
+    socket: UdpSocket,
+    peer: Option<SocketAddr>,
+    local: usize,
+    seed: u64,
+    // What the match is played on, which the other end has to agree on.
+    map_checksum: u64,
+    params: WaveParams,
+    // Our player's inputs, by tick.
+    local_inputs: Vec<FrameInput>,
+    // The other player's inputs, by tick, for as far as we've heard.
+    remote_inputs: Vec<FrameInput>,
+    // What our player is doing, to go in on the next tick.
+    next_local: FrameInput,
+    // The world at the start of each tick from `saved_from` on.
+    saved: VecDeque<World>,
+    saved_from: usize,
+    // How many of our inputs the other end has.
+    peer_has: usize,
+    /// How many times a wrong guess has been played back, for
+    /// seeing how well things are going.
+    pub rollbacks: usize,
+}
+
+impl NetSession {
+    /// Starts a session on an already bound socket.  `peer` is where the
+    /// other end is, if it's known; otherwise it's whoever talks first.
+    pub fn new(socket: UdpSocket,
+               local: usize,
+               peer: Option<SocketAddr>,
+               seed: u64,
+               map: &Map,
+               params: WaveParams)
+               -> io::Result<NetSession> {
+        socket.set_read_timeout(None)?;
+        socket.set_nonblocking(true)?;
+        Ok(NetSession {
+            socket: socket,
+            peer: peer,
+            local: local,
+            seed: seed,
+            map_checksum: map.checksum(),
+            params: params,
+            local_inputs: Vec::new(),
+            remote_inputs: Vec::new(),
+            next_local: FrameInput::default(),
+            saved: VecDeque::new(),
+            saved_from: 0,
+            peer_has: 0,
+            rollbacks: 0,
+        })
+    }
+
+    /// Waits on `socket` for somebody to join, and hosts a match with
+    /// them as player 2 on the given seed, map and params.
+    pub fn host(socket: UdpSocket,
+                seed: u64,
+                map: &Map,
+                params: WaveParams)
+                -> io::Result<NetSession> {
+        socket.set_nonblocking(false)?;
+        socket.set_read_timeout(None)?;
+        let (map_checksum, params_checksum) = (map.checksum(), params.checksum());
+        let mut buf = [0; 512];
+        loop {
+            let (len, from) = match socket.recv_from(&mut buf) {
+                Ok(got) => got,
+                Err(ref e) if harmless(e) => continue,
+                Err(e) => return Err(e),
+            };
+            if let Some((HELLO, body)) = open_packet(&buf[..len]) {
+                // Somebody on a different sea still gets told what we're
+                // on, so they know why they can't join.
+                if body.len() < 16 || same_setup(body, map_checksum, params_checksum).is_err() {
+                    let welcome = setup_packet(WELCOME, Some(seed), map_checksum, params_checksum);
+                    socket.send_to(&welcome, from)?;
+                    continue;
+                }
+                let mut session = NetSession::new(socket, 0, Some(from), seed, map, params)?;
+                session.welcome()?;
+                return Ok(session);
+            }
+        }
+    }
+
+    /// Joins whoever's hosting at `addr`, as player 2.  Refuses to if
+    /// the host is playing on a different map or with different params.
+    pub fn join<A: ToSocketAddrs>(socket: UdpSocket,
+                                  addr: A,
+                                  map: &Map,
+                                  params: WaveParams)
+                                  -> io::Result<NetSession> {
+        let host = match addr.to_socket_addrs()?.next() {
+            Some(host) => host,
+            None => return Err(io::Error::new(io::ErrorKind::InvalidInput, "no address to join")),
+        };
+        socket.set_nonblocking(false)?;
+        socket.set_read_timeout(Some(Duration::from_millis(250)))?;
+        let (map_checksum, params_checksum) = (map.checksum(), params.checksum());
+        let hello = setup_packet(HELLO, None, map_checksum, params_checksum);
+        let start = Instant::now();
+        let mut buf = [0; 512];
+        while start.elapsed() < Duration::from_secs(JOIN_TIMEOUT) {
+            socket.send_to(&hello, host)?;
+            let (len, from) = match socket.recv_from(&mut buf) {
+                Ok(got) => got,
+                Err(ref e) if harmless(e) => continue,
+                Err(e) => return Err(e),
+            };
+            if from != host {
+                continue;
+            }
+            if let Some((WELCOME, body)) = open_packet(&buf[..len]) {
+                if body.len() < 24 {
+                    continue;
+                }
+                if let Err(why) = same_setup(&body[8..], map_checksum, params_checksum) {
+                    return Err(io::Error::new(io::ErrorKind::InvalidData, why));
+                }
+                return NetSession::new(socket, 1, Some(host), read_u64(body), map, params);
+            }
+        }
+        Err(io::Error::new(io::ErrorKind::TimedOut, "nobody answered"))
+    }
+
+    /// Which player is on this end.
+    pub fn local_player(&self) -> usize {
+        self.local
+    }
+
+    /// The other end's player.
+    pub fn remote_player(&self) -> usize {
+        1 - self.local
+    }
+
+    /// The seed the match is being played on.
+    pub fn seed(&self) -> u64 {
+        self.seed
+    }
+
+    /// The wave params both ends agreed on.  They can't change during
+    /// the match, since the other end wouldn't know.
+    pub fn params(&self) -> WaveParams {
+        self.params
+    }
+
+    pub fn local_addr(&self) -> io::Result<SocketAddr> {
+        self.socket.local_addr()
+    }
+
+    /// The last tick both players' inputs are known up to.  Everything
+    /// before it is settled and won't be rolled back.
+    pub fn confirmed_frame(&self, world: &World) -> usize {
+        usize::min(self.remote_inputs.len(), world.frame)
+    }
+
+    /// The world as it was at `confirmed_frame()`, which nothing the
+    /// other end sends can change any more.  Who won has to come from
+    /// this, or a rollback could take a win back after it's been scored.
+    pub fn confirmed_world<'a>(&'a self, world: &'a World) -> &'a World {
+        let confirmed = self.confirmed_frame(world);
+        if confirmed < self.saved_from {
+            return world;
+        }
+        self.saved.get(confirmed - self.saved_from).unwrap_or(world)
+    }
+
+    /// Takes an input from our player, for the next tick.  Inputs for
+    /// anybody else are ignored.
+    pub fn push_input(&mut self, input: Input) {
+        match input {
+            Input::Steer(player, thrust, turn) if player == self.local => {
+                self.next_local.thrust = thrust;
+                self.next_local.turn = turn;
+            }
+            Input::Jump(player) if player == self.local => self.next_local.jump = true,
+            Input::Reset => self.next_local.reset = true,
+            _ => (),
+        }
+    }
+
+    /// Runs `world` on by one tick, unless we're too far ahead of the
+    /// other end, in which case it waits.  Returns whether it ran.
+    pub fn advance(&mut self, world: &mut World) -> io::Result<bool> {
+        self.receive(world)?;
+        if world.frame >= self.confirmed_frame(world) + MAX_ROLLBACK {
+            self.send()?;
+            return Ok(false);
+        }
+        self.local_inputs.push(self.next_local);
+        self.next_local.jump = false;
+        self.next_local.reset = false;
+        self.step(world);
+        self.forget_settled(world);
+        self.send()?;
+        Ok(true)
+    }
+
+    /// Catches up with the other end without running any further,
+    /// rolling back if need be.
+    pub fn poll(&mut self, world: &mut World) -> io::Result<()> {
+        self.receive(world)?;
+        self.forget_settled(world);
+        self.send()
+    }
+
+    fn remote_input(&self, frame: usize) -> FrameInput {
+        match self.remote_inputs.get(frame) {
+            Some(&input) => input,
+            None => self.remote_inputs.last().map_or(FrameInput::default(), |i| i.predict()),
+        }
+    }
+
+    fn step(&mut self, world: &mut World) {
+        let frame = world.frame;
+        if self.saved.is_empty() {
+            self.saved_from = frame;
+        }
+        self.saved.push_back(world.clone());
+        let mut inputs = [FrameInput::default(); NET_PLAYERS];
+        inputs[self.local] = self.local_inputs[frame];
+        inputs[self.remote_player()] = self.remote_input(frame);
+        for (player, input) in inputs.iter().enumerate() {
+            input.apply(world, player);
+        }
+        world.update();
+    }
+
+    // Drops saved states from before the last tick both inputs are
+    // known for, since nothing can go back that far any more.
+    fn forget_settled(&mut self, world: &World) {
+        let confirmed = self.confirmed_frame(world);
+        while self.saved_from < confirmed && !self.saved.is_empty() {
+            self.saved.pop_front();
+            self.saved_from += 1;
+        }
+    }
+
+    // Puts the world back to the start of `frame` and plays it forward
+    // again to where it was.
+    fn roll_back(&mut self, world: &mut World, frame: usize) {
+        let now = world.frame;
+        let index = frame - self.saved_from;
+        *world = self.saved[index].clone();
+        self.saved.truncate(index);
+        while world.frame < now {
+            self.step(world);
+        }
+        self.rollbacks += 1;
+    }
+
+    fn receive(&mut self, world: &mut World) -> io::Result<()> {
+        let mut buf = [0; 1024];
+        // The first tick a guess turned out wrong on, if any.
+        let mut wrong = None;
+        loop {
+            let (len, from) = match self.socket.recv_from(&mut buf) {
+                Ok(got) => got,
+                Err(ref e) if harmless(e) => break,
+                Err(e) => return Err(e),
+            };
+            if self.peer.is_none() {
+                self.peer = Some(from);
+            }
+            if Some(from) != self.peer {
+                continue;
+            }
+            match open_packet(&buf[..len]) {
+                // They missed the welcome.
+                Some((HELLO, _)) => self.welcome()?,
+                Some((INPUTS, body)) => {
+                    if let Some(frame) = self.read_inputs(body, world.frame) {
+                        wrong = Some(wrong.map_or(frame, |w| usize::min(w, frame)));
+                    }
+                }
+                _ => (),
+            }
+        }
+        if let Some(frame) = wrong {
+            self.roll_back(world, frame);
+        }
+        Ok(())
+    }
+
+    // Takes in the other end's inputs, returning the first tick before
+    // `now` we guessed wrong for.
+    fn read_inputs(&mut self, body: &[u8], now: usize) -> Option<usize> {
+        if body.len() < 9 {
+            return None;
+        }
+        let ack = read_u32(&body[0..4]) as usize;
+        let start = read_u32(&body[4..8]) as usize;
+        let count = body[8] as usize;
+        let inputs = &body[9..];
+        if inputs.len() < count * INPUT_SIZE {
+            return None;
+        }
+        self.peer_has = usize::max(self.peer_has, usize::min(ack, self.local_inputs.len()));
+
+        let mut wrong = None;
+        for i in 0..count {
+            let frame = start + i;
+            // Anything we already have, or anything after a gap.
+            if frame != self.remote_inputs.len() {
+                continue;
+            }
+            let input = FrameInput::read(&inputs[i * INPUT_SIZE..]);
+            if frame < now && wrong.is_none() && input != self.remote_input(frame) {
+                wrong = Some(frame);
+            }
+            self.remote_inputs.push(input);
+        }
+        wrong
+    }
+
+    fn welcome(&mut self) -> io::Result<()> {
+        let p = setup_packet(WELCOME, Some(self.seed), self.map_checksum, self.params.checksum());
+        self.send_packet(&p)
+    }
+
+    fn send(&mut self) -> io::Result<()> {
+        let start = self.peer_has;
+        let end = usize::min(self.local_inputs.len(), start + MAX_INPUTS_PER_PACKET);
+        let mut p = packet(INPUTS);
+        write_u32(&mut p, self.remote_inputs.len() as u32);
+        write_u32(&mut p, start as u32);
+        p.push((end - start) as u8);
+        for input in &self.local_inputs[start..end] {
+            input.write(&mut p);
+        }
+        self.send_packet(&p)
+    }
+
+    fn send_packet(&self, p: &[u8]) -> io::Result<()> {
+        if let Some(peer) = self.peer {
+            match self.socket.send_to(p, peer) {
+                Err(ref e) if harmless(e) => (),
+                Err(e) => return Err(e),
+                Ok(_) => (),
+            }
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,302 @@
+//! Tunable physics numbers, loaded from a TOML file so
+//! the ocean can be tweaked without recompiling.
+
+use std::error::Error;
+use std::fmt;
+use std::fs::{self, File};
+use std::io::{self, Read};
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+use std::time::SystemTime;
+
+use toml;
+
+use field::{Boundary, MAX_DEPTH};
+use snapshot::{SnapshotWriter, SnapshotReader, SnapshotError};
+
+/// Where the game looks for wave parameters by default.
+pub const DEFAULT_PARAMS_FILE: &str = "resources/wave_params.toml";
+
+/// The hardest a cell's neighbors can pull on it, per unit of its own
+/// height.  That happens for a wave that flips sign every column: the
+/// 2 side neighbors and 4 diagonals are each 2 away, so the pull is
+/// 2 * 2 + 4 * 2 / sqrt(2).
+const MAX_LAPLACIAN: f32 = 4.0 + 4.0 * ::std::f32::consts::SQRT_2;
+
+#[derive(Debug, Copy, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct WaveParams {
+    /// How far each cell's position moves along its velocity per tick.
+    pub dt: f32,
+    /// How strongly each cell is affected by its neighbors.
+    /// Higher numbers mean weaker.
+    pub surface_tension: f32,
+    /// Velocity and position are multiplied by this every tick.
+    /// 0.98 makes waves go forever, 0.97 makes them just kind of go plonk.
+    pub decay_factor: f32,
+    /// How hard each cell gets pulled back towards flat water.
+    pub restoring_force: f32,
+    /// Waves taller than this are drawn as dangerous crests.
+    pub flip_threshold: f32,
+    /// What the edges of the field do to waves.
+    pub boundary: Boundary,
+    /// With an absorbing boundary, how many cells in from the edge
+    /// waves start getting damped.
+    pub sponge_width: usize,
+    /// With an absorbing boundary, the fraction of a wave removed each
+    /// tick at the very edge.  Falls off to nothing at `sponge_width`.
+    pub sponge_damping: f32,
+}
+
+impl Default for WaveParams {
+    fn default() -> Self {
+        WaveParams {
+            dt: 0.01,
+            surface_tension: 4.0,
+            decay_factor: 0.99,
+            restoring_force: 0.05,
+            flip_threshold: 0.1,
+            boundary: Boundary::Reflecting,
+            sponge_width: 8,
+            sponge_damping: 0.2,
+        }
+    }
+}
+
+#[derive(Debug)]
+pub enum ParamsError {
+    Io(io::Error),
+    Parse(toml::de::Error),
+    /// The params would make the field blow up; holds why.
+    Unstable(String),
+}
+
+impl fmt::Display for ParamsError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            ParamsError::Io(ref e) => write!(f, "could not read params: {}", e),
+            ParamsError::Parse(ref e) => write!(f, "could not parse params: {}", e),
+            ParamsError::Unstable(ref why) => write!(f, "params are unstable, {}", why),
+        }
+    }
+}
+
+impl Error for ParamsError {
+    fn description(&self) -> &str {
+        match *self {
+            ParamsError::Io(_) => "could not read params",
+            ParamsError::Parse(_) => "could not parse params",
+            ParamsError::Unstable(_) => "params are unstable",
+        }
+    }
+}
+
+impl From<io::Error> for ParamsError {
+    fn from(e: io::Error) -> Self {
+        ParamsError::Io(e)
+    }
+}
+
+impl From<toml::de::Error> for ParamsError {
+    fn from(e: toml::de::Error) -> Self {
+        ParamsError::Parse(e)
+    }
+}
+
+impl FromStr for WaveParams {
+    type Err = ParamsError;
+
+    /// Parses params from TOML.  Anything left out keeps its default.
+    /// Params that fail `is_stable()` are rejected.
+    fn from_str(s: &str) -> Result<WaveParams, ParamsError> {
+        let params: WaveParams = toml::from_str(s)?;
+        if let Some(why) = params.instability() {
+            return Err(ParamsError::Unstable(why));
+        }
+        Ok(params)
+    }
+}
+
+impl WaveParams {
+    /// The field solver is semi-implicit (symplectic) Euler: each tick a
+    /// cell pulled back with stiffness `k` does `v -= k * u`, then
+    /// `u += dt * v`.  That step has determinant 1 and trace `2 - dt * k`,
+    /// so like leapfrog it only stays bounded while `dt * k <= 4` (that
+    /// is, `h^2 * w^2 <= 4`) for the stiffest cell the grid can hold,
+    /// which is in the deepest water.  There `dt * k =
+    /// dt * (MAX_LAPLACIAN * MAX_DEPTH / surface_tension + restoring_force)`,
+    /// so this returns that divided by 4: anything over 1 blows up.
+    ///
+    /// The defaults come to about 0.012, so there's plenty of headroom.
+    pub fn courant_number(&self) -> f32 {
+        self.dt * (MAX_LAPLACIAN * MAX_DEPTH / self.surface_tension + self.restoring_force) / 4.0
+    }
+
+    pub fn is_stable(&self) -> bool {
+        self.instability().is_none()
+    }
+
+    /// Why the params would make the field blow up, if they would.
+    /// Besides the courant number, waves grow without end if time runs
+    /// backwards, cells get pushed away from flat water, or the decay
+    /// adds to them rather than taking away.
+    pub fn instability(&self) -> Option<String> {
+        // Written so that NaNs fail too.
+        let within = |x: f32, low: f32, high: f32| x >= low && x <= high;
+        if !(self.dt > 0.0) {
+            Some(format!("dt has to be over 0, not {}", self.dt))
+        } else if !(self.surface_tension > 0.0) {
+            Some(format!("surface_tension has to be over 0, not {}", self.surface_tension))
+        } else if !(self.restoring_force >= 0.0) {
+            Some(format!("restoring_force can't be negative, but it's {}", self.restoring_force))
+        } else if !within(self.decay_factor, 0.0, 1.0) {
+            Some(format!("decay_factor has to be from 0 to 1, not {}", self.decay_factor))
+        } else if !within(self.sponge_damping, 0.0, 1.0) {
+            Some(format!("sponge_damping has to be from 0 to 1, not {}", self.sponge_damping))
+        } else if !(self.courant_number() <= 1.0) {
+            Some(format!("courant number {} is over 1", self.courant_number()))
+        } else {
+            None
+        }
+    }
+
+    /// A number that changes if any of the params do, for making sure
+    /// two ends of a network match are on the same sea.
+    pub fn checksum(&self) -> u64 {
+        let mut w = SnapshotWriter::new();
+        self.write_snapshot(&mut w);
+        // FNV-1a, like `Map::checksum()`.
+        w.finish().iter().fold(0xcbf2_9ce4_8422_2325, |hash, &byte| {
+            (hash ^ byte as u64).wrapping_mul(0x0100_0000_01b3)
+        })
+    }
+
+    pub fn load<P: AsRef<Path>>(path: P) -> Result<WaveParams, ParamsError> {
+        let mut s = String::new();
+        File::open(path)?.read_to_string(&mut s)?;
+        WaveParams::from_str(&s)
+    }
+
+    pub fn write_snapshot(&self, w: &mut SnapshotWriter) {
+        w.f32(self.dt);
+        w.f32(self.surface_tension);
+        w.f32(self.decay_factor);
+        w.f32(self.restoring_force);
+        w.f32(self.flip_threshold);
+        w.u8(match self.boundary {
+            Boundary::Reflecting => 0,
+            Boundary::Absorbing => 1,
+            Boundary::Periodic => 2,
+            Boundary::FixedZero => 3,
+        });
+        w.usize(self.sponge_width);
+        w.f32(self.sponge_damping);
+    }
+
+    pub fn read_snapshot(r: &mut SnapshotReader) -> Result<WaveParams, SnapshotError> {
+        let params = WaveParams {
+            dt: r.f32()?,
+            surface_tension: r.f32()?,
+            decay_factor: r.f32()?,
+            restoring_force: r.f32()?,
+            flip_threshold: r.f32()?,
+            boundary: match r.u8()? {
+                0 => Boundary::Reflecting,
+                1 => Boundary::Absorbing,
+                2 => Boundary::Periodic,
+                3 => Boundary::FixedZero,
+                _ => return Err(SnapshotError::Invalid("boundary")),
+            },
+            sponge_width: r.usize()?,
+            sponge_damping: r.f32()?,
+        };
+        if !params.is_stable() {
+            return Err(SnapshotError::Invalid("params"));
+        }
+        Ok(params)
+    }
+}
+
+/// Ready-made kinds of sea to pick from in game.  Each one is the
+/// params file with a few numbers changed, so it still follows edits.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum Preset {
+    /// Just what the file says.
+    File,
+    /// Waves die off quickly.
+    Calm,
+    /// Waves hang around and pile up.
+    Stormy,
+}
+
+impl Preset {
+    pub fn all() -> [Preset; 3] {
+        [Preset::File, Preset::Calm, Preset::Stormy]
+    }
+
+    pub fn name(&self) -> &'static str {
+        match *self {
+            Preset::File => "From file",
+            Preset::Calm => "Calm",
+            Preset::Stormy => "Stormy",
+        }
+    }
+
+    /// The preset's params, starting from `base`.
+    pub fn apply(&self, base: WaveParams) -> WaveParams {
+        match *self {
+            Preset::File => base,
+            Preset::Calm => {
+                WaveParams {
+                    decay_factor: 0.97,
+                    restoring_force: 0.08,
+                    ..base
+                }
+            }
+            Preset::Stormy => {
+                WaveParams {
+                    decay_factor: 0.995,
+                    restoring_force: 0.03,
+                    ..base
+                }
+            }
+        }
+    }
+}
+
+/// Keeps an eye on a params file so it can be reloaded
+/// while the game is running.
+pub struct ParamsWatcher {
+    path: PathBuf,
+    modified: Option<SystemTime>,
+}
+
+impl ParamsWatcher {
+    pub fn new<P: AsRef<Path>>(path: P) -> Self {
+        let path = path.as_ref().to_path_buf();
+        let modified = modified_time(&path);
+        ParamsWatcher {
+            path: path,
+            modified: modified,
+        }
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// Returns freshly loaded params if the file has changed since
+    /// the last time this was called, or `None` if it hasn't.
+    pub fn poll(&mut self) -> Option<Result<WaveParams, ParamsError>> {
+        let modified = modified_time(&self.path);
+        if modified.is_none() || modified == self.modified {
+            return None;
+        }
+        self.modified = modified;
+        Some(WaveParams::load(&self.path))
+    }
+}
+
+fn modified_time(path: &Path) -> Option<SystemTime> {
+    fs::metadata(path).and_then(|m| m.modified()).ok()
+}
@@ -0,0 +1,239 @@
+//! Who's in a match and how each of them is set up.
+
+use std::f32::consts::PI;
+
+use bot::Difficulty;
+use ship::{Ship, ShipControls};
+use snapshot::{SnapshotWriter, SnapshotReader, SnapshotError};
+use world::FlipCause;
+
+pub const MIN_PLAYERS: usize = 2;
+pub const MAX_PLAYERS: usize = 8;
+
+/// Where a ship starts a round, in screen pixels.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct Spawn {
+    pub x: f32,
+    pub y: f32,
+    pub bearing: f32,
+}
+
+impl Spawn {
+    pub fn new(x: f32, y: f32, bearing: f32) -> Self {
+        Spawn {
+            x: x,
+            y: y,
+            bearing: bearing,
+        }
+    }
+}
+
+/// What's steering a ship.  The simulation doesn't care; this is for
+/// whatever is turning keys and buttons into `Input`s.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum Control {
+    /// One of the keyboard layouts, numbered from 0.
+    Keyboard(usize),
+    /// A gamepad, numbered in the order they were plugged in.
+    Gamepad(usize),
+    /// The computer.
+    Bot(Difficulty),
+    /// Somebody at the other end of a network match.
+    Remote,
+    /// Nobody.
+    Nobody,
+}
+
+#[derive(Clone)]
+pub struct Player {
+    pub ship: Ship,
+    /// Sprite tint, and colour on scoreboards and such.
+    pub colour: (u8, u8, u8),
+    /// 1.0 if this ship's splashes make crests, -1.0 if they make troughs.
+    pub polarity: f32,
+    pub spawn: Spawn,
+    pub control: Control,
+    /// What the ship will be told to do next tick.
+    pub controls: ShipControls,
+    /// The tick the ship went over on and why, once it has.
+    pub flipped: Option<(usize, FlipCause)>,
+    /// The last tick somebody rammed the ship hard, and who.
+    pub rammed: Option<(usize, usize)>,
+}
+
+impl Player {
+    pub fn new(spawn: Spawn, colour: (u8, u8, u8), polarity: f32, control: Control) -> Self {
+        Player {
+            ship: spawn_ship(&spawn),
+            colour: colour,
+            polarity: polarity,
+            spawn: spawn,
+            control: control,
+            controls: ShipControls::default(),
+            flipped: None,
+            rammed: None,
+        }
+    }
+
+    /// Puts the ship back at its spawn point, good as new.
+    pub fn respawn(&mut self) {
+        self.ship = spawn_ship(&self.spawn);
+        self.controls = ShipControls::default();
+        self.flipped = None;
+        self.rammed = None;
+    }
+
+    pub fn afloat(&self) -> bool {
+        !self.ship.flipped
+    }
+
+    /// Saves everything but `control`, which isn't part of the simulation.
+    pub fn write_snapshot(&self, w: &mut SnapshotWriter) {
+        w.f32(self.spawn.x);
+        w.f32(self.spawn.y);
+        w.f32(self.spawn.bearing);
+        let (r, g, b) = self.colour;
+        w.u8(r);
+        w.u8(g);
+        w.u8(b);
+        w.f32(self.polarity);
+        w.f32(self.controls.thrust);
+        w.f32(self.controls.turn);
+        w.bool(self.controls.jump);
+        match self.flipped {
+            None => w.u8(0),
+            Some((tick, cause)) => {
+                let (kind, by) = match cause {
+                    FlipCause::Waves => (1, 0),
+                    FlipCause::Rammed(by) => (2, by),
+                    FlipCause::Stomped(by) => (3, by),
+                };
+                w.u8(kind);
+                w.usize(tick);
+                w.usize(by);
+            }
+        }
+        match self.rammed {
+            None => w.u8(0),
+            Some((tick, by)) => {
+                w.u8(1);
+                w.usize(tick);
+                w.usize(by);
+            }
+        }
+        self.ship.write_snapshot(w);
+    }
+
+    /// Reads a player back, with nobody steering it.
+    pub fn read_snapshot(r: &mut SnapshotReader) -> Result<Player, SnapshotError> {
+        let spawn = Spawn::new(r.f32()?, r.f32()?, r.f32()?);
+        let colour = (r.u8()?, r.u8()?, r.u8()?);
+        let polarity = r.f32()?;
+        let controls = ShipControls {
+            thrust: r.f32()?,
+            turn: r.f32()?,
+            jump: r.bool()?,
+        };
+        let flipped = match r.u8()? {
+            0 => None,
+            kind => {
+                let tick = r.usize()?;
+                let by = r.usize()?;
+                let cause = match kind {
+                    1 => FlipCause::Waves,
+                    2 => FlipCause::Rammed(by),
+                    3 => FlipCause::Stomped(by),
+                    _ => return Err(SnapshotError::Invalid("flip cause")),
+                };
+                Some((tick, cause))
+            }
+        };
+        let rammed = match r.u8()? {
+            0 => None,
+            1 => Some((r.usize()?, r.usize()?)),
+            _ => return Err(SnapshotError::Invalid("ram")),
+        };
+        Ok(Player {
+            ship: Ship::read_snapshot(r)?,
+            colour: colour,
+            polarity: polarity,
+            spawn: spawn,
+            control: Control::Nobody,
+            controls: controls,
+            flipped: flipped,
+            rammed: rammed,
+        })
+    }
+}
+
+fn spawn_ship(spawn: &Spawn) -> Ship {
+    let mut ship = Ship::new(spawn.x as i32, spawn.y as i32);
+    ship.bearing = spawn.bearing;
+    ship
+}
+
+// The first two are where the ships have always started; the rest
+// fill in the corners and sides, everybody facing into the middle.
+const SPAWNS: [(f32, f32, f32); MAX_PLAYERS] = [(100.0, 100.0, PI),
+                                                (600.0, 400.0, 0.0),
+                                                (700.0, 100.0, PI),
+                                                (100.0, 500.0, 0.0),
+                                                (400.0, 100.0, PI),
+                                                (400.0, 500.0, 0.0),
+                                                (100.0, 300.0, PI / 2.0),
+                                                (700.0, 300.0, -PI / 2.0)];
+
+const COLOURS: [(u8, u8, u8); MAX_PLAYERS] = [(220, 40, 40),
+                                              (40, 90, 220),
+                                              (40, 180, 60),
+                                              (230, 200, 30),
+                                              (160, 60, 200),
+                                              (240, 130, 30),
+                                              (40, 200, 200),
+                                              (230, 90, 170)];
+
+/// The standard setup for `count` players: spawns around the edge,
+/// alternating crests and troughs, keyboard layouts for the first four
+/// and gamepads for the rest.
+pub fn default_players(count: usize) -> Vec<Player> {
+    assert!(count >= MIN_PLAYERS && count <= MAX_PLAYERS,
+            "need {} to {} players, not {}",
+            MIN_PLAYERS,
+            MAX_PLAYERS,
+            count);
+    (0..count)
+        .map(|i| {
+            let (x, y, bearing) = SPAWNS[i];
+            let polarity = if i % 2 == 0 { -1.0 } else { 1.0 };
+            let control = if i < 4 {
+                Control::Keyboard(i)
+            } else {
+                Control::Gamepad(i - 4)
+            };
+            Player::new(Spawn::new(x, y, bearing), COLOURS[i], polarity, control)
+        })
+        .collect()
+}
+
+/// Hands the first `pads` players a gamepad each, in order, and gives
+/// everybody else a keyboard layout, starting from the first.
+/// Bots and remote players are left alone.
+pub fn assign_controls(players: &mut [Player], pads: usize) {
+    let mut pad = 0;
+    let mut layout = 0;
+    for player in players.iter_mut() {
+        match player.control {
+            Control::Bot(_) | Control::Remote => continue,
+            _ => (),
+        }
+        player.control = if pad < pads {
+            pad += 1;
+            Control::Gamepad(pad - 1)
+        } else if layout < 4 {
+            layout += 1;
+            Control::Keyboard(layout - 1)
+        } else {
+            Control::Nobody
+        };
+    }
+}
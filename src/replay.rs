@@ -0,0 +1,329 @@
+//! Recording and playing back the inputs of a match.
+//!
+//! A replay is a plain text file: a version line, the match seed,
+//! how many players there were, the `Map::checksum()` of the map, and
+//! the wave params the match started with.  Then there's one line per
+//! input tagged with the tick it was applied on, along with any changes
+//! to the wave params, from the params file being edited or a preset
+//! being picked.
+//!
+//! ```text
+//! flipwrecked-replay 3
+//! seed 1485388800
+//! players 2
+//! map 6a4563eb780e6869
+//! params { dt = 0.01, surface_tension = 4.0, decay_factor = 0.99, ... }
+//! 120 steer 0 1000 0
+//! 164 steer 0 0 -1000
+//! 170 jump 1
+//! 300 steer 1 1000 -250
+//! 650 params { dt = 0.01, surface_tension = 3.0, decay_factor = 0.99, ... }
+//! 900 reset
+//! ```
+//!
+//! Version 1 replays, from when ships were steered with a set of held
+//! buttons, are turned into version 3 inputs as they're loaded.  The
+//! oldest of those have no `players` line, and are always 2 players.
+//! Neither they nor version 2 replays say what map or params they were
+//! played with.
+
+use std::fs::File;
+use std::io;
+use std::io::{BufRead, BufReader, Write};
+use std::path::Path;
+
+use toml;
+
+use map::Map;
+use params::WaveParams;
+use player::{MIN_PLAYERS, MAX_PLAYERS};
+
+pub const REPLAY_VERSION: u32 = 3;
+const MAGIC: &str = "flipwrecked-replay";
+
+/// Steering amounts in a `Steer` input are whole numbers,
+/// so they come out of a replay exactly as they went in.
+pub const STEER_SCALE: f32 = 1000.0;
+
+/// Everything a player can do that changes the simulation.
+/// The number is which player did it.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum Input {
+    /// Thrust and turn, as in `ShipControls`, times `STEER_SCALE`.
+    /// They stay that way until the next `Steer`.
+    Steer(usize, i16, i16),
+    Jump(usize),
+    Reset,
+}
+
+/// Steering full ahead, hard to starboard, and so on.
+pub fn steer_amount(amount: f32) -> i16 {
+    (amount * STEER_SCALE).round() as i16
+}
+
+fn invalid(msg: String) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, msg)
+}
+
+/// Wave params as a TOML inline table, so they fit on one line.
+fn params_to_line(params: &WaveParams) -> io::Result<String> {
+    let table = toml::to_string(params).map_err(|e| invalid(e.to_string()))?;
+    let fields: Vec<&str> = table.lines().filter(|l| !l.is_empty()).collect();
+    Ok(format!("{{ {} }}", fields.join(", ")))
+}
+
+#[derive(Deserialize)]
+struct ParamsLine {
+    params: WaveParams,
+}
+
+fn params_from_line(line: &str) -> Option<WaveParams> {
+    let parsed: ParamsLine = toml::from_str(&format!("params = {}", line)).ok()?;
+    if parsed.params.is_stable() {
+        Some(parsed.params)
+    } else {
+        None
+    }
+}
+
+// What comes after the word `params` on a line.
+fn after_params(line: &str) -> &str {
+    line.splitn(2, "params").nth(1).unwrap_or("")
+}
+
+impl Input {
+    fn to_line(&self) -> String {
+        match *self {
+            Input::Steer(player, thrust, turn) => format!("steer {} {} {}", player, thrust, turn),
+            Input::Jump(player) => format!("jump {}", player),
+            Input::Reset => String::from("reset"),
+        }
+    }
+
+    fn from_words(words: &[&str]) -> Option<Input> {
+        let player = || words.get(1).and_then(|w| w.parse().ok());
+        let amount = |i: usize| words.get(i).and_then(|w| w.parse().ok());
+        match words.get(0) {
+            Some(&"steer") => Some(Input::Steer(player()?, amount(2)?, amount(3)?)),
+            Some(&"jump") => Some(Input::Jump(player()?)),
+            Some(&"reset") => Some(Input::Reset),
+            _ => None,
+        }
+    }
+}
+
+/// What one player was holding down in a version 1 replay.
+#[derive(Debug, Clone, Default)]
+struct OldControls {
+    up: bool,
+    left: bool,
+    right: bool,
+    stick_thrust: i16,
+    stick_turn: i16,
+}
+
+impl OldControls {
+    fn steer(&self, player: usize) -> Input {
+        let key = |held: bool| if held { STEER_SCALE as i16 } else { 0 };
+        let scale = STEER_SCALE as i16;
+        let thrust = self.stick_thrust + key(self.up);
+        let turn = self.stick_turn + key(self.right) - key(self.left);
+        Input::Steer(player,
+                     thrust.max(0).min(scale),
+                     turn.max(-scale).min(scale))
+    }
+}
+
+/// Turns a version 1 line into an input as later versions record
+/// them, keeping track of which buttons everybody is holding down.
+fn from_old_words(words: &[&str], held: &mut Vec<OldControls>) -> Option<Input> {
+    let player = || words.get(1).and_then(|w| w.parse::<usize>().ok());
+    let down = match words.get(0) {
+        Some(&"press") => true,
+        Some(&"release") => false,
+        Some(&"steer") => {
+            let amount = |i: usize| words.get(i).and_then(|w| w.parse().ok());
+            let (player, thrust, turn) = (player()?, amount(2)?, amount(3)?);
+            if held.len() <= player {
+                held.resize(player + 1, OldControls::default());
+            }
+            held[player].stick_thrust = thrust;
+            held[player].stick_turn = turn;
+            return Some(held[player].steer(player));
+        }
+        _ => return Input::from_words(words),
+    };
+    let player = player()?;
+    if held.len() <= player {
+        held.resize(player + 1, OldControls::default());
+    }
+    match words.get(2) {
+        Some(&"up") => held[player].up = down,
+        Some(&"left") => held[player].left = down,
+        Some(&"right") => held[player].right = down,
+        _ => return None,
+    }
+    Some(held[player].steer(player))
+}
+
+/// A whole recorded match, loaded from a file.
+#[derive(Debug, Clone)]
+pub struct Replay {
+    pub seed: u64,
+    pub players: usize,
+    /// The `Map::checksum()` of the map it was played on, and the wave
+    /// params it started with.  Replays from before version 3 don't
+    /// have these.
+    pub map: Option<u64>,
+    pub params: Option<WaveParams>,
+    /// (tick, input) pairs, in the order they happened.
+    pub events: Vec<(usize, Input)>,
+    /// The ticks the wave params changed on, and what to.
+    pub param_changes: Vec<(usize, WaveParams)>,
+}
+
+impl Replay {
+    pub fn load<P: AsRef<Path>>(path: P) -> io::Result<Replay> {
+        let file = File::open(path)?;
+        let mut lines = BufReader::new(file).lines();
+
+        let header = lines.next().unwrap_or(Ok(String::new()))?;
+        let mut words = header.split_whitespace();
+        let version = if words.next() == Some(MAGIC) {
+            words.next().and_then(|v| v.parse::<u32>().ok())
+        } else {
+            None
+        };
+        let version = match version {
+            Some(v) if v >= 1 && v <= REPLAY_VERSION => v,
+            Some(v) => return Err(invalid(format!("unsupported replay version {}", v))),
+            None => return Err(invalid(String::from("not a replay file"))),
+        };
+
+        let seed_line = lines.next().unwrap_or(Ok(String::new()))?;
+        let mut words = seed_line.split_whitespace();
+        let seed = if words.next() == Some("seed") {
+            words.next().and_then(|s| s.parse().ok())
+        } else {
+            None
+        };
+        let seed = seed.ok_or_else(|| invalid(String::from("replay has no seed")))?;
+
+        let mut players = 2;
+        let mut map = None;
+        let mut params = None;
+        let mut events = Vec::new();
+        let mut param_changes = Vec::new();
+        let mut held = Vec::new();
+        for line in lines {
+            let line = line?;
+            let words: Vec<&str> = line.split_whitespace().collect();
+            if words.is_empty() {
+                continue;
+            }
+            let bad_line = || invalid(format!("bad replay line: {}", line));
+            let header = events.is_empty() && param_changes.is_empty();
+            match words[0] {
+                "players" if header => {
+                    players = words.get(1).and_then(|n| n.parse().ok()).ok_or_else(&bad_line)?;
+                    // Anything else would never get as far as playing.
+                    if players < MIN_PLAYERS || players > MAX_PLAYERS {
+                        return Err(bad_line());
+                    }
+                    continue;
+                }
+                "map" if header && version >= 3 => {
+                    let checksum = words.get(1).and_then(|n| u64::from_str_radix(n, 16).ok());
+                    map = Some(checksum.ok_or_else(&bad_line)?);
+                    continue;
+                }
+                "params" if header && version >= 3 => {
+                    params = Some(params_from_line(after_params(&line)).ok_or_else(&bad_line)?);
+                    continue;
+                }
+                _ => (),
+            }
+            let tick = words[0].parse().ok();
+            if version >= 3 && words.get(1) == Some(&"params") {
+                match (tick, params_from_line(after_params(&line))) {
+                    (Some(tick), Some(changed)) => param_changes.push((tick, changed)),
+                    _ => return Err(bad_line()),
+                }
+                continue;
+            }
+            let input = if version == 1 {
+                if words.get(1) == Some(&"reset") {
+                    held.clear();
+                }
+                from_old_words(&words[1..], &mut held)
+            } else {
+                Input::from_words(&words[1..])
+            };
+            match (tick, input) {
+                (Some(tick), Some(input)) => events.push((tick, input)),
+                _ => return Err(bad_line()),
+            }
+        }
+        if version >= 3 && (map.is_none() || params.is_none()) {
+            return Err(invalid(String::from("replay doesn't say what map or params it's on")));
+        }
+
+        Ok(Replay {
+            seed: seed,
+            players: players,
+            map: map,
+            params: params,
+            events: events,
+            param_changes: param_changes,
+        })
+    }
+
+    /// Whether the replay can be watched on `map`.  Replays too old to
+    /// say what they were played on are given the benefit of the doubt.
+    pub fn check_map(&self, map: &Map) -> Result<(), String> {
+        if self.players > map.max_players() {
+            return Err(format!("the replay has {} players, but {} only has room for {}",
+                               self.players,
+                               map.name,
+                               map.max_players()));
+        }
+        match self.map {
+            Some(checksum) if checksum != map.checksum() => {
+                Err(format!("the replay wasn't played on {}", map.name))
+            }
+            _ => Ok(()),
+        }
+    }
+}
+
+/// Writes inputs out as they happen, so a replay survives
+/// the game being killed partway through.
+pub struct ReplayWriter {
+    file: File,
+}
+
+impl ReplayWriter {
+    pub fn create<P: AsRef<Path>>(path: P,
+                                  seed: u64,
+                                  players: usize,
+                                  map: &Map,
+                                  params: &WaveParams)
+                                  -> io::Result<ReplayWriter> {
+        let mut file = File::create(path)?;
+        writeln!(file, "{} {}", MAGIC, REPLAY_VERSION)?;
+        writeln!(file, "seed {}", seed)?;
+        writeln!(file, "players {}", players)?;
+        writeln!(file, "map {:016x}", map.checksum())?;
+        writeln!(file, "params {}", params_to_line(params)?)?;
+        Ok(ReplayWriter { file: file })
+    }
+
+    pub fn record(&mut self, tick: usize, input: Input) -> io::Result<()> {
+        writeln!(self.file, "{} {}", tick, input.to_line())
+    }
+
+    /// Records the wave params changing, from `tick` on.
+    pub fn record_params(&mut self, tick: usize, params: &WaveParams) -> io::Result<()> {
+        writeln!(self.file, "{} params {}", tick, params_to_line(params)?)
+    }
+}
@@ -0,0 +1,150 @@
+//! A stack of screens sharing one window: the title, menus, the match,
+//! and whatever is laid over it.  Only the top one gets updated and
+//! sees input; the ones under it are frozen until it's popped, but can
+//! still be drawn underneath an overlay.
+//!
+//! ggez hands input events over without a `Context`, so scenes can't
+//! load anything when a key is pressed.  Instead they remember what was
+//! pressed and ask for a `Transition` from their next `update()`.
+
+use ggez;
+use ggez::GameResult;
+use ggez::game;
+use ggez::graphics;
+use ggez::event::*;
+
+use std::time::Duration;
+
+/// What the scene stack should do after a scene's update.
+pub enum Transition<S> {
+    None,
+    /// Put a new scene on top.  The current one is frozen underneath.
+    Push(Box<Scene<S>>),
+    /// Take the current scene off and go back to the one under it.
+    Pop,
+    /// Throw away every scene and start over from this one.
+    ReplaceAll(Box<Scene<S>>),
+    Quit,
+}
+
+/// One screen of the game.  `S` is whatever the scenes share, like
+/// settings and loaded fonts, which lives as long as the stack does.
+pub trait Scene<S> {
+    fn update(&mut self,
+              ctx: &mut ggez::Context,
+              shared: &mut S,
+              dt: Duration)
+              -> GameResult<Transition<S>>;
+
+    /// Draws the scene.  The screen is cleared before the first scene
+    /// is drawn and presented after the last, so scenes don't do either.
+    fn draw(&mut self, ctx: &mut ggez::Context, shared: &mut S) -> GameResult<()>;
+
+    /// Whether the scene under this one should be drawn first, for
+    /// scenes that only cover part of the screen.
+    fn draws_below(&self) -> bool {
+        false
+    }
+
+    /// Called when another scene gets pushed on top of this one, so it
+    /// can let go of anything it won't hear about while it's covered,
+    /// like keys being released.
+    fn covered(&mut self, _shared: &mut S) {}
+
+    fn key_down(&mut self, _shared: &mut S, _keycode: Keycode, _repeat: bool) {}
+    fn key_up(&mut self, _shared: &mut S, _keycode: Keycode) {}
+    fn pad_button(&mut self, _shared: &mut S, _btn: Button) {}
+    fn pad_axis(&mut self, _shared: &mut S, _axis: Axis, _value: i16) {}
+    fn mouse_down(&mut self, _shared: &mut S, _button: MouseButton, _x: i32, _y: i32) {}
+}
+
+pub struct SceneStack<S> {
+    shared: S,
+    scenes: Vec<Box<Scene<S>>>,
+}
+
+impl<S> SceneStack<S> {
+    /// Starts with `scenes`, the last one on top.
+    pub fn new(shared: S, scenes: Vec<Box<Scene<S>>>) -> Self {
+        SceneStack {
+            shared: shared,
+            scenes: scenes,
+        }
+    }
+
+    fn push(&mut self, scene: Box<Scene<S>>) {
+        if let Some(top) = self.scenes.last_mut() {
+            top.covered(&mut self.shared);
+        }
+        self.scenes.push(scene);
+    }
+}
+
+impl<S> game::EventHandler for SceneStack<S> {
+    fn update(&mut self, ctx: &mut ggez::Context, dt: Duration) -> GameResult<()> {
+        let transition = match self.scenes.last_mut() {
+            Some(scene) => scene.update(ctx, &mut self.shared, dt)?,
+            None => Transition::Quit,
+        };
+        match transition {
+            Transition::None => (),
+            Transition::Push(scene) => self.push(scene),
+            Transition::Pop => {
+                self.scenes.pop();
+            }
+            Transition::ReplaceAll(scene) => {
+                self.scenes.clear();
+                self.scenes.push(scene);
+            }
+            Transition::Quit => self.scenes.clear(),
+        }
+        if self.scenes.is_empty() {
+            ctx.quit()?;
+        }
+        Ok(())
+    }
+
+    fn draw(&mut self, ctx: &mut ggez::Context) -> GameResult<()> {
+        graphics::clear(ctx);
+        // Start from the highest scene that covers the whole screen.
+        let mut bottom = self.scenes.len().saturating_sub(1);
+        while bottom > 0 && self.scenes[bottom].draws_below() {
+            bottom -= 1;
+        }
+        for scene in &mut self.scenes[bottom..] {
+            scene.draw(ctx, &mut self.shared)?;
+        }
+        ctx.renderer.present();
+        Ok(())
+    }
+
+    fn key_down_event(&mut self, keycode: Keycode, _keymod: Mod, repeat: bool) {
+        if let Some(scene) = self.scenes.last_mut() {
+            scene.key_down(&mut self.shared, keycode, repeat);
+        }
+    }
+
+    fn key_up_event(&mut self, keycode: Keycode, _keymod: Mod, _repeat: bool) {
+        if let Some(scene) = self.scenes.last_mut() {
+            scene.key_up(&mut self.shared, keycode);
+        }
+    }
+
+    fn controller_button_down_event(&mut self, btn: Button) {
+        if let Some(scene) = self.scenes.last_mut() {
+            scene.pad_button(&mut self.shared, btn);
+        }
+    }
+
+    fn controller_axis_event(&mut self, axis: Axis, value: i16) {
+        if let Some(scene) = self.scenes.last_mut() {
+            scene.pad_axis(&mut self.shared, axis, value);
+        }
+    }
+
+    fn mouse_button_down_event(&mut self, button: MouseButton, x: i32, y: i32) {
+        if let Some(scene) = self.scenes.last_mut() {
+            scene.mouse_down(&mut self.shared, button, x, y);
+        }
+    }
+}
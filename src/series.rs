@@ -0,0 +1,140 @@
+//! Playing several rounds in a row and keeping score.
+
+use world::Outcome;
+
+/// How long a series goes on for.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum SeriesFormat {
+    /// Just the one round, win or draw.
+    Single,
+    /// Whoever wins most of `n` rounds.  Draws don't count as one of them.
+    /// With more than two players nobody may ever win most of them, so
+    /// once `n` rounds have been won, whoever has won the most takes it.
+    BestOf(usize),
+    /// Whoever wins `n` rounds first.
+    FirstTo(usize),
+}
+
+impl SeriesFormat {
+    /// The formats to pick from in the menu.
+    pub fn all() -> [SeriesFormat; 7] {
+        [SeriesFormat::Single,
+         SeriesFormat::BestOf(3),
+         SeriesFormat::BestOf(5),
+         SeriesFormat::BestOf(7),
+         SeriesFormat::FirstTo(3),
+         SeriesFormat::FirstTo(5),
+         SeriesFormat::FirstTo(10)]
+    }
+
+    /// How many rounds somebody has to win to take the series.
+    pub fn wins_needed(&self) -> usize {
+        match *self {
+            SeriesFormat::Single => 1,
+            SeriesFormat::BestOf(n) => n / 2 + 1,
+            SeriesFormat::FirstTo(n) => n,
+        }
+    }
+
+    pub fn name(&self) -> String {
+        match *self {
+            SeriesFormat::Single => String::from("Single round"),
+            SeriesFormat::BestOf(n) => format!("Best of {}", n),
+            SeriesFormat::FirstTo(n) => format!("First to {}", n),
+        }
+    }
+
+    /// Parses `single`, `bo<n>` or `ft<n>`, like `bo5` for best of 5.
+    pub fn from_name(name: &str) -> Option<SeriesFormat> {
+        let count = |n: &str| match n.parse::<usize>() {
+            Ok(n) if n > 0 => Some(n),
+            _ => None,
+        };
+        if name == "single" {
+            Some(SeriesFormat::Single)
+        } else if name.starts_with("bo") {
+            count(&name[2..]).map(SeriesFormat::BestOf)
+        } else if name.starts_with("ft") {
+            count(&name[2..]).map(SeriesFormat::FirstTo)
+        } else {
+            None
+        }
+    }
+}
+
+/// The score so far in a series.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Series {
+    pub format: SeriesFormat,
+    /// Rounds won, by player.
+    pub scores: Vec<usize>,
+    /// Rounds finished so far, draws included.
+    pub rounds: usize,
+}
+
+impl Series {
+    pub fn new(format: SeriesFormat, players: usize) -> Self {
+        Series {
+            format: format,
+            scores: vec![0; players],
+            rounds: 0,
+        }
+    }
+
+    /// Counts up a finished round.
+    pub fn record(&mut self, outcome: Outcome) {
+        if self.is_over() {
+            return;
+        }
+        self.rounds += 1;
+        if let Outcome::Winner(player) = outcome {
+            if let Some(score) = self.scores.get_mut(player) {
+                *score += 1;
+            }
+        }
+    }
+
+    /// The round being played now, or the last one once it's all over.
+    pub fn round(&self) -> usize {
+        if self.is_over() {
+            self.rounds
+        } else {
+            self.rounds + 1
+        }
+    }
+
+    /// Who took the series, if anybody has yet.
+    pub fn winner(&self) -> Option<usize> {
+        let needed = self.format.wins_needed();
+        if let Some(player) = self.scores.iter().position(|&score| score >= needed) {
+            return Some(player);
+        }
+        match self.format {
+            SeriesFormat::BestOf(n) if self.scores.iter().sum::<usize>() >= n => {
+                // Tied at the top plays on until somebody pulls ahead.
+                let best = self.scores.iter().cloned().max().unwrap_or(0);
+                if self.scores.iter().filter(|&&score| score == best).count() == 1 {
+                    self.scores.iter().position(|&score| score == best)
+                } else {
+                    None
+                }
+            }
+            _ => None,
+        }
+    }
+
+    pub fn is_over(&self) -> bool {
+        match self.format {
+            SeriesFormat::Single => self.rounds > 0,
+            _ => self.winner().is_some(),
+        }
+    }
+
+    /// Back to nothing each, for another series.
+    pub fn reset(&mut self) {
+        for score in &mut self.scores {
+            *score = 0;
+        }
+        self.rounds = 0;
+    }
+}
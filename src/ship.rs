@@ -1,38 +1,46 @@
-use ggez;
-use ggez::game;
-use ggez::event::*;
-use ggez::Context;
-use ggez::graphics;
-use ggez::GameResult;
-use ggez::graphics::Image;
-use ggez::graphics::Drawable;
-
 use na;
 use na::Vector2;
 
 use std::f32::consts;
-use std::collections::HashSet;
-
-const DRAG: f32 = 0.97;
-const RAD_TO_DEGREES: f32 = 180.0 / consts::PI;
-const FLIPPING_TIMEOUT: u32 = 10;
 
-#[derive(Debug, Copy, Clone, Hash, PartialEq, Eq)]
-pub enum Buttons {
-    Up,
-    Left,
-    Right,
-}
-const SHIP_SIZE: f32 = 128.0;
-
-// Redundant declaration, any way to use constants in main.rs?
-const WINDOW_HEIGHT: usize = 600;
-const WINDOW_WIDTH: usize = 800;
-// Again, redundant
-fn clamp(val: f32, lower: f32, upper: f32) -> f32 {
-    f32::min(f32::max(val, lower), upper)
+use clamp;
+use hull::{Footprint, HullSample};
+use snapshot::{SnapshotWriter, SnapshotReader, SnapshotError};
+
+// Rolling and pitching.  Water sloping across the hull shoves the ship
+// over, its keel pulls it back upright, and it goes over for good once
+// it tips past the capsize angle.  Ships are longer than they are wide,
+// so waves hitting the side (rolling) push much harder than waves
+// hitting the bow (pitching).
+const ROLL_COUPLING: f32 = 0.35;
+const PITCH_COUPLING: f32 = 0.1;
+const RIGHTING: f32 = 0.02;
+const ROLL_DAMPING: f32 = 0.9;
+const CAPSIZE_ANGLE: f32 = 1.0;
+// Past this the ship is drawn tipping over.
+const TIPPING_ANGLE: f32 = 0.5;
+
+/// What a ship is being told to do for one tick.  Keyboards only ever
+/// give whole numbers; sticks and bots can give anything in between.
+#[derive(Debug, Copy, Clone, PartialEq, Default)]
+pub struct ShipControls {
+    /// How hard to push forwards, from 0 to 1.
+    pub thrust: f32,
+    /// From -1 (hard to port) to 1 (hard to starboard).
+    pub turn: f32,
+    /// Start a jump, if the ship can.
+    pub jump: bool,
 }
 
+pub const SHIP_SIZE: f32 = 128.0;
+
+/// How many ticks a jump keeps a ship out of the water.
+pub const JUMP_TICKS: usize = 61;
+/// How many ticks after landing before the ship can jump again.
+pub const JUMP_COOLDOWN: usize = 30;
+/// Velocity is multiplied by this every tick.
+pub const DRAG: f32 = 0.97;
+
 fn magnitude(vec: &Vector2<f32>) -> f32 {
     (vec.x.powi(2) + vec.y.powi(2)).sqrt()
 }
@@ -42,14 +50,11 @@ fn calculate_jump_scale(index: usize) -> f32 {
     data[index / 6] * 0.75
 }
 
+#[derive(Clone)]
 pub struct Ship {
     pub location: Vector2<f32>,
     pub velocity: Vector2<f32>,
     pub angular_velocity: f32,
-    pub image: Image,
-
-    angle_image: Image,
-    sideways_image: Image,
 
     scale: f32,
     pub bearing: f32,
@@ -62,13 +67,16 @@ pub struct Ship {
     pub jumping: bool,
     pub post_jump: usize,
     pub flipped: bool,
-    flipping: u32,
-
-    keys_down: HashSet<Buttons>,
+    // Radians; positive is starboard side down.
+    roll: f32,
+    roll_velocity: f32,
+    // Radians; positive is bow down.
+    pitch: f32,
+    pitch_velocity: f32,
 }
 
 impl Ship {
-    pub fn new(start_x: i32, start_y: i32, ctx: &mut Context, filename: &str) -> Self {
+    pub fn new(start_x: i32, start_y: i32) -> Self {
         Ship {
             location: Vector2::new(start_x as f32, start_y as f32),
             velocity: Vector2::new(0.0, 0.0),
@@ -77,34 +85,48 @@ impl Ship {
 
             speed: 0.1,
 
-            image: Image::new(ctx, format!("{}.png", filename)).unwrap(),
-            angle_image: Image::new(ctx, format!("{}_tipping.png", filename)).unwrap(),
-            sideways_image: Image::new(ctx, format!("{}_sideways.png", filename)).unwrap(),
-
             turning_torque: 0.001,
             bearing: 0.0,
-            length: 128.0,
-            width: 128.0,
+            // The hull inside the 128x128 sprite.
+            length: 96.0,
+            width: 60.0,
             collider_radius: 64.0 * 1.414,
             jumping: false,
             jump_index: 0,
             post_jump: 0,
             flipped: false,
-            flipping: 0,
+            roll: 0.0,
+            roll_velocity: 0.0,
+            pitch: 0.0,
+            pitch_velocity: 0.0,
+        }
+    }
+
+    pub fn can_jump(&self) -> bool {
+        !self.flipped && !self.jumping && self.post_jump == 0
+    }
 
-            keys_down: HashSet::new(),
+    /// How many more ticks until the ship comes down, if it's jumping.
+    pub fn jump_ticks_left(&self) -> Option<usize> {
+        if self.jumping {
+            Some(JUMP_TICKS - self.jump_index)
+        } else {
+            None
         }
     }
 
     pub fn jump(&mut self) {
-        if !self.flipped && !self.jumping && self.post_jump == 0 {
+        if self.can_jump() {
             // println!("Jumping starting?");
             self.jumping = true;
             self.jump_index = 0;
         }
     }
 
-    pub fn update(&mut self) {
+    pub fn update(&mut self, controls: &ShipControls) {
+        if controls.jump {
+            self.jump();
+        }
         let speed = self.speed;
         let velocity = self.velocity;
         let mut acceleration: Vector2<f32> = na::zero();
@@ -129,24 +151,21 @@ impl Ship {
         //
         // Trying to add torque
 
-        for keycode in &self.keys_down {
-            match *keycode {
-                Buttons::Up => {
-                    let facing_vec_x = f32::cos(self.bearing - consts::PI / 2.0);
-                    let facing_vec_y = f32::sin(self.bearing - consts::PI / 2.0);
-                    let force = Vector2::new(facing_vec_x, facing_vec_y);
-                    acceleration += force;
-                }
-                Buttons::Left => {
-                    // self.bearing -= self.turning_speed;
-                    torque -= self.turning_torque;
-                }
-                Buttons::Right => {
-                    // self.bearing += self.turning_speed;
-                    torque += self.turning_torque;
-                }
-            }
+        // Wrecks don't steer.
+        let (thrust, turn) = if self.flipped {
+            (0.0, 0.0)
+        } else {
+            (clamp(controls.thrust, 0.0, 1.0), clamp(controls.turn, -1.0, 1.0))
+        };
+
+        if thrust > 0.0 {
+            let facing_vec_x = f32::cos(self.bearing - consts::PI / 2.0);
+            let facing_vec_y = f32::sin(self.bearing - consts::PI / 2.0);
+            let force = Vector2::new(facing_vec_x, facing_vec_y);
+            acceleration += force * thrust;
         }
+        // self.bearing += self.turning_speed;
+        torque += self.turning_torque * turn;
 
         // Cooldown timer that makes you invincible after jumps
         // but maybe also prevents you from jumping constantly
@@ -161,32 +180,18 @@ impl Ship {
             self.scale = calculate_jump_scale(self.jump_index);
             self.jump_index += 1;
 
-            if self.jump_index == 61 {
+            if self.jump_index == JUMP_TICKS {
                 // println!("Done jumping");
                 self.scale = 1.0;
                 self.jump_index = 0;
                 self.jumping = false;
-                self.post_jump = 30;
-            }
-        }
-
-        if self.flipping > 0 {
-            self.flipping += 1;
-            if self.flipping == FLIPPING_TIMEOUT {
-                self.flipped = true;
+                self.post_jump = JUMP_COOLDOWN;
             }
         }
 
         self.velocity += acceleration;
         self.velocity *= DRAG;
         self.location += velocity * speed as f32;
-        self.location.x = clamp(self.location.x,
-                                self.collider_radius,
-                                WINDOW_WIDTH as f32 - self.collider_radius);
-        self.location.y = clamp(self.location.y,
-                                self.collider_radius,
-                                WINDOW_HEIGHT as f32 - self.collider_radius);
-
 
         self.angular_velocity += torque;
         self.bearing += self.angular_velocity;
@@ -201,58 +206,148 @@ impl Ship {
     }
 
 
-    pub fn draw(&mut self, ctx: &mut Context) -> GameResult<()> {
-        let size = 128.0 * self.scale;
-        let half_size = size / 2.0;
-        let r = graphics::Rect::new((self.location.x - half_size) as i32,
-                                    (self.location.y - half_size) as i32,
-                                    size as u32,
-                                    size as u32);
-        // let c = graphics::Point::new((0.0 * self.scale) as i32,
-        //                             (0.0 * self.scale) as i32);
-
-        if self.flipping > 0 && self.flipping < FLIPPING_TIMEOUT {
-            self.angle_image.draw_ex(ctx,
-                                     None,
-                                     Some(r),
-                                     (self.bearing * RAD_TO_DEGREES) as f64,
-                                     None,
-                                     false,
-                                     false);
-        }
-        if self.flipped {
-            self.sideways_image.draw_ex(ctx,
-                                        None,
-                                        Some(r),
-                                        (self.bearing * RAD_TO_DEGREES) as f64,
-                                        None,
-                                        false,
-                                        false);
-        } else {
-            self.image.draw_ex(ctx,
-                               None,
-                               Some(r),
-                               (self.bearing * RAD_TO_DEGREES) as f64,
-                               None,
-                               false,
-                               false);
-        }
+    /// Keeps the ship inside an arena `width` by `height` pixels.
+    pub fn keep_inside(&mut self, width: f32, height: f32) {
+        self.location.x = clamp(self.location.x,
+                                self.collider_radius,
+                                width - self.collider_radius);
+        self.location.y = clamp(self.location.y,
+                                self.collider_radius,
+                                height - self.collider_radius);
+    }
 
-        Ok(())
+    /// The patch of water the hull is sitting on.
+    pub fn footprint(&self) -> Footprint {
+        Footprint::from_screen(self.location, self.length, self.width, self.bearing)
     }
 
-    pub fn key_down_event(&mut self, button: Buttons) {
-        if !self.flipped {
-            self.keys_down.insert(button);
-        }
+    /// How big the ship is drawn; grows and shrinks during a jump.
+    pub fn scale(&self) -> f32 {
+        self.scale
+    }
+
+    /// Length and width of the hull, in pixels.
+    pub fn hull_size(&self) -> (f32, f32) {
+        (self.length, self.width)
+    }
+
+    /// How far the ship moves per tick for each unit of `velocity`.
+    pub fn speed(&self) -> f32 {
+        self.speed
+    }
+
+    /// Whether this is the tick the ship came back down from a jump.
+    pub fn just_landed(&self) -> bool {
+        self.post_jump == JUMP_COOLDOWN
+    }
+
+    /// Gives the ship a shove in roll and pitch, in radians per tick.
+    /// It's up to the waves whether it rights itself or goes over.
+    pub fn knock(&mut self, roll: f32, pitch: f32) {
+        self.roll_velocity += roll;
+        self.pitch_velocity += pitch;
+    }
+
+    /// Whether the ship is heeled over far enough that it's in danger.
+    pub fn is_tipping(&self) -> bool {
+        !self.flipped && f32::max(self.roll.abs(), self.pitch.abs()) > TIPPING_ANGLE
+    }
+
+    pub fn roll(&self) -> f32 {
+        self.roll
     }
 
-    pub fn key_up_event(&mut self, button: Buttons) {
-        self.keys_down.remove(&button);
+    pub fn pitch(&self) -> f32 {
+        self.pitch
     }
 
+    pub fn roll_velocity(&self) -> f32 {
+        self.roll_velocity
+    }
+
+    pub fn pitch_velocity(&self) -> f32 {
+        self.pitch_velocity
+    }
+
+    /// Rocks the ship on the water under it for one tick, and capsizes it
+    /// if it goes too far.  `None` means the ship is out of the water,
+    /// in which case it just rights itself.
+    pub fn ride_waves(&mut self, water: Option<&HullSample>) {
+        if self.flipped {
+            return;
+        }
+        // Water higher on one side lifts that side, tipping
+        // the ship towards the other.
+        let (roll_push, pitch_push) = match water {
+            Some(sample) => (-sample.roll_slope * ROLL_COUPLING,
+                             -sample.pitch_slope * PITCH_COUPLING),
+            None => (0.0, 0.0),
+        };
+
+        self.roll_velocity += roll_push - RIGHTING * self.roll.sin();
+        self.roll_velocity *= ROLL_DAMPING;
+        self.roll += self.roll_velocity;
+
+        self.pitch_velocity += pitch_push - RIGHTING * self.pitch.sin();
+        self.pitch_velocity *= ROLL_DAMPING;
+        self.pitch += self.pitch_velocity;
+
+        if self.roll.abs() > CAPSIZE_ANGLE || self.pitch.abs() > CAPSIZE_ANGLE {
+            self.flip();
+        }
+    }
+
+    /// Capsizes the ship.
     pub fn flip(&mut self) {
-        self.flipping = 1;
-        println!("Flipped!");
+        self.flipped = true;
+        // println!("Flipped!");
+    }
+
+    /// Saves what changes during a match.  How fast the ship goes and
+    /// how big it is are the same for every ship, so they're left out.
+    pub fn write_snapshot(&self, w: &mut SnapshotWriter) {
+        w.f32(self.location.x);
+        w.f32(self.location.y);
+        w.f32(self.velocity.x);
+        w.f32(self.velocity.y);
+        w.f32(self.angular_velocity);
+        w.f32(self.scale);
+        w.f32(self.bearing);
+        w.usize(self.jump_index);
+        w.bool(self.jumping);
+        w.usize(self.post_jump);
+        w.bool(self.flipped);
+        w.f32(self.roll);
+        w.f32(self.roll_velocity);
+        w.f32(self.pitch);
+        w.f32(self.pitch_velocity);
+    }
+
+    pub fn read_snapshot(r: &mut SnapshotReader) -> Result<Ship, SnapshotError> {
+        let ship = Ship {
+            location: Vector2::new(r.f32()?, r.f32()?),
+            velocity: Vector2::new(r.f32()?, r.f32()?),
+            angular_velocity: r.f32()?,
+            scale: r.f32()?,
+            bearing: r.f32()?,
+            jump_index: r.usize()?,
+            jumping: r.bool()?,
+            post_jump: r.usize()?,
+            flipped: r.bool()?,
+            roll: r.f32()?,
+            roll_velocity: r.f32()?,
+            pitch: r.f32()?,
+            pitch_velocity: r.f32()?,
+            ..Ship::new(0, 0)
+        };
+        // Anywhere off in the billions would overflow working out
+        // which cells are under the ship.
+        if !(ship.location.x.abs() < 1e6 && ship.location.y.abs() < 1e6) {
+            return Err(SnapshotError::Invalid("location"));
+        }
+        if ship.jump_index >= JUMP_TICKS {
+            return Err(SnapshotError::Invalid("jump"));
+        }
+        Ok(ship)
     }
 }
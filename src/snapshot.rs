@@ -0,0 +1,176 @@
+//! Saving the whole state of a match to a compact binary blob, and
+//! getting it back exactly, for save states, rollback and bug reports.
+//!
+//! Everything is little-endian, and floats are stored bit for bit.
+//! Each part of the world writes and reads its own piece; this module
+//! just has the plumbing.  Which keys or pads steer which ship isn't
+//! part of the simulation, so it isn't saved.
+//!
+//! The field's random number generator is only drawn from by the
+//! disabled `sprinkle_random_bits()`, so it's started again from the
+//! seed instead of being saved.
+
+use std::error::Error;
+use std::fmt;
+
+pub const SNAPSHOT_VERSION: u8 = 4;
+const MAGIC: &[u8] = b"FWSNAP";
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum SnapshotError {
+    /// It isn't a snapshot at all.
+    NotASnapshot,
+    /// A snapshot from some other version of the game.
+    Version(u8),
+    /// It ended before it should have.
+    Truncated,
+    /// Something in it makes no sense.
+    Invalid(&'static str),
+}
+
+impl fmt::Display for SnapshotError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            SnapshotError::NotASnapshot => write!(f, "not a snapshot"),
+            SnapshotError::Version(v) => {
+                write!(f,
+                       "snapshot is version {}, this game reads version {}",
+                       v,
+                       SNAPSHOT_VERSION)
+            }
+            SnapshotError::Truncated => write!(f, "snapshot is cut short"),
+            SnapshotError::Invalid(what) => write!(f, "snapshot has a bad {}", what),
+        }
+    }
+}
+
+impl Error for SnapshotError {
+    fn description(&self) -> &str {
+        match *self {
+            SnapshotError::NotASnapshot => "not a snapshot",
+            SnapshotError::Version(_) => "wrong snapshot version",
+            SnapshotError::Truncated => "snapshot is cut short",
+            SnapshotError::Invalid(_) => "bad value in snapshot",
+        }
+    }
+}
+
+/// Builds up a snapshot.
+pub struct SnapshotWriter {
+    bytes: Vec<u8>,
+}
+
+impl Default for SnapshotWriter {
+    fn default() -> Self {
+        SnapshotWriter::new()
+    }
+}
+
+impl SnapshotWriter {
+    /// Starts a snapshot off with the header.
+    pub fn new() -> Self {
+        let mut w = SnapshotWriter { bytes: MAGIC.to_vec() };
+        w.u8(SNAPSHOT_VERSION);
+        w
+    }
+
+    pub fn finish(self) -> Vec<u8> {
+        self.bytes
+    }
+
+    pub fn u8(&mut self, n: u8) {
+        self.bytes.push(n);
+    }
+
+    pub fn bool(&mut self, b: bool) {
+        self.u8(b as u8);
+    }
+
+    pub fn u32(&mut self, n: u32) {
+        for i in 0..4 {
+            self.u8((n >> (8 * i)) as u8);
+        }
+    }
+
+    pub fn u64(&mut self, n: u64) {
+        self.u32(n as u32);
+        self.u32((n >> 32) as u32);
+    }
+
+    pub fn f32(&mut self, x: f32) {
+        self.u32(x.to_bits());
+    }
+
+    pub fn usize(&mut self, n: usize) {
+        self.u64(n as u64);
+    }
+}
+
+/// Reads back what a `SnapshotWriter` wrote, in the same order.
+pub struct SnapshotReader<'a> {
+    bytes: &'a [u8],
+}
+
+impl<'a> SnapshotReader<'a> {
+    /// Checks the header and starts reading after it.
+    pub fn new(bytes: &'a [u8]) -> Result<Self, SnapshotError> {
+        if bytes.len() < MAGIC.len() || &bytes[..MAGIC.len()] != MAGIC {
+            return Err(SnapshotError::NotASnapshot);
+        }
+        let mut r = SnapshotReader { bytes: &bytes[MAGIC.len()..] };
+        let version = r.u8()?;
+        if version != SNAPSHOT_VERSION {
+            return Err(SnapshotError::Version(version));
+        }
+        Ok(r)
+    }
+
+    /// Fails if there's anything left over.
+    pub fn finish(self) -> Result<(), SnapshotError> {
+        if self.bytes.is_empty() {
+            Ok(())
+        } else {
+            Err(SnapshotError::Invalid("length"))
+        }
+    }
+
+    pub fn u8(&mut self) -> Result<u8, SnapshotError> {
+        match self.bytes.split_first() {
+            Some((&n, rest)) => {
+                self.bytes = rest;
+                Ok(n)
+            }
+            None => Err(SnapshotError::Truncated),
+        }
+    }
+
+    pub fn bool(&mut self) -> Result<bool, SnapshotError> {
+        match self.u8()? {
+            0 => Ok(false),
+            1 => Ok(true),
+            _ => Err(SnapshotError::Invalid("flag")),
+        }
+    }
+
+    pub fn u32(&mut self) -> Result<u32, SnapshotError> {
+        let mut n = 0;
+        for i in 0..4 {
+            n |= (self.u8()? as u32) << (8 * i);
+        }
+        Ok(n)
+    }
+
+    pub fn u64(&mut self) -> Result<u64, SnapshotError> {
+        let lo = self.u32()? as u64;
+        let hi = self.u32()? as u64;
+        Ok(lo | hi << 32)
+    }
+
+    pub fn f32(&mut self) -> Result<f32, SnapshotError> {
+        Ok(f32::from_bits(self.u32()?))
+    }
+
+    pub fn usize(&mut self) -> Result<usize, SnapshotError> {
+        Ok(self.u64()? as usize)
+    }
+}
@@ -0,0 +1,116 @@
+//! Disturbances that can be stamped into the `Field`.
+//!
+//! A `Splash` is a shape, a falloff profile across that shape, what it
+//! pushes on (position or velocity), and how hard.  Splashes add to
+//! whatever is already in the field and get clipped at the edges.
+//!
+//! ```ignore
+//! let landing = Splash::new(SplashShape::Circle { radius: 7.0 }, 1.0)
+//!     .falloff(Falloff::Smooth)
+//!     .target(SplashTarget::Velocity);
+//! field.splash(40.0, 30.0, &landing);
+//! ```
+
+use std::f32::consts::PI;
+
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum SplashShape {
+    /// Everything within `radius` cells in x and y.
+    Square { radius: f32 },
+    /// Everything within `radius` cells.
+    Circle { radius: f32 },
+    /// A bell curve; cut off at 3 sigma, where it's down to about 1%.
+    /// Has its own falloff, so the `Falloff` is ignored.
+    Gaussian { sigma: f32 },
+    /// A band `width` cells wide whose middle is `radius` cells out.
+    /// The falloff goes from the middle of the band to its edges.
+    Ring { radius: f32, width: f32 },
+}
+
+/// How the force drops off from the middle of the shape to its edge.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum Falloff {
+    /// Full force everywhere inside.
+    Flat,
+    /// Straight line down to nothing at the edge.
+    Linear,
+    /// A cosine bump; no sharp edge for waves to ring off of.
+    Smooth,
+}
+
+/// What part of the water the splash pushes on.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum SplashTarget {
+    /// Lifts or drops the water directly; makes bigger waves.
+    Position,
+    /// Gives the water a shove; gentler and smoother.
+    Velocity,
+}
+
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct Splash {
+    pub shape: SplashShape,
+    pub falloff: Falloff,
+    pub target: SplashTarget,
+    /// Added at full strength; negative makes a trough.
+    pub force: f32,
+}
+
+impl Splash {
+    /// A flat splash that pushes on position.
+    pub fn new(shape: SplashShape, force: f32) -> Self {
+        Splash {
+            shape: shape,
+            falloff: Falloff::Flat,
+            target: SplashTarget::Position,
+            force: force,
+        }
+    }
+
+    pub fn falloff(mut self, falloff: Falloff) -> Self {
+        self.falloff = falloff;
+        self
+    }
+
+    pub fn target(mut self, target: SplashTarget) -> Self {
+        self.target = target;
+        self
+    }
+
+    /// How far from the centre this splash can reach, in cells.
+    pub fn extent(&self) -> f32 {
+        match self.shape {
+            SplashShape::Square { radius } |
+            SplashShape::Circle { radius } => radius,
+            SplashShape::Gaussian { sigma } => sigma * 3.0,
+            SplashShape::Ring { radius, width } => radius + width / 2.0,
+        }
+    }
+
+    /// How much of the force lands on a cell `dx`, `dy` cells
+    /// away from the centre, from 0 to 1.
+    pub fn weight(&self, dx: f32, dy: f32) -> f32 {
+        let distance = (dx * dx + dy * dy).sqrt();
+        // How far out towards the edge of the shape the cell is, 0 to 1.
+        let t = match self.shape {
+            SplashShape::Square { radius } => f32::max(dx.abs(), dy.abs()) / radius,
+            SplashShape::Circle { radius } => distance / radius,
+            SplashShape::Gaussian { sigma } => {
+                if distance > sigma * 3.0 {
+                    return 0.0;
+                }
+                return (-(distance * distance) / (2.0 * sigma * sigma)).exp();
+            }
+            SplashShape::Ring { radius, width } => (distance - radius).abs() / (width / 2.0),
+        };
+        if !(t <= 1.0) {
+            // Also catches NaN from zero-sized shapes.
+            return 0.0;
+        }
+        match self.falloff {
+            Falloff::Flat => 1.0,
+            Falloff::Linear => 1.0 - t,
+            Falloff::Smooth => 0.5 * (1.0 + (PI * t).cos()),
+        }
+    }
+}
@@ -0,0 +1,355 @@
+use na::Vector2;
+
+use collision::{collide, collide_rock, hulls_touch};
+use field::{Field, FIELD_CELL_SIZE, screen_to_field};
+use map::Map;
+use splash::{Splash, SplashShape, Falloff};
+use params::WaveParams;
+use player::{Player, default_players, MIN_PLAYERS, MAX_PLAYERS};
+use replay::{Input, STEER_SCALE};
+use snapshot::{SnapshotWriter, SnapshotReader, SnapshotError};
+
+/// How a round ended.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum Outcome {
+    /// The last ship afloat, by player number.
+    Winner(usize),
+    /// Everybody went over.
+    Draw,
+}
+
+/// What put a ship over.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum FlipCause {
+    /// Rocked over by the water.
+    Waves,
+    /// Knocked over by another player's ship running into it.
+    Rammed(usize),
+    /// Landed on by another player.
+    Stomped(usize),
+}
+
+impl FlipCause {
+    /// A short name, without who did it.
+    pub fn name(&self) -> &'static str {
+        match *self {
+            FlipCause::Waves => "waves",
+            FlipCause::Rammed(_) => "rammed",
+            FlipCause::Stomped(_) => "stomped",
+        }
+    }
+}
+
+/// A ship that capsizes this many ticks or less after a hard ram
+/// was put over by the ram, not the water.
+const RAM_BLAME_TICKS: usize = 60;
+
+/// The big splash a ship makes coming down from a jump.
+/// `polarity` is 1.0 for a crest, -1.0 for a trough.
+pub fn landing_splash(polarity: f32) -> Splash {
+    Splash::new(SplashShape::Circle { radius: 7.0 }, polarity)
+}
+
+/// The little bit of water a ship pushes around every tick.
+fn wake_splash(polarity: f32) -> Splash {
+    Splash::new(SplashShape::Circle { radius: 1.5 }, polarity * 0.01)
+}
+
+/// Rams softer than this, in pixels per tick, don't throw up any water.
+const RAM_SPLASH_IMPULSE: f32 = 0.5;
+
+/// The water thrown up where two ships hit.
+fn ram_splash(impulse: f32) -> Splash {
+    let force = f32::min(impulse * 0.1, 0.5);
+    Splash::new(SplashShape::Circle { radius: 3.0 }, force).falloff(Falloff::Smooth)
+}
+
+/// Everything that makes up a match: the ocean and the ships on it,
+/// plus the rules that decide who gets flipped.  Cloning one saves
+/// the whole state of the match, to go back to later.
+#[derive(Clone)]
+pub struct World {
+    pub field: Field,
+    pub players: Vec<Player>,
+    pub frame: usize,
+    seed: u64,
+}
+
+impl World {
+    /// Creates a new match with the standard setup for `player_count`
+    /// players.  Two worlds made with the same seed and players, and fed
+    /// the same inputs on the same ticks, play out identically.
+    pub fn new(seed: u64, params: WaveParams, player_count: usize) -> Self {
+        World::with_players(seed, params, default_players(player_count))
+    }
+
+    pub fn with_players(seed: u64, params: WaveParams, players: Vec<Player>) -> Self {
+        World {
+            field: Field::new(seed, params),
+            players: players,
+            frame: 0,
+            seed: seed,
+        }
+    }
+
+    /// Starts a match on `map`, with each player at their spawn there.
+    pub fn on_map(seed: u64, params: WaveParams, map: &Map, mut players: Vec<Player>) -> Self {
+        map.place(&mut players);
+        World {
+            field: map.field(seed, params),
+            players: players,
+            frame: 0,
+            seed: seed,
+        }
+    }
+
+    /// Puts the water and all the ships back the way they started.
+    pub fn reset(&mut self) {
+        self.field.calm(self.seed);
+        for player in &mut self.players {
+            player.respawn();
+        }
+    }
+
+    /// Applies one player input.  Inputs should be applied between
+    /// calls to `update()`, and recorded with the `frame` they were
+    /// applied on, so a replay can feed them back in on the same tick.
+    pub fn apply_input(&mut self, input: Input) {
+        match input {
+            Input::Jump(player) => {
+                if let Some(player) = self.players.get_mut(player) {
+                    player.controls.jump = true;
+                }
+            }
+            Input::Steer(player, thrust, turn) => {
+                if let Some(player) = self.players.get_mut(player) {
+                    player.controls.thrust = thrust as f32 / STEER_SCALE;
+                    player.controls.turn = turn as f32 / STEER_SCALE;
+                }
+            }
+            Input::Reset => self.reset(),
+        }
+    }
+
+    pub fn seed(&self) -> u64 {
+        self.seed
+    }
+
+    /// Saves the whole state of the match, see `snapshot`.
+    pub fn snapshot(&self) -> Vec<u8> {
+        let mut w = SnapshotWriter::new();
+        w.u64(self.seed);
+        w.usize(self.frame);
+        self.field.write_snapshot(&mut w);
+        w.u8(self.players.len() as u8);
+        for player in &self.players {
+            player.write_snapshot(&mut w);
+        }
+        w.finish()
+    }
+
+    /// Loads a match from a snapshot.  Nobody is steering any of the
+    /// ships; that's up to whoever's running it.
+    pub fn from_snapshot(bytes: &[u8]) -> Result<World, SnapshotError> {
+        let mut r = SnapshotReader::new(bytes)?;
+        let seed = r.u64()?;
+        let frame = r.usize()?;
+        let field = Field::read_snapshot(&mut r, seed)?;
+        let count = r.u8()? as usize;
+        if count < MIN_PLAYERS || count > MAX_PLAYERS {
+            return Err(SnapshotError::Invalid("player count"));
+        }
+        let mut players = Vec::with_capacity(count);
+        for _ in 0..count {
+            let player = Player::read_snapshot(&mut r)?;
+            // Anything from after `frame`, or blamed on a ship that isn't
+            // there, would blow up working out who flipped who.
+            if let Some((tick, cause)) = player.flipped {
+                let by = match cause {
+                    FlipCause::Waves => 0,
+                    FlipCause::Rammed(by) | FlipCause::Stomped(by) => by,
+                };
+                if tick > frame || by >= count {
+                    return Err(SnapshotError::Invalid("flip"));
+                }
+            }
+            if let Some((tick, by)) = player.rammed {
+                if tick > frame || by >= count {
+                    return Err(SnapshotError::Invalid("ram"));
+                }
+            }
+            players.push(player);
+        }
+        r.finish()?;
+        Ok(World {
+            field: field,
+            players: players,
+            frame: frame,
+            seed: seed,
+        })
+    }
+
+    /// Puts the match back the way it was when `bytes` was saved,
+    /// keeping whoever's steering each ship.  The snapshot has to
+    /// have the same number of players.
+    pub fn restore(&mut self, bytes: &[u8]) -> Result<(), SnapshotError> {
+        let mut world = World::from_snapshot(bytes)?;
+        if world.players.len() != self.players.len() {
+            return Err(SnapshotError::Invalid("player count"));
+        }
+        for (new, old) in world.players.iter_mut().zip(&self.players) {
+            new.control = old.control;
+        }
+        *self = world;
+        Ok(())
+    }
+
+    /// Advances the simulation by exactly one tick.
+    pub fn update(&mut self) {
+        // Wakes and landings go where the ships were at the start of the tick.
+        let splash_locations: Vec<(f32, f32)> = self.players
+            .iter()
+            .map(|p| screen_to_field(p.ship.location.x, p.ship.location.y))
+            .collect();
+
+        self.field.update();
+        let (width, height) = self.field.screen_size();
+        for player in &mut self.players {
+            player.ship.update(&player.controls);
+            // Jumping is a one-off; everything else stays
+            // as it is until the next input changes it.
+            player.controls.jump = false;
+        }
+        self.stomp_ships();
+        self.collide_ships();
+        self.collide_rocks();
+        // After the collisions, since they can push a ship over the edge.
+        for player in &mut self.players {
+            player.ship.keep_inside(width, height);
+        }
+        self.calculate_flips();
+
+        for (player, &(sx, sy)) in self.players.iter().zip(&splash_locations) {
+            if player.ship.just_landed() {
+                // create splash from landing
+                // println!("Splashing down");
+                self.field.splash(sx, sy, &landing_splash(player.polarity));
+            } else if !player.ship.jumping {
+                // create wake
+                self.field.splash(sx, sy, &wake_splash(player.polarity));
+            }
+        }
+
+        self.frame += 1;
+    }
+
+    // Coming down from a jump on top of somebody sinks them outright.
+    fn stomp_ships(&mut self) {
+        for i in 0..self.players.len() {
+            if !self.players[i].ship.just_landed() {
+                continue;
+            }
+            for j in 0..self.players.len() {
+                let crushed = {
+                    let lander = &self.players[i].ship;
+                    let other = &self.players[j].ship;
+                    i != j && !other.jumping && !other.flipped && hulls_touch(lander, other)
+                };
+                if crushed {
+                    // println!("Player {} landed on player {}", i, j);
+                    self.players[j].ship.flip();
+                    self.players[j].flipped = Some((self.frame, FlipCause::Stomped(i)));
+                }
+            }
+        }
+    }
+
+    // Bounces ships off each other, wrecks included.  Ships in the
+    // air sail right over everything.  Hard rams splash.
+    fn collide_ships(&mut self) {
+        for i in 0..self.players.len() {
+            let (left, right) = self.players.split_at_mut(i + 1);
+            let a = &mut left[i];
+            if a.ship.jumping {
+                continue;
+            }
+            for (j, b) in right.iter_mut().enumerate() {
+                if b.ship.jumping {
+                    continue;
+                }
+                if let Some(impact) = collide(&mut a.ship, &mut b.ship) {
+                    if impact.impulse > RAM_SPLASH_IMPULSE {
+                        let (x, y) = screen_to_field(impact.point.x, impact.point.y);
+                        self.field.splash(x, y, &ram_splash(impact.impulse));
+                        a.rammed = Some((self.frame, i + 1 + j));
+                        b.rammed = Some((self.frame, i));
+                    }
+                }
+            }
+        }
+    }
+
+    // Bounces ships off rocks.  They're too tall to jump over, so ships
+    // in the air hit them too.  Hard hits splash, like rams.
+    fn collide_rocks(&mut self) {
+        let cell = FIELD_CELL_SIZE as f32;
+        let (w, h) = (self.field.width() as i32, self.field.height() as i32);
+        for player in &mut self.players {
+            let ship = &mut player.ship;
+            // The hull can't reach any further than this many cells.
+            let (length, width) = ship.hull_size();
+            let reach = ((length * length + width * width).sqrt() / 2.0 / cell) as i32 + 1;
+            let (cx, cy) = screen_to_field(ship.location.x, ship.location.y);
+            let (cx, cy) = (cx as i32, cy as i32);
+            for y in cy - reach..cy + reach + 1 {
+                for x in cx - reach..cx + reach + 1 {
+                    if x < 0 || y < 0 || x >= w || y >= h ||
+                       !self.field.is_rock(x as usize, y as usize) {
+                        continue;
+                    }
+                    let centre = Vector2::new((x as f32 + 0.5) * cell, (y as f32 + 0.5) * cell);
+                    if let Some(impact) = collide_rock(ship, centre, cell) {
+                        if impact.impulse > RAM_SPLASH_IMPULSE {
+                            let (sx, sy) = screen_to_field(impact.point.x, impact.point.y);
+                            self.field.splash(sx, sy, &ram_splash(impact.impulse));
+                        }
+                    }
+                }
+            }
+        }
+    }
+
+    // Rocks each ship on the water under it, which might capsize it.
+    // Ships in the middle of a jump are clear of the water.
+    fn calculate_flips(&mut self) {
+        let frame = self.frame;
+        for player in &mut self.players {
+            {
+                let ship = &mut player.ship;
+                let sample = if ship.jumping {
+                    None
+                } else {
+                    Some(self.field.sample_hull(&ship.footprint()))
+                };
+                ship.ride_waves(sample.as_ref());
+            }
+            if player.ship.flipped && player.flipped.is_none() {
+                let cause = match player.rammed {
+                    Some((when, by)) if frame - when <= RAM_BLAME_TICKS => FlipCause::Rammed(by),
+                    _ => FlipCause::Waves,
+                };
+                player.flipped = Some((frame, cause));
+            }
+        }
+    }
+
+    /// Returns who won, or `None` while two or more ships are still afloat.
+    pub fn outcome(&self) -> Option<Outcome> {
+        let mut afloat = self.players.iter().enumerate().filter(|&(_, p)| p.afloat());
+        match (afloat.next(), afloat.next()) {
+            (None, _) => Some(Outcome::Draw),
+            (Some((i, _)), None) => Some(Outcome::Winner(i)),
+            _ => None,
+        }
+    }
+}
@@ -0,0 +1,39 @@
+extern crate flipwrecked;
+
+use flipwrecked::bot::{Bot, Difficulty};
+use flipwrecked::params::WaveParams;
+use flipwrecked::replay::Input;
+use flipwrecked::world::World;
+
+const TICKS: usize = 600;
+
+/// Everything the bots did over a match between `difficulties`.
+fn bot_match(seed: u64, difficulties: &[Difficulty]) -> Vec<(usize, Input)> {
+    let mut world = World::new(seed, WaveParams::default(), difficulties.len());
+    let mut bots: Vec<Bot> = difficulties.iter()
+        .enumerate()
+        .map(|(i, &difficulty)| Bot::new(i, difficulty, seed))
+        .collect();
+    let mut inputs = Vec::new();
+    for _ in 0..TICKS {
+        for bot in &mut bots {
+            for input in bot.inputs(&world) {
+                inputs.push((world.frame, input));
+                world.apply_input(input);
+            }
+        }
+        world.update();
+    }
+    inputs
+}
+
+#[test]
+fn bots_are_deterministic() {
+    let everybody = [Difficulty::Easy, Difficulty::Normal, Difficulty::Hard, Difficulty::Hard];
+    let first = bot_match(7, &everybody);
+    assert!(first.iter().any(|&(_, input)| input != Input::Steer(0, 0, 0)),
+            "the bots never did anything");
+    assert_eq!(first, bot_match(7, &everybody));
+    assert!(first != bot_match(8, &everybody), "the seed made no difference");
+}
+
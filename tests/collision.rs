@@ -0,0 +1,104 @@
+extern crate flipwrecked;
+extern crate nalgebra as na;
+
+use std::f32::consts::PI;
+
+use na::Vector2;
+
+use flipwrecked::collision::hulls_touch;
+use flipwrecked::params::WaveParams;
+use flipwrecked::replay::Input;
+use flipwrecked::ship::{Ship, JUMP_TICKS};
+use flipwrecked::world::{World, FlipCause};
+
+// Long enough for a ship at full speed to cross the gap and for
+// whatever it hit to finish rocking.
+const TICKS: usize = 120;
+
+fn ship_at(x: f32, y: f32, bearing: f32) -> Ship {
+    let mut ship = Ship::new(x as i32, y as i32);
+    ship.bearing = bearing;
+    ship
+}
+
+/// Player 0 charges at full speed to the right, into player 1 sitting
+/// still facing `bearing`.
+fn ram(bearing: f32) -> World {
+    let mut world = World::new(1, WaveParams::default(), 2);
+    world.players[0].ship = ship_at(250.0, 300.0, PI / 2.0);
+    world.players[0].ship.velocity = Vector2::new(32.0, 0.0);
+    world.players[1].ship = ship_at(400.0, 300.0, bearing);
+    world.apply_input(Input::Steer(0, 1000, 0));
+    for _ in 0..TICKS {
+        world.update();
+    }
+    world
+}
+
+#[test]
+fn hulls_are_boxes() {
+    // Side by side, with room between them.
+    let a = ship_at(300.0, 300.0, 0.0);
+    assert!(!hulls_touch(&a, &ship_at(370.0, 300.0, 0.0)));
+    // Turned end on, the same ship is long enough to reach.
+    assert!(hulls_touch(&a, &ship_at(370.0, 300.0, PI / 2.0)));
+    // Corner to corner, close enough that round hulls would touch.
+    assert!(!hulls_touch(&a, &ship_at(365.0, 410.0, 0.0)));
+    assert!(hulls_touch(&a, &ship_at(355.0, 390.0, 0.0)));
+}
+
+#[test]
+fn ramming_the_side_flips() {
+    let world = ram(0.0);
+    match world.players[1].flipped {
+        Some((_, FlipCause::Rammed(0))) => (),
+        other => panic!("expected player 0 to ram player 1 over, got {:?}", other),
+    }
+    assert_eq!(world.players[0].flipped, None);
+}
+
+#[test]
+fn ramming_the_bow_or_stern_only_bumps() {
+    for &bearing in &[-PI / 2.0, PI / 2.0] {
+        let world = ram(bearing);
+        assert!(world.players[1].rammed.is_some(), "missed at {}", bearing);
+        assert_eq!(world.players[1].flipped, None, "flipped at {}", bearing);
+        assert_eq!(world.players[0].flipped, None);
+    }
+}
+
+#[test]
+fn landing_on_a_ship_sinks_it() {
+    let mut world = World::new(1, WaveParams::default(), 2);
+    world.players[0].ship = ship_at(400.0, 300.0, 0.0);
+    world.players[1].ship = ship_at(400.0, 300.0, PI / 2.0);
+    world.apply_input(Input::Jump(0));
+    // The jump starts on tick 0, so it comes down on the last tick of it.
+    for _ in 0..JUMP_TICKS - 1 {
+        world.update();
+        assert_eq!(world.players[1].flipped, None, "sunk before the jump came down");
+    }
+    world.update();
+    assert_eq!(world.players[1].flipped, Some((JUMP_TICKS - 1, FlipCause::Stomped(0))));
+    assert_eq!(world.players[0].flipped, None);
+}
+
+#[test]
+fn rammed_ships_stay_in_the_arena() {
+    let mut world = World::new(1, WaveParams::default(), 2);
+    let (width, height) = world.field.screen_size();
+    // Player 1 is up against the right edge, and player 0 pins it there.
+    world.players[0].ship = ship_at(width - 250.0, 300.0, PI / 2.0);
+    world.players[0].ship.velocity = Vector2::new(32.0, 0.0);
+    world.players[1].ship = ship_at(width, 300.0, PI / 2.0);
+    world.players[1].ship.keep_inside(width, height);
+    world.apply_input(Input::Steer(0, 1000, 0));
+    for tick in 0..TICKS {
+        world.update();
+        for player in &world.players {
+            let mut inside = player.ship.clone();
+            inside.keep_inside(width, height);
+            assert_eq!(player.ship.location, inside.location, "outside on tick {}", tick);
+        }
+    }
+}
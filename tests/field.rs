@@ -0,0 +1,159 @@
+extern crate flipwrecked;
+
+use std::str::FromStr;
+
+use flipwrecked::field::{Boundary, Field, FIELD_WIDTH, FIELD_HEIGHT};
+use flipwrecked::params::WaveParams;
+use flipwrecked::splash::{Splash, SplashShape};
+
+// Few enough ticks that nothing reaches the edge of the field,
+// since edges would break the symmetry.
+const TICKS: usize = 10;
+const CHECK_RADIUS: i32 = 8;
+
+fn position(field: &Field, cx: usize, cy: usize, dx: i32, dy: i32) -> f32 {
+    let x = (cx as i32 + dx) as usize;
+    let y = (cy as i32 + dy) as usize;
+    field.cell(x, y).position
+}
+
+fn assert_close(a: f32, b: f32, what: &str) {
+    assert!((a - b).abs() < 1e-5, "{}: {} != {}", what, a, b);
+}
+
+#[test]
+fn centre_splash_spreads_symmetrically() {
+    let (cx, cy) = (FIELD_WIDTH / 2, FIELD_HEIGHT / 2);
+    let mut field = Field::new(1, WaveParams::default());
+    let splash = Splash::new(SplashShape::Square { radius: 1.0 }, 1.0);
+    field.splash(cx as f32, cy as f32, &splash);
+    for _ in 0..TICKS {
+        field.update();
+    }
+
+    // Make sure something actually happened out here.
+    assert!(position(&field, cx, cy, 4, 0).abs() > 1e-4);
+
+    for dx in -CHECK_RADIUS..CHECK_RADIUS + 1 {
+        for dy in -CHECK_RADIUS..CHECK_RADIUS + 1 {
+            let here = position(&field, cx, cy, dx, dy);
+            assert_close(here, position(&field, cx, cy, -dx, dy), "left/right");
+            assert_close(here, position(&field, cx, cy, dx, -dy), "up/down");
+            assert_close(here, position(&field, cx, cy, dy, dx), "diagonal");
+        }
+    }
+}
+
+#[test]
+fn default_params_are_stable() {
+    assert!(WaveParams::default().is_stable());
+}
+
+#[test]
+fn unstable_params_are_rejected() {
+    let unstable = ["dt = 10.0\nsurface_tension = 0.5",
+                    "dt = 0.0",
+                    "dt = -0.01",
+                    "surface_tension = 0.0",
+                    "restoring_force = -0.05",
+                    "decay_factor = 1.01",
+                    "decay_factor = -0.5",
+                    "sponge_damping = 2.0",
+                    "dt = nan"];
+    for text in &unstable {
+        assert!(WaveParams::from_str(text).is_err(), "accepted {:?}", text);
+    }
+    // Right on the edge is fine.
+    assert!(WaveParams::from_str("decay_factor = 1.0\nrestoring_force = 0.0").is_ok());
+}
+
+fn energy_after_hitting_edge(boundary: Boundary) -> f32 {
+    let mut params = WaveParams::default();
+    params.boundary = boundary;
+    let mut field = Field::new(1, params);
+    field.splash(4.0,
+                 (FIELD_HEIGHT / 2) as f32,
+                 &Splash::new(SplashShape::Circle { radius: 2.0 }, 1.0));
+    for _ in 0..200 {
+        field.update();
+    }
+    let mut energy = 0.0;
+    for x in 0..field.width() {
+        for y in 0..field.height() {
+            let cell = field.cell(x, y);
+            energy += cell.position * cell.position + cell.velocity * cell.velocity;
+        }
+    }
+    energy
+}
+
+#[test]
+fn absorbing_edge_soaks_up_waves() {
+    let reflected = energy_after_hitting_edge(Boundary::Reflecting);
+    let absorbed = energy_after_hitting_edge(Boundary::Absorbing);
+    assert!(absorbed < reflected * 0.5,
+            "absorbed {} vs reflected {}",
+            absorbed,
+            reflected);
+}
+
+// How much each cell moved after `splash` at `x`, `y` on a fresh field.
+fn splash_change(x: f32, y: f32, splash: &Splash) -> Vec<Vec<f32>> {
+    let before = Field::new(1, WaveParams::default());
+    let mut after = before.clone();
+    after.splash(x, y, splash);
+    let moved = |x, y| after.cell(x, y).position - before.cell(x, y).position;
+    (0..FIELD_WIDTH).map(|x| (0..FIELD_HEIGHT).map(|y| moved(x, y)).collect()).collect()
+}
+
+#[test]
+fn splashes_off_the_edge_are_clipped() {
+    let radius = 40.0;
+    let splash = Splash::new(SplashShape::Circle { radius: radius }, 1.0);
+    for &(cx, cy) in &[(0, 0), (FIELD_WIDTH - 1, FIELD_HEIGHT - 1)] {
+        let change = splash_change(cx as f32, cy as f32, &splash);
+        for (x, column) in change.iter().enumerate() {
+            for (y, &moved) in column.iter().enumerate() {
+                let dx = x as f32 - cx as f32;
+                let dy = y as f32 - cy as f32;
+                let expected = if dx * dx + dy * dy <= radius * radius { 1.0 } else { 0.0 };
+                let what = format!("({}, {}) from ({}, {})", x, y, cx, cy);
+                assert_close(moved, expected, &what);
+            }
+        }
+    }
+}
+
+#[test]
+fn splashes_entirely_off_the_field_do_nothing() {
+    let splash = Splash::new(SplashShape::Square { radius: 3.0 }, 1.0);
+    let change = splash_change(-10.0, (FIELD_HEIGHT + 10) as f32, &splash);
+    assert!(change.iter().all(|column| column.iter().all(|&c| c == 0.0)));
+}
+
+#[test]
+fn ring_splashes_miss_the_middle() {
+    let (cx, cy) = (FIELD_WIDTH / 2, FIELD_HEIGHT / 2);
+    let splash = Splash::new(SplashShape::Ring { radius: 6.0, width: 2.0 }, 1.0);
+    let change = splash_change(cx as f32, cy as f32, &splash);
+    for &(d, expected) in &[(0, 0.0), (3, 0.0), (5, 1.0), (6, 1.0), (7, 1.0), (8, 0.0)] {
+        assert_close(change[cx + d][cy], expected, &format!("{} right", d));
+        assert_close(change[cx][cy - d], expected, &format!("{} up", d));
+    }
+}
+
+#[test]
+fn gaussian_splashes_fall_off() {
+    let (cx, cy) = (FIELD_WIDTH / 2, FIELD_HEIGHT / 2);
+    let sigma = 4.0;
+    let splash = Splash::new(SplashShape::Gaussian { sigma: sigma }, 1.0);
+    let change = splash_change(cx as f32, cy as f32, &splash);
+    assert_close(change[cx][cy], 1.0, "middle");
+    assert_close(change[cx + 4][cy], (-0.5f32).exp(), "one sigma");
+    for d in 0..12 {
+        assert!(change[cx + d + 1][cy] < change[cx + d][cy], "rises {} out", d);
+    }
+    // Cut off past three sigma.
+    assert_close(change[cx + 13][cy], 0.0, "past the cutoff");
+    assert_close(change[cx][cy + 13], 0.0, "past the cutoff");
+}
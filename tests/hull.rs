@@ -0,0 +1,80 @@
+extern crate flipwrecked;
+extern crate nalgebra as na;
+
+use std::f32::consts::PI;
+
+use na::Vector2;
+
+use flipwrecked::field::{Field, FIELD_WIDTH, FIELD_HEIGHT};
+use flipwrecked::hull::Footprint;
+use flipwrecked::params::WaveParams;
+use flipwrecked::splash::{Splash, SplashShape};
+
+// A ship-sized hull in the middle of the field, in cells.
+const LENGTH: f32 = 9.6;
+const WIDTH: f32 = 6.0;
+
+fn footprint(bearing: f32) -> Footprint {
+    Footprint {
+        centre: Vector2::new((FIELD_WIDTH / 2) as f32, (FIELD_HEIGHT / 2) as f32),
+        length: LENGTH,
+        width: WIDTH,
+        bearing: bearing,
+    }
+}
+
+/// Water rising `rise` per cell to the right, across the whole field,
+/// from the level it starts at.
+fn ramp(rise: f32) -> Field {
+    let mut field = Field::new(1, WaveParams::default());
+    // Just the one cell each.
+    let cell = SplashShape::Square { radius: 0.5 };
+    for x in 0..FIELD_WIDTH {
+        for y in 0..FIELD_HEIGHT {
+            field.splash(x as f32, y as f32, &Splash::new(cell, rise * x as f32));
+        }
+    }
+    field
+}
+
+fn assert_close(a: f32, b: f32, what: &str) {
+    assert!((a - b).abs() < 1e-4, "{}: {} != {}", what, a, b);
+}
+
+#[test]
+fn flat_water_has_no_tilt() {
+    let field = Field::new(1, WaveParams::default());
+    for &bearing in &[0.0, 0.3, PI / 2.0, PI, -2.0] {
+        let sample = field.sample_hull(&footprint(bearing));
+        let what = format!("bearing {}", bearing);
+        assert_close(sample.pitch_slope, 0.0, &what);
+        assert_close(sample.roll_slope, 0.0, &what);
+        assert_close(sample.max, sample.min, &what);
+    }
+}
+
+#[test]
+fn slopes_are_measured_along_the_hull() {
+    let rise = 0.01;
+    let field = ramp(rise);
+    let diagonal = rise * (PI / 4.0).sin();
+    // Bearing, then how much it should rise towards the bow and to starboard.
+    let cases = [(0.0, 0.0, rise),
+                 (PI / 2.0, rise, 0.0),
+                 (PI, 0.0, -rise),
+                 (-PI / 2.0, -rise, 0.0),
+                 (PI / 4.0, diagonal, diagonal)];
+    for &(bearing, pitch, roll) in &cases {
+        let sample = field.sample_hull(&footprint(bearing));
+        let what = format!("bearing {}", bearing);
+        assert_close(sample.pitch_slope, pitch, &what);
+        assert_close(sample.roll_slope, roll, &what);
+        // Whichever way the ship is facing, the water still rises to the right.
+        assert_close(sample.slope.x, rise, &what);
+        assert_close(sample.slope.y, 0.0, &what);
+        // Cell centres are half a cell in from their left edge.
+        let level = field.cell(0, 0).position;
+        assert_close(sample.mean - level, rise * ((FIELD_WIDTH / 2) as f32 - 0.5), &what);
+        assert!(sample.min < sample.mean && sample.mean < sample.max);
+    }
+}
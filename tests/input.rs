@@ -0,0 +1,95 @@
+extern crate flipwrecked;
+
+use std::fs;
+use std::str::FromStr;
+
+use flipwrecked::input::{Action, Device, InputMap, axis_strength};
+
+fn assert_close(a: f32, b: f32, what: &str) {
+    assert!((a - b).abs() < 1e-4, "{}: {} != {}", what, a, b);
+}
+
+#[test]
+fn default_bindings_are_fine() {
+    assert!(InputMap::default().check().is_ok());
+}
+
+#[test]
+fn bindings_save_and_load() {
+    let mut map = InputMap::default();
+    map.stick_dead_zone = 0.4;
+    map.bind(Device::Keyboard(0), Action::Jump, "Left Shift");
+    map.bind(Device::Keyboard(5), Action::Thrust, "F");
+    map.bind(Device::Gamepad, Action::Pause, "guide");
+    map.bind(Device::Gamepad, Action::TurnLeft, "rightx-");
+    map.clear(Device::Keyboard(1), Action::Thrust);
+
+    let path = ::std::env::temp_dir().join("flipwrecked_input.toml");
+    map.save(&path).unwrap();
+    let loaded = InputMap::load(&path);
+    let _ = fs::remove_file(&path);
+    let loaded = loaded.unwrap();
+
+    assert_eq!(loaded, map);
+    assert_eq!(loaded.key_action("Left Shift"), Some((0, Action::Jump)));
+    assert_eq!(loaded.key_action("S"), None);
+    assert_eq!(loaded.key_action("F"), Some((5, Action::Thrust)));
+    assert_eq!(loaded.key_action("I"), None);
+    assert_eq!(loaded.pad_action("guide"), Some(Action::Pause));
+    assert_eq!(loaded.pad_action("rightx-"), Some(Action::TurnLeft));
+    assert_eq!(loaded.pad_action("leftx-"), None);
+}
+
+#[test]
+fn binding_a_key_takes_it_off_whatever_it_did() {
+    let mut map = InputMap::default();
+    // Player 2's jump key becomes player 1's thrust.
+    map.bind(Device::Keyboard(0), Action::Thrust, "K");
+    assert_eq!(map.key_action("K"), Some((0, Action::Thrust)));
+    assert!(map.bindings(Device::Keyboard(1)).unwrap().jump.is_empty());
+    assert!(map.check().is_ok());
+}
+
+#[test]
+fn bad_bindings_are_refused() {
+    let bad = ["[[keyboard]]\nthrust = [\"W\"]\njump = [\"W\"]",
+               "[[keyboard]]\nthrust = [\"W\"]\n[[keyboard]]\nthrust = [\"W\"]",
+               "[gamepad]\njump = [\"a\", \"a\"]",
+               "[gamepad]\njump = [\"a\"]\nreset = [\"a\"]",
+               "[gamepad]\njump = [\"z\"]",
+               "[gamepad]\nthrust = [\"leftz+\"]",
+               "[[keyboard]]\nfly = [\"W\"]",
+               "stick_dead_zone = 1.0",
+               "trigger_dead_zone = -0.1",
+               "dead_zone = 0.1"];
+    for text in &bad {
+        assert!(InputMap::from_str(text).is_err(), "accepted {:?}", text);
+    }
+    // The same key on a keyboard and a gamepad button are different things.
+    assert!(InputMap::from_str("[[keyboard]]\njump = [\"b\"]\n[gamepad]\njump = [\"b\"]").is_ok());
+}
+
+#[test]
+fn dead_zones_are_cut_out() {
+    let zone = 0.25;
+    // Inside the dead zone, either way.
+    for &value in &[0, 1000, -1000, 8000, -8000] {
+        assert_eq!(axis_strength(value, true, zone), 0.0);
+        assert_eq!(axis_strength(value, false, zone), 0.0);
+    }
+    // Right at the edge of it.
+    let edge = (32767.0 * zone) as i16;
+    assert_eq!(axis_strength(edge, true, zone), 0.0);
+    assert_eq!(axis_strength(-edge, false, zone), 0.0);
+    // Halfway between the edge and full tilt.
+    let half = (32767.0 * (zone + 1.0) / 2.0) as i16;
+    assert_close(axis_strength(half, true, zone), 0.5, "half way");
+    // All the way over.
+    assert_close(axis_strength(32767, true, zone), 1.0, "full tilt");
+    assert_close(axis_strength(-32768, false, zone), 1.0, "full tilt back");
+    // Pushed the other way from what's asked about.
+    assert_eq!(axis_strength(32767, false, zone), 0.0);
+    assert_eq!(axis_strength(-32768, true, zone), 0.0);
+    // No dead zone at all.
+    assert_close(axis_strength(16384, true, 0.0), 0.5, "no dead zone");
+}
@@ -0,0 +1,239 @@
+extern crate flipwrecked;
+
+use std::str::FromStr;
+
+use flipwrecked::field::{Boundary, Field, DEFAULT_DEPTH, MAX_DAMPING, MAX_DEPTH, ROCK};
+use flipwrecked::map::{Map, DAMPING_STEP, level_depth};
+use flipwrecked::params::WaveParams;
+use flipwrecked::player::{default_players, Spawn};
+use flipwrecked::replay::Input;
+use flipwrecked::splash::{Splash, SplashShape};
+use flipwrecked::world::World;
+
+// 40x30, with a wall of rock down column 20 that has a gap at the
+// bottom, and shallow water in the top left corner.
+fn walled_map() -> String {
+    let mut tiles = String::new();
+    for y in 0..30 {
+        for x in 0..40 {
+            tiles.push(if x == 20 && y < 27 {
+                '#'
+            } else if x < 3 && y < 3 {
+                '2'
+            } else {
+                '.'
+            });
+        }
+        tiles.push('\n');
+    }
+    format!("name = \"Wall\"\nwidth = 40\nheight = 30\ntiles = \"\"\"\n{}\"\"\"\n\
+             [[spawns]]\nx = 10.0\ny = 15.0\nfacing = 90.0\n\
+             [[spawns]]\nx = 30.0\ny = 15.0\nfacing = 270.0\n",
+            tiles)
+}
+
+#[test]
+fn maps_load() {
+    let map = Map::from_str(&walled_map()).unwrap();
+    assert_eq!(map.name, "Wall");
+    assert_eq!((map.width(), map.height()), (40, 30));
+    assert_eq!(map.depth(20, 0), ROCK);
+    assert_eq!(map.depth(20, 29), DEFAULT_DEPTH);
+    assert!(map.depth(0, 0) < DEFAULT_DEPTH);
+    assert_eq!(map.max_players(), 2);
+    assert_eq!(map.spawns[1].x, 300.0);
+
+    let example = include_str!("../resources/maps/twin_islands.toml");
+    assert!(Map::from_str(example).unwrap().max_players() >= 2);
+}
+
+#[test]
+fn bad_maps_are_refused() {
+    let good = walled_map();
+    let bad = [good.replace("width = 40", "width = 41"),
+               good.replace("x = 30.0", "x = 20.0"),
+               good.replace("x = 30.0", "x = 45.0"),
+               good.replacen("..", ".?", 1),
+               good.replace("width = 40\nheight = 30", "width = 5\nheight = 5"),
+               good[..good.rfind("[[spawns]]").unwrap()].to_string()];
+    for map in &bad {
+        assert!(Map::from_str(map).is_err(), "should have refused:\n{}", map);
+    }
+}
+
+fn energy(field: &Field, xs: ::std::ops::Range<usize>) -> f32 {
+    let mut energy = 0.0;
+    for y in 0..field.height() {
+        for x in xs.clone() {
+            let cell = field.cell(x, y);
+            energy += cell.position * cell.position + cell.velocity * cell.velocity;
+        }
+    }
+    energy
+}
+
+#[test]
+fn rocks_stop_waves() {
+    let map = Map::from_str(&walled_map()).unwrap();
+    let mut walled = map.field(1, WaveParams::default());
+    let mut open = Field::with_size(40, 30, 1, WaveParams::default());
+    let splash = Splash::new(SplashShape::Circle { radius: 2.0 }, 1.0);
+    for field in [&mut walled, &mut open].iter_mut() {
+        field.splash(10.0, 10.0, &splash);
+        for _ in 0..200 {
+            field.update();
+        }
+    }
+    // Only what comes round through the gap gets past the wall.
+    assert!(energy(&walled, 21..40) < energy(&open, 21..40) * 0.1);
+    for y in 0..30 {
+        assert_eq!(walled.cell(20, y).position == 0.0, y < 27);
+    }
+}
+
+// How many ticks a splash takes to reach a cell 8 cells away,
+// through water of the given depth.
+fn arrival(depth: f32) -> usize {
+    let mut field = Field::with_size(30, 30, 1, WaveParams::default());
+    for y in 0..30 {
+        for x in 0..30 {
+            field.set_depth(x, y, depth);
+        }
+    }
+    field.splash(10.0, 15.0, &Splash::new(SplashShape::Circle { radius: 1.0 }, 1.0));
+    (1..1000)
+        .find(|_| {
+            field.update();
+            field.cell(18, 15).position.abs() > 0.01
+        })
+        .unwrap()
+}
+
+#[test]
+fn shallow_water_is_slower() {
+    assert!(arrival(0.4) > arrival(DEFAULT_DEPTH));
+    assert!(arrival(DEFAULT_DEPTH) > arrival(1.8));
+}
+
+#[test]
+fn ships_cant_sail_through_rocks() {
+    let map = Map::from_str(&walled_map()).unwrap();
+    let mut world = World::on_map(1, WaveParams::default(), &map, default_players(2));
+    assert_eq!(world.players[0].ship.location.x, 100.0);
+    // Full ahead, straight at the wall.
+    world.apply_input(Input::Steer(0, 1000, 0));
+    for _ in 0..300 {
+        world.update();
+        assert!(world.players[0].ship.location.x < 200.0);
+    }
+    assert!(world.players[0].ship.location.x > 140.0);
+}
+
+#[test]
+fn kelp_soaks_up_waves() {
+    let mut kelp = Field::with_size(40, 30, 1, WaveParams::default());
+    let mut open = Field::with_size(40, 30, 1, WaveParams::default());
+    for y in 0..30 {
+        for x in 18..22 {
+            kelp.set_damping(x, y, 0.1);
+        }
+    }
+    assert_eq!(kelp.damping(20, 0), 0.1);
+    let splash = Splash::new(SplashShape::Circle { radius: 2.0 }, 1.0);
+    for field in [&mut kelp, &mut open].iter_mut() {
+        field.splash(10.0, 15.0, &splash);
+        for _ in 0..200 {
+            field.update();
+        }
+    }
+    assert!(energy(&kelp, 22..40) < energy(&open, 22..40) * 0.1);
+    // Any thicker and waves would stop dead.
+    kelp.set_damping(20, 0, 1.0);
+    assert_eq!(kelp.damping(20, 0), MAX_DAMPING);
+}
+
+// The biggest wave there's ever been at a cell on the middle row of a
+// field, after a line of splashes across it.
+fn peak_behind(mut field: Field, x: usize) -> f32 {
+    let splash = Splash::new(SplashShape::Circle { radius: 2.0 }, 1.0);
+    for y in 14..46 {
+        field.splash(28.0, y as f32, &splash);
+    }
+    let mut peak: f32 = 0.0;
+    for _ in 0..1000 {
+        field.update();
+        peak = peak.max(field.cell(x, 30).position.abs());
+    }
+    peak
+}
+
+#[test]
+fn lenses_focus_waves() {
+    let map = Map::from_str(include_str!("../resources/maps/lens.toml")).unwrap();
+    assert!(map.damping(0, 0) > 0.0);
+    assert_eq!(map.damping(40, 30), 0.0);
+    let open = Field::with_size(80, 60, 1, WaveParams::default());
+    for &x in &[52, 56] {
+        let focused = peak_behind(map.field(1, WaveParams::default()), x);
+        let plain = peak_behind(open.clone(), x);
+        assert!(focused > plain * 1.2, "{} vs {} at {}", focused, plain, x);
+    }
+}
+
+#[test]
+fn edited_maps_save_and_load() {
+    let mut map = Map::from_str(&walled_map()).unwrap();
+    map.set_depth(5, 5, ROCK);
+    // Every depth there's a tile for, deepest included.
+    for level in 1..10 {
+        map.set_depth(10 + level as usize, 5, level_depth(level));
+    }
+    map.set_depth(10, 6, MAX_DEPTH);
+    map.set_damping(7, 5, DAMPING_STEP * 4.0);
+    map.spawns.push(Spawn::new(300.0, 250.0, ::std::f32::consts::PI));
+    map.check().unwrap();
+
+    let path = ::std::env::temp_dir().join("flipwrecked_edited_map.toml");
+    map.save(&path).unwrap();
+    let loaded = Map::load(&path).unwrap();
+    assert_eq!(loaded.name, "Wall");
+    for y in 0..map.height() {
+        for x in 0..map.width() {
+            assert_eq!(loaded.depth(x, y), map.depth(x, y), "depth at {}, {}", x, y);
+            assert_eq!(loaded.damping(x, y), map.damping(x, y), "kelp at {}, {}", x, y);
+        }
+    }
+    assert_eq!(loaded.max_players(), 3);
+    assert!((loaded.spawns[2].bearing - map.spawns[2].bearing).abs() < 1e-5);
+    assert_eq!(loaded.to_toml().unwrap(), map.to_toml().unwrap());
+
+    // Maps nobody could play on don't get saved.
+    map.spawns.truncate(1);
+    assert!(map.check().is_err());
+    assert!(map.save(&path).is_err());
+    assert_eq!(Map::load(&path).unwrap().max_players(), 3);
+    let _ = ::std::fs::remove_file(&path);
+}
+
+#[test]
+fn maps_can_pick_their_edges() {
+    let params = WaveParams::default();
+    let plain = Map::from_str(&walled_map()).unwrap();
+    assert_eq!(plain.boundary, None);
+    assert_eq!(plain.field(1, params).params.boundary, params.boundary);
+
+    let edges = |boundary: &str| {
+        walled_map().replace("height = 30\n",
+                             &format!("height = 30\nboundary = \"{}\"\n", boundary))
+    };
+    let absorbing = edges("absorbing");
+    let map = Map::from_str(&absorbing).unwrap();
+    assert_eq!(map.boundary, Some(Boundary::Absorbing));
+    assert_eq!(map.field(1, params).params.boundary, Boundary::Absorbing);
+    let world = World::on_map(1, params, &map, default_players(2));
+    assert_eq!(world.field.params.boundary, Boundary::Absorbing);
+    assert_eq!(Map::from_str(&map.to_toml().unwrap()).unwrap().boundary,
+               Some(Boundary::Absorbing));
+
+    assert!(Map::from_str(&edges("sticky")).is_err());
+}
@@ -0,0 +1,126 @@
+extern crate flipwrecked;
+
+use std::io;
+use std::net::UdpSocket;
+use std::thread;
+use std::time::Duration;
+
+use flipwrecked::map::Map;
+use flipwrecked::net::NetSession;
+use flipwrecked::params::WaveParams;
+use flipwrecked::replay::Input;
+use flipwrecked::world::World;
+
+const SEED: u64 = 7;
+const TICKS: usize = 300;
+
+// What each player does, by tick.  Steering changes the other end
+// can't guess, so there's something to roll back.
+fn script(player: usize, tick: usize) -> Vec<Input> {
+    match (player, tick) {
+        (0, 10) => vec![Input::Steer(0, 1000, 0)],
+        (0, 40) => vec![Input::Steer(0, 1000, 500)],
+        (0, 90) => vec![Input::Jump(0)],
+        (1, 5) => vec![Input::Steer(1, 800, -300)],
+        (1, 60) => vec![Input::Jump(1)],
+        (1, 120) => vec![Input::Steer(1, 0, 1000)],
+        (1, 200) => vec![Input::Steer(1, 1000, 0), Input::Jump(1)],
+        _ => vec![],
+    }
+}
+
+fn assert_same(a: &World, b: &World) {
+    assert_eq!(a.frame, b.frame);
+    for (pa, pb) in a.players.iter().zip(&b.players) {
+        assert_eq!(pa.ship.location, pb.ship.location);
+        assert_eq!(pa.ship.bearing, pb.ship.bearing);
+        assert_eq!(pa.ship.flipped, pb.ship.flipped);
+    }
+    for y in 0..a.field.height() {
+        for x in 0..a.field.width() {
+            assert_eq!(a.field.cell(x, y).position, b.field.cell(x, y).position);
+        }
+    }
+}
+
+fn connect() -> (NetSession, NetSession) {
+    let host_socket = UdpSocket::bind("127.0.0.1:0").unwrap();
+    let addr = host_socket.local_addr().unwrap();
+    let host = thread::spawn(move || {
+        NetSession::host(host_socket, SEED, &Map::open_sea(), WaveParams::default()).unwrap()
+    });
+    let guest = NetSession::join(UdpSocket::bind("127.0.0.1:0").unwrap(),
+                                 addr,
+                                 &Map::open_sea(),
+                                 WaveParams::default())
+        .unwrap();
+    (host.join().unwrap(), guest)
+}
+
+#[test]
+fn joining_needs_the_same_params() {
+    let host_socket = UdpSocket::bind("127.0.0.1:0").unwrap();
+    let addr = host_socket.local_addr().unwrap();
+    // The host waits for somebody who agrees, so it's left waiting.
+    thread::spawn(move || {
+        NetSession::host(host_socket, SEED, &Map::open_sea(), WaveParams::default())
+    });
+    let mut params = WaveParams::default();
+    params.decay_factor = 0.9;
+    let joined = NetSession::join(UdpSocket::bind("127.0.0.1:0").unwrap(),
+                                  addr,
+                                  &Map::open_sea(),
+                                  params);
+    match joined {
+        Err(ref e) if e.kind() == io::ErrorKind::InvalidData => (),
+        Err(e) => panic!("expected a refusal, got {}", e),
+        Ok(_) => panic!("joined a host with different params"),
+    }
+}
+
+#[test]
+fn loopback_match_matches_a_local_one() {
+    let (host, guest) = connect();
+    assert_eq!(host.local_player(), 0);
+    assert_eq!(guest.local_player(), 1);
+    assert_eq!(guest.seed(), SEED);
+
+    let mut sessions = [host, guest];
+    let mut worlds = [World::new(SEED, WaveParams::default(), 2),
+                      World::new(SEED, WaveParams::default(), 2)];
+    for round in 0.. {
+        assert!(round < 100_000, "the two ends never caught up");
+        let mut done = true;
+        for (i, (session, world)) in sessions.iter_mut().zip(worlds.iter_mut()).enumerate() {
+            // The guest only gets a go every third time round, so the
+            // host is always guessing ahead of it.
+            if world.frame < TICKS && (i == 0 || round % 3 == 0) {
+                for input in script(session.local_player(), world.frame) {
+                    session.push_input(input);
+                }
+                session.advance(world).unwrap();
+            } else {
+                session.poll(world).unwrap();
+            }
+            assert_eq!(session.confirmed_world(world).frame, session.confirmed_frame(world));
+            done &= session.confirmed_frame(world) == TICKS;
+        }
+        if done {
+            break;
+        }
+        thread::sleep(Duration::from_millis(1));
+    }
+    assert!(sessions[0].rollbacks > 0);
+
+    let mut local = World::new(SEED, WaveParams::default(), 2);
+    for tick in 0..TICKS {
+        for player in 0..2 {
+            for input in script(player, tick) {
+                local.apply_input(input);
+            }
+        }
+        local.update();
+    }
+    assert_same(&worlds[0], &local);
+    assert_same(&worlds[1], &local);
+}
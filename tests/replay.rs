@@ -0,0 +1,121 @@
+extern crate flipwrecked;
+
+use std::fs::{self, File};
+use std::io::Write;
+use std::path::PathBuf;
+
+use flipwrecked::map::Map;
+use flipwrecked::params::WaveParams;
+use flipwrecked::replay::{Input, Replay, ReplayWriter, STEER_SCALE};
+
+fn temp_file(name: &str) -> PathBuf {
+    ::std::env::temp_dir().join(format!("flipwrecked_{}.replay", name))
+}
+
+/// Writes `text` out and loads it back as a replay.
+fn load_text(name: &str, text: &str) -> ::std::io::Result<Replay> {
+    let path = temp_file(name);
+    File::create(&path).unwrap().write_all(text.as_bytes()).unwrap();
+    let replay = Replay::load(&path);
+    let _ = fs::remove_file(&path);
+    replay
+}
+
+#[test]
+fn replays_save_and_load() {
+    let map = Map::open_sea();
+    let params = WaveParams::default();
+    let mut calmer = params;
+    calmer.decay_factor = 0.9;
+    let inputs = vec![(0, Input::Steer(0, 1000, 0)),
+                      (12, Input::Steer(1, 250, -1000)),
+                      (12, Input::Jump(1)),
+                      (300, Input::Reset),
+                      (301, Input::Steer(3, 0, 7))];
+
+    let path = temp_file("round_trip");
+    {
+        let mut writer = ReplayWriter::create(&path, 1485388800, 4, &map, &params).unwrap();
+        for &(tick, input) in &inputs {
+            writer.record(tick, input).unwrap();
+        }
+        writer.record_params(150, &calmer).unwrap();
+    }
+    let replay = Replay::load(&path).unwrap();
+    let _ = fs::remove_file(&path);
+
+    assert_eq!(replay.seed, 1485388800);
+    assert_eq!(replay.players, 4);
+    assert_eq!(replay.events, inputs);
+    assert_eq!(replay.map, Some(map.checksum()));
+    assert_eq!(replay.params, Some(params));
+    assert_eq!(replay.param_changes, vec![(150, calmer)]);
+    assert!(replay.check_map(&map).is_ok());
+}
+
+#[test]
+fn replays_only_play_on_their_map() {
+    let map = Map::open_sea();
+    let path = temp_file("other_map");
+    ReplayWriter::create(&path, 1, 2, &map, &WaveParams::default()).unwrap();
+    let replay = Replay::load(&path).unwrap();
+    let _ = fs::remove_file(&path);
+
+    let mut other = map.clone();
+    other.set_depth(3, 3, 0.5);
+    assert!(replay.check_map(&other).is_err());
+}
+
+#[test]
+fn version_1_replays_are_converted() {
+    let replay = load_text("version_1",
+                           "flipwrecked-replay 1\n\
+                            seed 42\n\
+                            10 press 0 up\n\
+                            20 press 0 left\n\
+                            25 press 1 right\n\
+                            30 release 0 up\n\
+                            40 jump 1\n\
+                            50 release 0 left\n\
+                            900 reset\n")
+        .unwrap();
+    let full = STEER_SCALE as i16;
+    assert_eq!(replay.seed, 42);
+    assert_eq!(replay.players, 2);
+    assert_eq!(replay.map, None);
+    assert_eq!(replay.params, None);
+    assert_eq!(replay.events,
+               vec![(10, Input::Steer(0, full, 0)),
+                    (20, Input::Steer(0, full, -full)),
+                    (25, Input::Steer(1, 0, full)),
+                    (30, Input::Steer(0, 0, -full)),
+                    (40, Input::Jump(1)),
+                    (50, Input::Steer(0, 0, 0)),
+                    (900, Input::Reset)]);
+    // Too old to say what map it's for, so any map with room will do.
+    assert!(replay.check_map(&Map::open_sea()).is_ok());
+}
+
+#[test]
+fn unknown_versions_are_refused() {
+    assert!(load_text("version_99", "flipwrecked-replay 99\nseed 1\n").is_err());
+    assert!(load_text("version_0", "flipwrecked-replay 0\nseed 1\n").is_err());
+    assert!(load_text("not_a_replay", "seed 1\n10 reset\n").is_err());
+}
+
+#[test]
+fn impossible_player_counts_are_refused() {
+    let text = |players| format!("flipwrecked-replay 2\nseed 1\nplayers {}\n10 reset\n", players);
+    assert!(load_text("players_2", &text(2)).is_ok());
+    for &players in &[0, 1, 9, 99] {
+        assert!(load_text("bad_players", &text(players)).is_err(),
+                "accepted {} players",
+                players);
+    }
+}
+
+#[test]
+fn version_3_replays_need_their_header() {
+    let text = "flipwrecked-replay 3\nseed 1\nplayers 2\n10 reset\n";
+    assert!(load_text("no_header", text).is_err());
+}
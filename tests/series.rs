@@ -0,0 +1,90 @@
+extern crate flipwrecked;
+
+use flipwrecked::series::{Series, SeriesFormat};
+use flipwrecked::world::Outcome;
+
+fn play(series: &mut Series, outcomes: &[Outcome]) {
+    for &outcome in outcomes {
+        assert!(!series.is_over(), "over after {} rounds", series.rounds);
+        series.record(outcome);
+    }
+}
+
+#[test]
+fn best_of_ends_on_a_majority() {
+    let mut series = Series::new(SeriesFormat::BestOf(5), 2);
+    play(&mut series,
+         &[Outcome::Winner(0), Outcome::Winner(1), Outcome::Winner(0), Outcome::Winner(0)]);
+    assert!(series.is_over());
+    assert_eq!(series.winner(), Some(0));
+    assert_eq!(series.scores, vec![3, 1]);
+    // Nothing counts once it's over.
+    series.record(Outcome::Winner(1));
+    assert_eq!(series.scores, vec![3, 1]);
+    assert_eq!(series.round(), 4);
+}
+
+#[test]
+fn first_to_ends_at_n() {
+    let mut series = Series::new(SeriesFormat::FirstTo(3), 2);
+    play(&mut series,
+         &[Outcome::Winner(1), Outcome::Winner(0), Outcome::Winner(1), Outcome::Winner(0)]);
+    assert!(!series.is_over());
+    play(&mut series, &[Outcome::Winner(1)]);
+    assert!(series.is_over());
+    assert_eq!(series.winner(), Some(1));
+    assert_eq!(series.rounds, 5);
+}
+
+#[test]
+fn draws_are_nobodys_win() {
+    let mut series = Series::new(SeriesFormat::BestOf(3), 2);
+    play(&mut series,
+         &[Outcome::Draw, Outcome::Winner(0), Outcome::Draw, Outcome::Draw, Outcome::Winner(1)]);
+    assert!(!series.is_over());
+    assert_eq!(series.scores, vec![1, 1]);
+    assert_eq!(series.round(), 6);
+    play(&mut series, &[Outcome::Winner(1)]);
+    assert_eq!(series.winner(), Some(1));
+
+    let mut single = Series::new(SeriesFormat::Single, 2);
+    single.record(Outcome::Draw);
+    assert!(single.is_over());
+    assert_eq!(single.winner(), None);
+}
+
+#[test]
+fn best_of_with_a_crowd_ends_after_n_wins() {
+    let mut series = Series::new(SeriesFormat::BestOf(5), 4);
+    // Nobody gets three, but player 2 is ahead once five are won.
+    play(&mut series,
+         &[Outcome::Winner(2),
+           Outcome::Winner(0),
+           Outcome::Draw,
+           Outcome::Winner(2),
+           Outcome::Winner(1),
+           Outcome::Winner(3)]);
+    assert!(series.is_over());
+    assert_eq!(series.winner(), Some(2));
+
+    // A tie for the lead plays on until it's broken.
+    let mut series = Series::new(SeriesFormat::BestOf(3), 3);
+    play(&mut series, &[Outcome::Winner(0), Outcome::Winner(1), Outcome::Winner(2)]);
+    assert!(!series.is_over());
+    play(&mut series, &[Outcome::Winner(1)]);
+    assert_eq!(series.winner(), Some(1));
+}
+
+#[test]
+fn formats_have_names() {
+    for format in &SeriesFormat::all() {
+        let short = match *format {
+            SeriesFormat::Single => String::from("single"),
+            SeriesFormat::BestOf(n) => format!("bo{}", n),
+            SeriesFormat::FirstTo(n) => format!("ft{}", n),
+        };
+        assert_eq!(SeriesFormat::from_name(&short), Some(*format));
+    }
+    assert_eq!(SeriesFormat::from_name("bo0"), None);
+    assert_eq!(SeriesFormat::from_name("best of 3"), None);
+}
@@ -0,0 +1,61 @@
+extern crate flipwrecked;
+extern crate nalgebra as na;
+
+use na::Vector2;
+
+use flipwrecked::hull::HullSample;
+use flipwrecked::ship::Ship;
+
+// Plenty of time for the ship to settle one way or the other.
+const TICKS: usize = 300;
+
+/// Water with the same tilt everywhere under the hull.
+fn slope(pitch_slope: f32, roll_slope: f32) -> HullSample {
+    HullSample {
+        max: 0.0,
+        min: 0.0,
+        mean: 0.0,
+        // Only matters for drawing; the ship rides on the other two.
+        slope: Vector2::new(0.0, 0.0),
+        pitch_slope: pitch_slope,
+        roll_slope: roll_slope,
+    }
+}
+
+fn ride(water: &HullSample) -> Ship {
+    let mut ship = Ship::new(400, 300);
+    for _ in 0..TICKS {
+        ship.ride_waves(Some(water));
+    }
+    ship
+}
+
+#[test]
+fn calm_water_is_calm() {
+    let ship = ride(&slope(0.0, 0.0));
+    assert!(!ship.flipped);
+    assert_eq!((ship.roll(), ship.pitch()), (0.0, 0.0));
+}
+
+#[test]
+fn waves_on_the_beam_capsize_before_waves_on_the_bow() {
+    for &s in &[0.1, -0.1] {
+        let broadside = ride(&slope(0.0, s));
+        assert!(broadside.flipped, "broadside slope of {} didn't capsize", s);
+
+        let head_on = ride(&slope(s, 0.0));
+        assert!(!head_on.flipped, "head on slope of {} capsized", s);
+        assert!(head_on.pitch() != 0.0, "head on slope of {} did nothing", s);
+    }
+}
+
+#[test]
+fn out_of_the_water_rights_itself() {
+    let mut ship = Ship::new(400, 300);
+    ship.knock(0.1, 0.05);
+    for _ in 0..TICKS {
+        ship.ride_waves(None);
+    }
+    assert!(!ship.flipped);
+    assert!(ship.roll().abs() < 0.01 && ship.pitch().abs() < 0.01);
+}
@@ -0,0 +1,89 @@
+extern crate flipwrecked;
+
+use flipwrecked::params::WaveParams;
+use flipwrecked::player::Control;
+use flipwrecked::replay::Input;
+use flipwrecked::snapshot::SnapshotError;
+use flipwrecked::world::{World, FlipCause};
+
+const SEED: u64 = 11;
+
+// Gets a match going with ships moving, jumping and waves about.
+fn busy_world(ticks: usize) -> World {
+    let mut world = World::new(SEED, WaveParams::default(), 3);
+    world.apply_input(Input::Steer(0, 1000, 300));
+    world.apply_input(Input::Steer(1, 1000, -300));
+    world.apply_input(Input::Steer(2, 500, 1000));
+    for tick in 0..ticks {
+        if tick == 20 {
+            world.apply_input(Input::Jump(1));
+        }
+        world.update();
+    }
+    world
+}
+
+#[test]
+fn restored_world_carries_on_the_same() {
+    let mut world = busy_world(50);
+    let snapshot = world.snapshot();
+    let mut restored = World::from_snapshot(&snapshot).unwrap();
+    assert_eq!(restored.snapshot(), snapshot);
+
+    // Part way through the jump, so the jump has to come back right too.
+    for _ in 0..200 {
+        world.update();
+        restored.update();
+    }
+    assert_eq!(restored.snapshot(), world.snapshot());
+}
+
+#[test]
+fn restore_goes_back_and_keeps_controls() {
+    let mut world = busy_world(30);
+    world.players[0].control = Control::Gamepad(0);
+    let snapshot = world.snapshot();
+    for _ in 0..30 {
+        world.update();
+    }
+    world.restore(&snapshot).unwrap();
+    assert_eq!(world.frame, 30);
+    assert_eq!(world.players[0].control, Control::Gamepad(0));
+    assert_eq!(world.snapshot(), snapshot);
+}
+
+#[test]
+fn bad_snapshots_are_refused() {
+    let snapshot = busy_world(10).snapshot();
+    assert_eq!(World::from_snapshot(b"nonsense").err(),
+               Some(SnapshotError::NotASnapshot));
+    assert_eq!(World::from_snapshot(&snapshot[..snapshot.len() - 1]).err(),
+               Some(SnapshotError::Truncated));
+    let mut old = snapshot.clone();
+    old[6] = 0;
+    assert_eq!(World::from_snapshot(&old).err(), Some(SnapshotError::Version(0)));
+
+    let mut two = World::new(SEED, WaveParams::default(), 2);
+    assert!(two.restore(&snapshot).is_err());
+}
+
+#[test]
+fn impossible_blame_is_refused() {
+    let world = busy_world(10);
+    let bad = |change: &Fn(&mut World)| {
+        let mut bad = world.clone();
+        change(&mut bad);
+        World::from_snapshot(&bad.snapshot()).err()
+    };
+    assert_eq!(bad(&|w| w.players[0].rammed = Some((11, 1))),
+               Some(SnapshotError::Invalid("ram")));
+    assert_eq!(bad(&|w| w.players[0].rammed = Some((5, 3))),
+               Some(SnapshotError::Invalid("ram")));
+    assert_eq!(bad(&|w| w.players[1].flipped = Some((20, FlipCause::Waves))),
+               Some(SnapshotError::Invalid("flip")));
+    assert_eq!(bad(&|w| w.players[1].flipped = Some((5, FlipCause::Stomped(9)))),
+               Some(SnapshotError::Invalid("flip")));
+    assert_eq!(bad(&|w| w.players[2].ship.location.x = 1e20),
+               Some(SnapshotError::Invalid("location")));
+    assert!(bad(&|w| w.players[0].rammed = Some((10, 2))).is_none());
+}